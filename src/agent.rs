@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+use taskflow_core::{AgentOptions, DEFAULT_AGENT_PORT};
+
+const USAGE: &str = "\
+Usage: taskflow agent [--listen HOST:PORT] [--secret-file PATH] [--work-dir DIR]
+
+Processes items sent by a TaskFlow coordinator. Listens on 127.0.0.1:7979
+unless --listen says otherwise, e.g. 0.0.0.0:7979 for other machines. The
+shared secret is read from --secret-file or the TASKFLOW_AGENT_SECRET
+environment variable.";
+
+/// Runs `taskflow agent` with the arguments after `agent`.
+pub fn run(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut options = AgentOptions {
+        listen: format!("127.0.0.1:{}", DEFAULT_AGENT_PORT),
+        secret: std::env::var("TASKFLOW_AGENT_SECRET").unwrap_or_default(),
+        work_dir: std::env::temp_dir().join("taskflow-agent"),
+    };
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value\n\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--listen" => options.listen = value()?,
+            "--work-dir" => options.work_dir = PathBuf::from(value()?),
+            "--secret-file" => {
+                let path = value()?;
+                let secret = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+                options.secret = secret.trim().to_string();
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("Unknown argument {}\n\n{}", arg, USAGE)),
+        }
+    }
+
+    taskflow_core::serve_agent(options, |line| {
+        eprintln!(
+            "[{}] {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            line
+        );
+    })
+}
@@ -0,0 +1,288 @@
+use serde_json::{Value, json};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use taskflow_core::config::ApiSettings;
+use taskflow_core::{Event, ItemId, secret};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// How long a request waits for the app to answer it
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+// Comment lines sent on idle event streams to notice closed connections
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+const DEFAULT_LOG_LINES: usize = 100;
+// Request bodies are small JSON documents listing paths
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Something the app is asked to do or report on.
+#[derive(Debug, Clone)]
+pub enum ApiCommand {
+    Status,
+    ListItems,
+    GetItem(ItemId),
+    AddItems(Vec<PathBuf>),
+    Start,
+    Pause,
+    Resume,
+    Cancel,
+    Logs(usize),
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    /// The command can't be carried out in the current state.
+    Conflict(String),
+}
+
+/// A command waiting for the app's answer.
+pub struct ApiRequest {
+    pub command: ApiCommand,
+    reply: Sender<Result<Value, ApiError>>,
+}
+
+impl ApiRequest {
+    pub fn respond(self, result: Result<Value, ApiError>) {
+        let _ = self.reply.send(result);
+    }
+}
+
+/// A localhost HTTP server that forwards commands to the app.
+pub struct ApiServer {
+    server: Arc<Server>,
+    requests: Receiver<ApiRequest>,
+    pub port: u16,
+}
+
+impl ApiServer {
+    /// Starts serving on 127.0.0.1. `ctx` is woken whenever a command arrives
+    /// so it is answered even while the window is idle; `events` is streamed
+    /// to `/api/events` subscribers.
+    pub fn start(
+        settings: &ApiSettings,
+        ctx: egui::Context,
+        events: Receiver<Event>,
+    ) -> Result<Self, String> {
+        if settings.token.is_empty() {
+            return Err("API token is not set".to_string());
+        }
+
+        let server = Server::http(("127.0.0.1", settings.port))
+            .map_err(|e| format!("Failed to start API on port {}: {}", settings.port, e))?;
+        let server = Arc::new(server);
+        let (tx, requests) = mpsc::channel();
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+
+        let listener = Listener {
+            token: settings.token.clone(),
+            commands: tx,
+            subscribers: Arc::clone(&subscribers),
+            ctx,
+        };
+        let accepting = Arc::clone(&server);
+        thread::spawn(move || {
+            for request in accepting.incoming_requests() {
+                let listener = listener.clone();
+                thread::spawn(move || listener.handle(request));
+            }
+        });
+
+        let streams = Arc::clone(&subscribers);
+        thread::spawn(move || {
+            for event in events {
+                broadcast(&streams, &event);
+            }
+        });
+
+        Ok(Self {
+            server,
+            requests,
+            port: settings.port,
+        })
+    }
+
+    /// Commands received since the last call.
+    pub fn pending(&self) -> Vec<ApiRequest> {
+        self.requests.try_iter().collect()
+    }
+}
+
+/// Sends an event to every `/api/events` subscriber.
+fn broadcast(subscribers: &Mutex<Vec<Sender<String>>>, event: &Event) {
+    let message = format!("event: {}\ndata: {}\n\n", event.name, event.data);
+    subscribers
+        .lock()
+        .unwrap()
+        .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+#[derive(Clone)]
+struct Listener {
+    token: String,
+    commands: Sender<ApiRequest>,
+    subscribers: Arc<Mutex<Vec<Sender<String>>>>,
+    ctx: egui::Context,
+}
+
+impl Listener {
+    fn handle(&self, mut request: Request) {
+        let (path, query) = match request.url().split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (request.url().to_string(), String::new()),
+        };
+
+        if !self.authorized(&request, &query) {
+            respond(request, 401, json!({ "error": "missing or invalid token" }));
+            return;
+        }
+
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let command = match (request.method(), segments.as_slice()) {
+            (Method::Get, ["api", "events"]) => {
+                self.stream_events(request);
+                return;
+            }
+            (Method::Get, ["api", "status"]) => ApiCommand::Status,
+            (Method::Get, ["api", "items"]) => ApiCommand::ListItems,
+            (Method::Get, ["api", "items", id]) => match id.parse() {
+                Ok(id) => ApiCommand::GetItem(id),
+                Err(_) => {
+                    respond(request, 400, json!({ "error": "invalid item id" }));
+                    return;
+                }
+            },
+            (Method::Post, ["api", "items"]) => match read_paths(&mut request) {
+                Ok(paths) => ApiCommand::AddItems(paths),
+                Err(e) => {
+                    respond(request, 400, json!({ "error": e }));
+                    return;
+                }
+            },
+            (Method::Post, ["api", "run", "start"]) => ApiCommand::Start,
+            (Method::Post, ["api", "run", "pause"]) => ApiCommand::Pause,
+            (Method::Post, ["api", "run", "resume"]) => ApiCommand::Resume,
+            (Method::Post, ["api", "run", "cancel"]) => ApiCommand::Cancel,
+            (Method::Get, ["api", "logs"]) => {
+                let lines = query_param(&query, "lines")
+                    .and_then(|lines| lines.parse().ok())
+                    .unwrap_or(DEFAULT_LOG_LINES);
+                ApiCommand::Logs(lines)
+            }
+            _ => {
+                respond(request, 404, json!({ "error": "no such endpoint" }));
+                return;
+            }
+        };
+
+        let (reply, answer) = mpsc::channel();
+        if self.commands.send(ApiRequest { command, reply }).is_err() {
+            respond(
+                request,
+                503,
+                json!({ "error": "TaskFlow is shutting down" }),
+            );
+            return;
+        }
+        self.ctx.request_repaint();
+
+        match answer.recv_timeout(REPLY_TIMEOUT) {
+            Ok(Ok(body)) => respond(request, 200, body),
+            Ok(Err(ApiError::NotFound(e))) => respond(request, 404, json!({ "error": e })),
+            Ok(Err(ApiError::Conflict(e))) => respond(request, 409, json!({ "error": e })),
+            Err(_) => respond(request, 503, json!({ "error": "TaskFlow did not answer" })),
+        }
+    }
+
+    /// Accepts the token as a bearer token, or as `?token=` for clients such
+    /// as browser `EventSource` that can't set headers.
+    fn authorized(&self, request: &Request, query: &str) -> bool {
+        let bearer = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+            .map(str::trim);
+
+        bearer
+            .or_else(|| query_param(query, "token"))
+            .is_some_and(|token| secret::constant_time_eq(token, &self.token))
+    }
+
+    /// Holds the connection open and writes events as Server-Sent Events.
+    fn stream_events(&self, request: Request) {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+
+        let mut writer = request.into_writer();
+        let head = "HTTP/1.1 200 OK\r\n\
+                    Content-Type: text/event-stream\r\n\
+                    Cache-Control: no-cache\r\n\
+                    Connection: close\r\n\r\n";
+        if writer.write_all(head.as_bytes()).is_err() || writer.flush().is_err() {
+            return;
+        }
+
+        loop {
+            let message = match rx.recv_timeout(KEEP_ALIVE) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            // A failed write means the client went away; dropping `rx`
+            // unsubscribes it
+            if writer.write_all(message.as_bytes()).is_err() || writer.flush().is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Reads `{"paths": [...]}`; paths must be absolute.
+fn read_paths(request: &mut Request) -> Result<Vec<PathBuf>, String> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| format!("could not read body: {}", e))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(format!("body is larger than {} bytes", MAX_BODY_BYTES));
+    }
+
+    let body: Value = serde_json::from_str(&body).map_err(|e| format!("invalid JSON: {}", e))?;
+    let paths: Vec<PathBuf> = body
+        .get("paths")
+        .cloned()
+        .and_then(|paths| serde_json::from_value(paths).ok())
+        .ok_or("expected {\"paths\": [...]}")?;
+
+    match paths.iter().find(|path| !path.is_absolute()) {
+        Some(path) => Err(format!("{} is not an absolute path", path.display())),
+        None => Ok(paths),
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn respond(request: Request, status: u16, body: Value) {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    let _ = request.respond(response);
+}
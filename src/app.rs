@@ -1,23 +1,35 @@
-use crate::config::Config;
-use crate::logger::Logger;
-use crate::processor::{Processor, ProcessorResult};
-use crate::queue::{ItemState, Queue};
+use crate::api::{ApiCommand, ApiError, ApiServer};
 use crate::ui::{
-    ControlsState, DropZoneResult, QueueListInteraction, render_controls, render_drop_zone,
-    render_queue_list,
+    ControlsState, DropZoneResult, DryRunInteraction, HistoryInteraction, OutputAction,
+    PreflightInteraction, QueueFilter, QueueListInteraction, render_controls, render_drop_zone,
+    render_dry_run, render_history, render_preflight, render_queue_list,
 };
 use poll_promise::Promise;
+use serde_json::{Value, json};
 use std::path::PathBuf;
+use taskflow_core::config::LogLocation;
+use taskflow_core::dry_run::DryRun;
+use taskflow_core::events::item_json;
+use taskflow_core::preflight::Preflight;
+use taskflow_core::{Config, Engine, ItemState};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tab {
+    Queue,
+    History,
+}
 
 pub struct TaskFlowApp {
-    queue: Queue,
-    config: Config,
-    logger: Logger,
-    processor: Processor,
+    engine: Engine,
+    dry_run: Option<DryRun>,
+    api: Option<ApiServer>,
+    // Findings shown before starting, until the user starts anyway or cancels
+    preflight: Option<Preflight>,
 
     // UI state
-    is_processing: bool,
-    status_message: String,
+    tab: Tab,
+    history_search: String,
+    queue_filter: QueueFilter,
     last_clicked_index: Option<usize>,
 
     // Available scripts
@@ -26,28 +38,56 @@ pub struct TaskFlowApp {
     // Dialog promises
     script_dialog: Option<Promise<Option<PathBuf>>>,
     output_dialog: Option<Promise<Option<PathBuf>>>,
+    log_dir_dialog: Option<Promise<Option<PathBuf>>>,
     browse_files_dialog: Option<Promise<Option<Vec<PathBuf>>>>,
+    dry_run_export_dialog: Option<Promise<Option<PathBuf>>>,
 }
 
 impl TaskFlowApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        let config = Config::load();
-        let logger = Logger::new(config.output_directory.clone(), config.logging_enabled);
-
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut config = Config::load();
+        // Without a token the API refuses to start, so a failure here is safe
+        if config.api.enabled
+            && config.api.token.is_empty()
+            && let Ok(token) = taskflow_core::secret::random_token()
+        {
+            config.api.token = token;
+            let _ = config.save();
+        }
         let available_scripts = config.available_scripts.clone();
+        let mut engine = Engine::new(config);
+
+        let mut api = None;
+        if engine.config().api.enabled {
+            let settings = engine.config().api.clone();
+            let events = engine.subscribe();
+            match ApiServer::start(&settings, cc.egui_ctx.clone(), events) {
+                Ok(server) => {
+                    engine.set_status(format!("Ready (API on 127.0.0.1:{})", server.port));
+                    api = Some(server);
+                }
+                Err(e) => {
+                    engine.logger().log(&e);
+                    engine.set_status(format!("Error: {}", e));
+                }
+            }
+        }
 
         Self {
-            queue: Queue::new(),
-            config,
-            logger,
-            processor: Processor::new(),
-            is_processing: false,
-            status_message: "Ready".to_string(),
+            engine,
+            dry_run: None,
+            api,
+            preflight: None,
+            tab: Tab::Queue,
+            history_search: String::new(),
+            queue_filter: QueueFilter::default(),
             last_clicked_index: None,
             available_scripts,
             script_dialog: None,
             output_dialog: None,
+            log_dir_dialog: None,
             browse_files_dialog: None,
+            dry_run_export_dialog: None,
         }
     }
 
@@ -56,8 +96,8 @@ impl TaskFlowApp {
         for path in &paths {
             println!("  Adding to queue: {:?}", path);
         }
-        self.queue.add_multiple(paths);
-        println!("Queue now has {} items", self.queue.len());
+        self.engine.enqueue(paths);
+        println!("Queue now has {} items", self.engine.queue().len());
     }
 
     fn handle_controls(&mut self, state: ControlsState) {
@@ -67,6 +107,9 @@ impl TaskFlowApp {
                 Promise::spawn_thread("script_dialog", move || rfd::FileDialog::new().pick_file());
             self.script_dialog = Some(promise);
         }
+        if let Some(builtin) = state.builtin_to_add {
+            self.add_script(builtin);
+        }
 
         // Handle output dialog
         if state.browse_output_clicked && self.output_dialog.is_none() {
@@ -75,194 +118,382 @@ impl TaskFlowApp {
             });
             self.output_dialog = Some(promise);
         }
+        if state.browse_log_dir_clicked && self.log_dir_dialog.is_none() {
+            let promise = Promise::spawn_thread("log_dir_dialog", move || {
+                rfd::FileDialog::new().pick_folder()
+            });
+            self.log_dir_dialog = Some(promise);
+        }
 
         // Handle script remove
-        if let Some(index) = state.script_to_remove {
-            if index < self.available_scripts.len() {
-                let removed = self.available_scripts.remove(index);
-                // If we removed the selected script, clear selection
-                if self.config.selected_script.as_ref() == Some(&removed) {
-                    self.config.selected_script = None;
-                }
-                self.config.available_scripts = self.available_scripts.clone();
-                let _ = self.config.save();
+        if let Some(index) = state.script_to_remove
+            && index < self.available_scripts.len()
+        {
+            let removed = self.available_scripts.remove(index);
+            // If we removed the selected script, clear selection
+            if self.engine.config().selected_script.as_ref() == Some(&removed) {
+                self.engine.config_mut().selected_script = None;
             }
+            self.engine.config_mut().available_scripts = self.available_scripts.clone();
+            let _ = self.engine.config().save();
         }
 
-        if state.script_changed || state.output_changed {
-            let _ = self.config.save();
+        if state.script_changed
+            || state.output_changed
+            || state.script_settings_changed
+            || state.report_changed
+            || state.suspend_changed
+            || state.schedule_changed
+        {
+            let _ = self.engine.config().save();
         }
 
         if state.logging_changed {
-            self.logger.update(
-                self.config.output_directory.clone(),
-                self.config.logging_enabled,
-            );
-            let _ = self.config.save();
+            self.engine.reload_logger();
+            let _ = self.engine.config().save();
+        }
+
+        if state.report_clicked {
+            self.engine.write_report();
         }
 
         if state.start_clicked {
-            self.start_processing();
+            self.request_start();
+        }
+
+        if state.dry_run_clicked {
+            let dry_run = DryRun::new(self.engine.queue(), self.engine.config());
+            self.engine.set_status(match dry_run.problem_count() {
+                0 => format!("Dry run: {} item(s) ready", dry_run.rows.len()),
+                n => format!("Dry run: {} item(s), {} problem(s)", dry_run.rows.len(), n),
+            });
+            self.dry_run = Some(dry_run);
+        }
+
+        if state.pause_clicked {
+            self.engine.pause();
+        }
+
+        if state.resume_clicked {
+            self.engine.resume();
         }
 
         if state.cancel_clicked {
-            self.cancel_processing();
+            self.engine.cancel();
+        }
+    }
+
+    /// Adds a script to the list and selects it, unless it is listed already.
+    fn add_script(&mut self, script: String) {
+        if !self.available_scripts.contains(&script) {
+            self.available_scripts.push(script.clone());
+            self.engine.config_mut().selected_script = Some(script);
+            self.engine.config_mut().available_scripts = self.available_scripts.clone();
+            let _ = self.engine.config().save();
         }
     }
 
     fn poll_dialogs(&mut self) {
         // Check script dialog
-        if let Some(promise) = &self.script_dialog {
-            if let Some(result) = promise.ready() {
-                if let Some(path) = result {
-                    if let Some(path_str) = path.to_str() {
-                        let script = path_str.to_string();
-                        if !self.available_scripts.contains(&script) {
-                            self.available_scripts.push(script.clone());
-                            self.config.selected_script = Some(script);
-                            self.config.available_scripts = self.available_scripts.clone();
-                            let _ = self.config.save();
-                        }
-                    }
-                }
-                self.script_dialog = None;
+        if let Some(promise) = &self.script_dialog
+            && let Some(result) = promise.ready()
+        {
+            if let Some(path) = result
+                && let Some(path_str) = path.to_str()
+            {
+                self.add_script(path_str.to_string());
             }
+            self.script_dialog = None;
         }
 
         // Check output dialog
-        if let Some(promise) = &self.output_dialog {
-            if let Some(result) = promise.ready() {
-                if let Some(path) = result {
-                    self.config.output_directory = Some(path.clone());
-                    let _ = self.config.save();
-                }
-                self.output_dialog = None;
+        if let Some(promise) = &self.output_dialog
+            && let Some(result) = promise.ready()
+        {
+            if let Some(path) = result {
+                self.engine.config_mut().output_directory = Some(path.clone());
+                let _ = self.engine.config().save();
+            }
+            self.output_dialog = None;
+        }
+
+        // Check log directory dialog
+        if let Some(promise) = &self.log_dir_dialog
+            && let Some(result) = promise.ready()
+        {
+            if let Some(path) = result {
+                self.engine.config_mut().log.location = LogLocation::Custom(path.clone());
+                self.engine.reload_logger();
+                let _ = self.engine.config().save();
             }
+            self.log_dir_dialog = None;
         }
 
         // Check browse files dialog
-        if let Some(promise) = &self.browse_files_dialog {
-            if let Some(result) = promise.ready() {
-                if let Some(paths) = result {
-                    self.handle_file_drops(paths.clone());
-                }
-                self.browse_files_dialog = None;
+        if let Some(promise) = &self.browse_files_dialog
+            && let Some(result) = promise.ready()
+        {
+            if let Some(paths) = result {
+                self.handle_file_drops(paths.clone());
+            }
+            self.browse_files_dialog = None;
+        }
+
+        // Check dry run export dialog
+        if let Some(promise) = &self.dry_run_export_dialog
+            && let Some(result) = promise.ready()
+        {
+            if let Some(path) = result
+                && let Some(dry_run) = &self.dry_run
+            {
+                self.engine.set_status(match dry_run.write_csv(path) {
+                    Ok(()) => format!("Dry run exported to {}", path.display()),
+                    Err(e) => format!("Error: Failed to export dry run: {}", e),
+                });
             }
+            self.dry_run_export_dialog = None;
+        }
+    }
+
+    fn handle_dry_run_interaction(&mut self, interaction: DryRunInteraction) {
+        if interaction.export_clicked && self.dry_run_export_dialog.is_none() {
+            let directory = self.engine.config().output_directory.clone();
+            let promise = Promise::spawn_thread("dry_run_export", move || {
+                let mut dialog = rfd::FileDialog::new()
+                    .set_title("Export dry run")
+                    .set_file_name("taskflow_dry_run.csv")
+                    .add_filter("CSV", &["csv"]);
+                if let Some(dir) = directory {
+                    dialog = dialog.set_directory(dir);
+                }
+                dialog.save_file()
+            });
+            self.dry_run_export_dialog = Some(promise);
+        }
+
+        if interaction.close_clicked {
+            self.dry_run = None;
         }
     }
 
     fn handle_queue_interaction(&mut self, interaction: QueueListInteraction) {
         if interaction.delete_pressed {
-            self.queue.remove_selected();
+            self.engine.queue_mut().remove_selected();
+        }
+
+        if interaction.clear_completed {
+            self.engine.queue_mut().remove_completed();
+        }
+
+        if interaction.clear_warnings {
+            self.engine.queue_mut().remove_with_warnings();
+        }
+
+        if interaction.clear_failed {
+            self.engine.queue_mut().remove_failed();
+        }
+
+        if interaction.completed_items_changed {
+            let _ = self.engine.config().save();
         }
 
         if let Some(clicked_index) = interaction.clicked_index {
             if interaction.ctrl_held {
-                self.queue.toggle_select(clicked_index);
+                self.engine.queue_mut().toggle_select(clicked_index);
                 self.last_clicked_index = Some(clicked_index);
             } else if interaction.shift_held {
                 if let Some(last_index) = self.last_clicked_index {
-                    self.queue.clear_selection();
-                    self.queue.select_range(last_index, clicked_index);
+                    self.engine.queue_mut().clear_selection();
+                    self.engine
+                        .queue_mut()
+                        .select_range(last_index, clicked_index);
                 } else {
-                    self.queue.clear_selection();
-                    self.queue.select(clicked_index);
+                    self.engine.queue_mut().clear_selection();
+                    self.engine.queue_mut().select(clicked_index);
                 }
                 self.last_clicked_index = Some(clicked_index);
             } else {
-                self.queue.clear_selection();
-                self.queue.select(clicked_index);
+                self.engine.queue_mut().clear_selection();
+                self.engine.queue_mut().select(clicked_index);
                 self.last_clicked_index = Some(clicked_index);
             }
         }
 
         if let Some(target_index) = interaction.drag_target {
-            self.queue.move_selected(target_index);
+            self.engine.queue_mut().move_selected(target_index);
         }
+
+        self.handle_output_action(interaction.output_action);
     }
 
-    fn start_processing(&mut self) {
-        if self.config.selected_script.is_none() {
-            self.status_message = "Error: No script selected".to_string();
-            return;
+    fn handle_output_action(&mut self, action: Option<OutputAction>) {
+        let result = match action {
+            Some(OutputAction::Open(path)) => taskflow_core::open_output(&path),
+            Some(OutputAction::Reveal(path)) => taskflow_core::reveal_output(&path),
+            None => return,
+        };
+        if let Err(e) = result {
+            self.engine.set_status(format!("Error: {}", e));
         }
+    }
 
-        if self.config.output_directory.is_none() {
-            self.status_message = "Error: No output directory selected".to_string();
-            return;
+    /// Validates the run and starts it, or shows the findings first.
+    fn request_start(&mut self) {
+        let preflight = self.engine.preflight();
+        if preflight.is_clean() {
+            let _ = self.engine.start();
+        } else {
+            self.engine.set_status(format!(
+                "Pre-flight check: {} error(s), {} warning(s)",
+                preflight.error_count(),
+                preflight.warning_count()
+            ));
+            self.preflight = Some(preflight);
         }
+    }
 
-        if self.queue.is_empty() {
-            self.status_message = "Error: Queue is empty".to_string();
-            return;
+    fn handle_preflight_interaction(&mut self, interaction: PreflightInteraction) {
+        if interaction.start_anyway_clicked {
+            self.preflight = None;
+            let _ = self.engine.start();
         }
 
-        self.is_processing = true;
-        self.process_next_item();
+        if interaction.close_clicked {
+            self.preflight = None;
+        }
     }
 
-    fn process_next_item(&mut self) {
-        if let Some(index) = self.queue.get_next_pending() {
-            let item = &self.queue.items()[index];
-            let script = self.config.selected_script.clone().unwrap();
-            let output_dir = self.config.output_directory.clone().unwrap();
-
-            self.logger.log_start(&item.filename(), &script);
-
-            if let Err(e) = self
-                .processor
-                .process(index, item.path.clone(), script, output_dir)
-            {
-                self.logger.log_error(&item.filename(), &e);
-                self.queue.set_state(index, ItemState::Error(e));
-                self.process_next_item();
+    fn handle_history_interaction(&mut self, interaction: HistoryInteraction) {
+        if !interaction.requeue.is_empty() {
+            let count = interaction.requeue.len();
+            for (input, script) in interaction.requeue {
+                self.engine.enqueue_with_script(vec![input], &script);
             }
-        } else {
-            self.is_processing = false;
-            self.status_message = "All items processed".to_string();
+            self.engine
+                .set_status(format!("Re-queued {} item(s)", count));
         }
+
+        self.handle_output_action(interaction.output_action);
     }
 
-    fn cancel_processing(&mut self) {
-        let _ = self.processor.cancel();
-        self.is_processing = false;
-        self.status_message = "Processing cancelled".to_string();
+    fn poll_api(&mut self) {
+        let Some(requests) = self.api.as_ref().map(ApiServer::pending) else {
+            return;
+        };
+        for request in requests {
+            let result = self.handle_api_command(request.command.clone());
+            request.respond(result);
+        }
     }
 
-    fn poll_processor(&mut self) {
-        while let Some(result) = self.processor.try_recv_result() {
-            match result {
-                ProcessorResult::Started(index) => {
-                    self.queue.set_state(index, ItemState::Processing);
-                    let filename = self.queue.items()[index].filename();
-                    self.status_message = format!("Processing: {}", filename);
+    fn handle_api_command(&mut self, command: ApiCommand) -> Result<Value, ApiError> {
+        match command {
+            ApiCommand::Status => Ok(self.status_json()),
+            ApiCommand::ListItems => {
+                let items: Vec<Value> = self.engine.queue().items().iter().map(item_json).collect();
+                Ok(json!({ "items": items }))
+            }
+            ApiCommand::GetItem(id) => self
+                .engine
+                .queue()
+                .get(id)
+                .map(item_json)
+                .ok_or_else(|| ApiError::NotFound(format!("No item with id {}", id))),
+            ApiCommand::AddItems(paths) => {
+                let ids = self.engine.enqueue(paths);
+                let items: Vec<Value> = ids
+                    .iter()
+                    .filter_map(|&id| self.engine.queue().get(id))
+                    .map(item_json)
+                    .collect();
+                self.engine
+                    .set_status(format!("Added {} item(s) via API", items.len()));
+                Ok(json!({ "items": items }))
+            }
+            ApiCommand::Start => {
+                if self.engine.is_processing() {
+                    return Err(ApiError::Conflict(
+                        "A run is already in progress".to_string(),
+                    ));
+                }
+                // Nobody can confirm warnings remotely, so only errors block
+                let preflight = self.engine.preflight();
+                let findings: Vec<String> = preflight
+                    .findings
+                    .iter()
+                    .map(|finding| finding.message.clone())
+                    .collect();
+                if preflight.error_count() > 0 {
+                    return Err(ApiError::Conflict(format!(
+                        "Pre-flight check failed: {}",
+                        findings.join("; ")
+                    )));
                 }
-                ProcessorResult::Success(index) => {
-                    let filename = self.queue.items()[index].filename();
-                    self.logger.log_success(&filename);
-                    self.queue.set_state(index, ItemState::Completed);
-                    self.queue.remove_completed();
-                    self.process_next_item();
+                self.engine.start().map_err(ApiError::Conflict)?;
+                Ok(json!({ "started": true, "warnings": findings }))
+            }
+            ApiCommand::Pause => {
+                if !self.engine.is_processing() {
+                    return Err(ApiError::Conflict("No run in progress".to_string()));
                 }
-                ProcessorResult::Error(index, error) => {
-                    let filename = self.queue.items()[index].filename();
-                    self.logger.log_error(&filename, &error);
-                    self.queue.set_state(index, ItemState::Error(error));
-                    self.process_next_item();
+                self.engine.pause();
+                Ok(self.status_json())
+            }
+            ApiCommand::Resume => {
+                if !self.engine.is_paused() {
+                    return Err(ApiError::Conflict("The run is not paused".to_string()));
                 }
-                ProcessorResult::Cancelled => {
-                    self.is_processing = false;
-                    self.status_message = "Cancelled".to_string();
+                self.engine.resume();
+                Ok(self.status_json())
+            }
+            ApiCommand::Cancel => {
+                if !self.engine.is_processing() {
+                    return Err(ApiError::Conflict("No run in progress".to_string()));
                 }
+                self.engine.cancel();
+                Ok(self.status_json())
             }
+            ApiCommand::Logs(lines) => Ok(json!({ "lines": self.engine.logger().tail(lines) })),
         }
     }
+
+    fn status_json(&self) -> Value {
+        let count = |state: fn(&ItemState) -> bool| {
+            self.engine
+                .queue()
+                .items()
+                .iter()
+                .filter(|item| state(item.state()))
+                .count()
+        };
+        let config = self.engine.config();
+        json!({
+            "processing": self.engine.is_processing(),
+            "paused": self.engine.is_paused(),
+            "status": self.engine.status(),
+            "script": config.selected_script,
+            "output_dir": config.output_directory,
+            "current_item": self.engine.current_item(),
+            "current_items": self.engine.current_items(),
+            "counts": {
+                "pending": count(|s| matches!(s, ItemState::Pending)),
+                "processing": count(|s| matches!(s, ItemState::Processing)),
+                "completed": count(|s| matches!(s, ItemState::Completed)),
+                "with_warnings": count(|s| matches!(s, ItemState::CompletedWithWarnings)),
+                "skipped": count(|s| matches!(s, ItemState::Skipped(_))),
+                "failed": count(|s| matches!(s, ItemState::Error(_))),
+            },
+        })
+    }
 }
 
 impl eframe::App for TaskFlowApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Poll for processor results
-        self.poll_processor();
+        // Collect results and dispatch the next item
+        self.engine.poll();
+
+        // Answer commands from the local API
+        self.poll_api();
 
         // Poll for dialog results
         self.poll_dialogs();
@@ -271,58 +502,99 @@ impl eframe::App for TaskFlowApp {
             ui.heading("TaskFlow");
             ui.separator();
 
-            // Drop zone
-            match render_drop_zone(ui) {
-                DropZoneResult::DroppedFiles(paths) => {
-                    self.handle_file_drops(paths);
-                }
-                DropZoneResult::BrowseClicked => {
-                    if self.browse_files_dialog.is_none() {
-                        let promise = Promise::spawn_thread("browse_files", move || {
-                            rfd::FileDialog::new()
-                                .set_title("Select files to add to queue")
-                                .pick_files()
-                        });
-                        self.browse_files_dialog = Some(promise);
-                    }
-                }
-                DropZoneResult::None => {}
-            }
-
-            ui.add_space(10.0);
-
-            // Controls
-            let controls_state = render_controls(
-                ui,
-                &self.available_scripts,
-                &mut self.config.selected_script,
-                &mut self.config.output_directory,
-                &mut self.config.logging_enabled,
-                self.is_processing,
-            );
-            self.handle_controls(controls_state);
-
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.tab, Tab::Queue, "Queue");
+                ui.selectable_value(&mut self.tab, Tab::History, "History");
+            });
             ui.separator();
 
-            // Queue
-            ui.label(format!("Queue ({} items)", self.queue.len()));
-            let queue_interaction = render_queue_list(ui, self.queue.items_mut());
-            self.handle_queue_interaction(queue_interaction);
+            match self.tab {
+                Tab::Queue => {
+                    // Drop zone
+                    match render_drop_zone(ui) {
+                        DropZoneResult::DroppedFiles(paths) => {
+                            self.handle_file_drops(paths);
+                        }
+                        DropZoneResult::BrowseClicked => {
+                            if self.browse_files_dialog.is_none() {
+                                let promise = Promise::spawn_thread("browse_files", move || {
+                                    rfd::FileDialog::new()
+                                        .set_title("Select files to add to queue")
+                                        .pick_files()
+                                });
+                                self.browse_files_dialog = Some(promise);
+                            }
+                        }
+                        DropZoneResult::None => {}
+                    }
+
+                    ui.add_space(10.0);
+
+                    // Controls
+                    let has_report = !self.engine.report().is_empty();
+                    let (is_processing, is_paused) =
+                        (self.engine.is_processing(), self.engine.is_paused());
+                    let controls_state = render_controls(
+                        ui,
+                        &self.available_scripts,
+                        self.engine.config_mut(),
+                        has_report,
+                        is_processing,
+                        is_paused,
+                    );
+                    self.handle_controls(controls_state);
+
+                    ui.separator();
+
+                    // Queue
+                    let mut completed_items = self.engine.config().completed_items;
+                    let queue_interaction = render_queue_list(
+                        ui,
+                        self.engine.queue().items(),
+                        &mut completed_items,
+                        &mut self.queue_filter,
+                    );
+                    self.engine.config_mut().completed_items = completed_items;
+                    self.handle_queue_interaction(queue_interaction);
+
+                    ui.separator();
+                }
+                Tab::History => {
+                    let history_interaction =
+                        render_history(ui, self.engine.history(), &mut self.history_search);
+                    self.handle_history_interaction(history_interaction);
 
-            ui.separator();
+                    ui.separator();
+                }
+            }
 
             // Status bar
             ui.horizontal(|ui| {
                 ui.label("Status:");
-                ui.label(&self.status_message);
+                if self.engine.is_paused() {
+                    ui.colored_label(egui::Color32::from_rgb(200, 150, 50), "⏸ Paused");
+                } else if self.engine.is_waiting_for_schedule() {
+                    ui.colored_label(egui::Color32::from_rgb(100, 149, 237), "🕑 Scheduled");
+                }
+                ui.label(self.engine.status());
             });
         });
 
+        if let Some(dry_run) = &self.dry_run {
+            let interaction = render_dry_run(ctx, dry_run);
+            self.handle_dry_run_interaction(interaction);
+        }
+
+        if let Some(preflight) = &self.preflight {
+            let interaction = render_preflight(ctx, preflight);
+            self.handle_preflight_interaction(interaction);
+        }
+
         // Request repaint to keep UI responsive
         ctx.request_repaint();
     }
 
     fn save(&mut self, _storage: &mut dyn eframe::Storage) {
-        let _ = self.config.save();
+        let _ = self.engine.config().save();
     }
 }
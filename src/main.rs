@@ -1,13 +1,20 @@
+mod agent;
+mod api;
 mod app;
-mod config;
-mod logger;
-mod processor;
-mod queue;
 mod ui;
 
 use app::TaskFlowApp;
 
 fn main() -> Result<(), eframe::Error> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("agent") {
+        if let Err(e) = agent::run(args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([600.0, 700.0])
@@ -1,39 +1,38 @@
+use chrono::NaiveTime;
 use egui::Ui;
-use std::path::PathBuf;
+use taskflow_core::builtin::Builtin;
+use taskflow_core::config::{BatchInput, CollisionPolicy, Config, LogLocation, ScheduleSettings};
+use taskflow_core::scheduler;
 
+#[derive(Default)]
 pub struct ControlsState {
     pub script_changed: bool,
     pub output_changed: bool,
+    pub script_settings_changed: bool,
     pub logging_changed: bool,
+    pub report_changed: bool,
+    pub report_clicked: bool,
+    pub suspend_changed: bool,
+    pub schedule_changed: bool,
     pub start_clicked: bool,
+    pub dry_run_clicked: bool,
+    pub pause_clicked: bool,
+    pub resume_clicked: bool,
     pub cancel_clicked: bool,
     pub add_script_clicked: bool,
+    pub builtin_to_add: Option<String>,
     pub browse_output_clicked: bool,
+    pub browse_log_dir_clicked: bool,
     pub script_to_remove: Option<usize>,
 }
 
-impl Default for ControlsState {
-    fn default() -> Self {
-        Self {
-            script_changed: false,
-            output_changed: false,
-            logging_changed: false,
-            start_clicked: false,
-            cancel_clicked: false,
-            add_script_clicked: false,
-            browse_output_clicked: false,
-            script_to_remove: None,
-        }
-    }
-}
-
 pub fn render_controls(
     ui: &mut Ui,
     scripts: &[String],
-    selected_script: &mut Option<String>,
-    output_dir: &mut Option<PathBuf>,
-    logging_enabled: &mut bool,
+    config: &mut Config,
+    has_report: bool,
     is_processing: bool,
+    is_paused: bool,
 ) -> ControlsState {
     let mut state = ControlsState::default();
 
@@ -44,9 +43,13 @@ pub fn render_controls(
     for (index, script) in scripts.iter().enumerate() {
         ui.horizontal(|ui| {
             // Script name (selectable)
-            let is_selected = selected_script.as_ref() == Some(script);
-            if ui.selectable_label(is_selected, script).clicked() {
-                *selected_script = Some(script.clone());
+            let is_selected = config.selected_script.as_ref() == Some(script);
+            let name = match Builtin::parse(script) {
+                Some(builtin) => format!("Built-in: {}", builtin.label()),
+                None => script.clone(),
+            };
+            if ui.selectable_label(is_selected, name).clicked() {
+                config.selected_script = Some(script.clone());
                 state.script_changed = true;
             }
 
@@ -57,17 +60,29 @@ pub fn render_controls(
         });
     }
 
-    // Add script button
-    if ui.button("+ Add Script").clicked() {
-        state.add_script_clicked = true;
-    }
+    ui.horizontal(|ui| {
+        // Add script button
+        if ui.button("+ Add Script").clicked() {
+            state.add_script_clicked = true;
+        }
+
+        ui.menu_button("+ Add Built-in", |ui| {
+            for builtin in Builtin::ALL {
+                if ui.button(builtin.label()).clicked() {
+                    state.builtin_to_add = Some(builtin.script());
+                    ui.close();
+                }
+            }
+        });
+    });
 
     ui.add_space(10.0);
 
     ui.horizontal(|ui| {
         ui.label("Output:");
 
-        let dir_text = output_dir
+        let dir_text = config
+            .output_directory
             .as_ref()
             .and_then(|p| p.to_str())
             .unwrap_or("Not selected");
@@ -79,29 +94,286 @@ pub fn render_controls(
         }
     });
 
+    if let Some(script) = config.selected_script.clone() {
+        ui.horizontal(|ui| {
+            ui.label("If output exists:");
+            let mut policy = config.script_settings(&script).on_collision;
+            egui::ComboBox::from_id_salt("on_collision")
+                .selected_text(policy.label())
+                .show_ui(ui, |ui| {
+                    for option in [
+                        CollisionPolicy::Overwrite,
+                        CollisionPolicy::Skip,
+                        CollisionPolicy::AutoRename,
+                        CollisionPolicy::Fail,
+                    ] {
+                        if ui
+                            .selectable_value(&mut policy, option, option.label())
+                            .changed()
+                        {
+                            state.script_settings_changed = true;
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Applies when the script's output_name is set");
+
+            if state.script_settings_changed {
+                config
+                    .script_settings
+                    .entry(script.clone())
+                    .or_default()
+                    .on_collision = policy;
+            }
+        });
+
+        if Builtin::parse(&script).is_none() {
+            ui.horizontal(|ui| {
+                ui.label("Run:");
+                let settings = config.script_settings(&script);
+                let (mut batch, mut size) = (settings.batch, settings.batch_size.unwrap_or(0));
+                let text = |batch: Option<BatchInput>| match batch {
+                    Some(input) => format!("In batches ({})", input.label()),
+                    None => "Once per file".to_string(),
+                };
+                let mut changed = false;
+                egui::ComboBox::from_id_salt("batch")
+                    .selected_text(text(batch))
+                    .show_ui(ui, |ui| {
+                        for option in [
+                            None,
+                            Some(BatchInput::Args),
+                            Some(BatchInput::ListFile),
+                            Some(BatchInput::Stdin),
+                        ] {
+                            changed |= ui
+                                .selectable_value(&mut batch, option, text(option))
+                                .changed();
+                        }
+                    })
+                    .response
+                    .on_hover_text("A batch script gets many files in a single run");
+
+                if batch.is_some() {
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut size)
+                                .range(0..=100_000)
+                                .prefix("Files per run: "),
+                        )
+                        .on_hover_text("0 runs every pending file at once")
+                        .changed();
+                }
+
+                if changed {
+                    state.script_settings_changed = true;
+                    let settings = config.script_settings.entry(script).or_default();
+                    settings.batch = batch;
+                    settings.batch_size = (size > 0).then_some(size);
+                }
+            });
+        }
+    }
+
     ui.horizontal(|ui| {
-        if ui.checkbox(logging_enabled, "Enable logging").changed() {
+        if ui
+            .checkbox(&mut config.logging_enabled, "Enable logging")
+            .changed()
+        {
             state.logging_changed = true;
         }
+
+        ui.add_enabled_ui(config.logging_enabled, |ui| {
+            egui::ComboBox::from_id_salt("log_location")
+                .selected_text(config.log.location.label())
+                .show_ui(ui, |ui| {
+                    let mut options =
+                        vec![LogLocation::OutputDirectory, LogLocation::StateDirectory];
+                    // Keep a configured custom directory selectable
+                    if let LogLocation::Custom(_) = config.log.location {
+                        options.push(config.log.location.clone());
+                    }
+                    for option in options {
+                        let label = option.label();
+                        if ui
+                            .selectable_value(&mut config.log.location, option, label)
+                            .changed()
+                        {
+                            state.logging_changed = true;
+                        }
+                    }
+                    if ui.selectable_label(false, "Choose directory...").clicked() {
+                        state.browse_log_dir_clicked = true;
+                    }
+                })
+                .response
+                .on_hover_text(match config.log.location {
+                    LogLocation::Custom(ref dir) => dir.display().to_string(),
+                    _ => String::new(),
+                });
+        });
     });
 
-    ui.separator();
+    ui.horizontal(|ui| {
+        if ui
+            .checkbox(
+                &mut config.report_on_completion,
+                "Write report when finished",
+            )
+            .changed()
+        {
+            state.report_changed = true;
+        }
 
-    // Start/Cancel button
-    let button_text = if is_processing { "Cancel" } else { "Start" };
-    let button = egui::Button::new(button_text).fill(if is_processing {
-        egui::Color32::from_rgb(200, 50, 50)
-    } else {
-        egui::Color32::from_rgb(50, 150, 50)
+        if ui
+            .add_enabled(has_report, egui::Button::new("Generate Report"))
+            .clicked()
+        {
+            state.report_clicked = true;
+        }
     });
 
-    if ui.add_sized([ui.available_width(), 32.0], button).clicked() {
-        if is_processing {
-            state.cancel_clicked = true;
-        } else {
-            state.start_clicked = true;
+    ui.horizontal(|ui| {
+        if ui
+            .checkbox(
+                &mut config.suspend_on_pause,
+                "Suspend running script on pause",
+            )
+            .changed()
+        {
+            state.suspend_changed = true;
         }
+    });
+
+    egui::CollapsingHeader::new("Schedule")
+        .id_salt("schedule")
+        .show(ui, |ui| {
+            if render_schedule(ui, &mut config.schedule) {
+                state.schedule_changed = true;
+            }
+        });
+
+    ui.separator();
+
+    if !is_processing {
+        ui.horizontal(|ui| {
+            let width = ui.available_width() - ui.spacing().item_spacing.x;
+
+            let button = egui::Button::new("Start").fill(egui::Color32::from_rgb(50, 150, 50));
+            if ui.add_sized([width * 0.7, 32.0], button).clicked() {
+                state.start_clicked = true;
+            }
+
+            if ui
+                .add_sized([width * 0.3, 32.0], egui::Button::new("Dry Run"))
+                .on_hover_text("Preview the commands without running them")
+                .clicked()
+            {
+                state.dry_run_clicked = true;
+            }
+        });
+        return state;
     }
 
+    // Pause/Resume and Cancel buttons
+    ui.horizontal(|ui| {
+        let width = (ui.available_width() - ui.spacing().item_spacing.x) / 2.0;
+
+        let (pause_text, pause_color) = if is_paused {
+            ("Resume", egui::Color32::from_rgb(50, 150, 50))
+        } else {
+            ("Pause", egui::Color32::from_rgb(200, 150, 50))
+        };
+        let pause_button = egui::Button::new(pause_text).fill(pause_color);
+        if ui.add_sized([width, 32.0], pause_button).clicked() {
+            if is_paused {
+                state.resume_clicked = true;
+            } else {
+                state.pause_clicked = true;
+            }
+        }
+
+        let cancel_button = egui::Button::new("Cancel").fill(egui::Color32::from_rgb(200, 50, 50));
+        if ui.add_sized([width, 32.0], cancel_button).clicked() {
+            state.cancel_clicked = true;
+        }
+    });
+
     state
 }
+
+/// Start time and processing window editors. Returns true if the settings changed.
+fn render_schedule(ui: &mut Ui, schedule: &mut ScheduleSettings) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        let mut enabled = schedule.start_at.is_some();
+        let text_id = egui::Id::new("schedule_start_at_text");
+        let mut text = ui.data_mut(|d| {
+            d.get_temp_mut_or_insert_with(text_id, || {
+                scheduler::format_time(
+                    schedule
+                        .start_at
+                        .unwrap_or(NaiveTime::from_hms_opt(22, 0, 0).unwrap()),
+                )
+            })
+            .clone()
+        });
+
+        if ui.checkbox(&mut enabled, "Start at").changed() {
+            schedule.start_at = if enabled {
+                scheduler::parse_time(&text).ok()
+            } else {
+                None
+            };
+            changed = true;
+        }
+
+        let response = ui.add(egui::TextEdit::singleline(&mut text).desired_width(50.0));
+        if response.changed() {
+            if enabled && let Ok(time) = scheduler::parse_time(&text) {
+                schedule.start_at = Some(time);
+                changed = true;
+            }
+            ui.data_mut(|d| d.insert_temp(text_id, text.clone()));
+        }
+        if let Err(e) = scheduler::parse_time(&text) {
+            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), "⚠")
+                .on_hover_text(e);
+        }
+    });
+
+    ui.horizontal(|ui| {
+        if ui
+            .checkbox(&mut schedule.windows_enabled, "Only run within")
+            .changed()
+        {
+            changed = true;
+        }
+
+        let text_id = egui::Id::new("schedule_windows_text");
+        let mut text = ui.data_mut(|d| {
+            d.get_temp_mut_or_insert_with(text_id, || scheduler::format_windows(&schedule.windows))
+                .clone()
+        });
+
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut text)
+                .hint_text("22:00-06:00")
+                .desired_width(ui.available_width() - 30.0),
+        );
+        if response.changed() {
+            if let Ok(windows) = scheduler::parse_windows(&text) {
+                schedule.windows = windows;
+                changed = true;
+            }
+            ui.data_mut(|d| d.insert_temp(text_id, text.clone()));
+        }
+        if let Err(e) = scheduler::parse_windows(&text) {
+            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), "⚠")
+                .on_hover_text(e);
+        }
+    });
+
+    changed
+}
@@ -0,0 +1,123 @@
+use crate::ui::render_finding;
+use egui::{Color32, Context};
+use taskflow_core::dry_run::DryRun;
+
+#[derive(Default)]
+pub struct DryRunInteraction {
+    pub export_clicked: bool,
+    pub close_clicked: bool,
+}
+
+pub fn render_dry_run(ctx: &Context, dry_run: &DryRun) -> DryRunInteraction {
+    let mut interaction = DryRunInteraction::default();
+    let mut open = true;
+
+    egui::Window::new("Dry Run")
+        .open(&mut open)
+        .default_size([900.0, 400.0])
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} item(s)", dry_run.rows.len()));
+                let problems = dry_run.problem_count();
+                if problems > 0 {
+                    ui.colored_label(
+                        Color32::from_rgb(255, 100, 100),
+                        format!("⚠ {} problem(s)", problems),
+                    );
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("Close").clicked() {
+                        interaction.close_clicked = true;
+                    }
+                    if ui
+                        .add_enabled(!dry_run.rows.is_empty(), egui::Button::new("Export CSV…"))
+                        .clicked()
+                    {
+                        interaction.export_clicked = true;
+                    }
+                });
+            });
+
+            for finding in &dry_run.problems {
+                render_finding(ui, finding);
+            }
+
+            ui.separator();
+
+            egui::ScrollArea::both()
+                .id_salt("dry_run_scroll")
+                .show(ui, |ui| {
+                    egui::Grid::new("dry_run_grid")
+                        .striped(true)
+                        .num_columns(6)
+                        .show(ui, |ui| {
+                            for heading in [
+                                "Input",
+                                "Command",
+                                "Working dir",
+                                "Environment",
+                                "Expected output",
+                                "Problems",
+                            ] {
+                                ui.strong(heading);
+                            }
+                            ui.end_row();
+
+                            for row in &dry_run.rows {
+                                ui.label(row.filename())
+                                    .on_hover_text(row.input.to_string_lossy());
+
+                                match row.invocation {
+                                    Some(ref inv) => {
+                                        ui.monospace(inv.command_line());
+                                        ui.label(
+                                            inv.working_dir
+                                                .as_ref()
+                                                .map(|d| d.to_string_lossy().into_owned())
+                                                .unwrap_or_else(|| "(inherited)".to_string()),
+                                        );
+                                        ui.label(format!("{} variable(s)", inv.env.len()))
+                                            .on_hover_text(
+                                                inv.env
+                                                    .iter()
+                                                    .map(|(k, v)| format!("{}={}", k, v))
+                                                    .collect::<Vec<_>>()
+                                                    .join("\n"),
+                                            );
+                                        ui.label(
+                                            inv.expected_output
+                                                .as_ref()
+                                                .map(|o| o.to_string_lossy().into_owned())
+                                                .unwrap_or_else(|| "—".to_string()),
+                                        );
+                                    }
+                                    None => {
+                                        for _ in 0..4 {
+                                            ui.weak("—");
+                                        }
+                                    }
+                                }
+
+                                if row.problems.is_empty() {
+                                    ui.colored_label(Color32::from_rgb(100, 200, 100), "✓");
+                                } else {
+                                    ui.vertical(|ui| {
+                                        for finding in &row.problems {
+                                            render_finding(ui, finding);
+                                        }
+                                    });
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+
+    if !open {
+        interaction.close_clicked = true;
+    }
+
+    interaction
+}
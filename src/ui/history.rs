@@ -0,0 +1,122 @@
+use crate::ui::{OutputAction, render_outputs_menu};
+use egui::{Color32, Ui};
+use std::path::PathBuf;
+use taskflow_core::format_duration;
+use taskflow_core::history::{History, HistoryOutcome};
+
+// Most entries listed at once; narrow the search to see older ones
+const MAX_RESULTS: usize = 500;
+
+#[derive(Default)]
+pub struct HistoryInteraction {
+    /// Inputs to re-queue with the script each was run with.
+    pub requeue: Vec<(PathBuf, String)>,
+    pub output_action: Option<OutputAction>,
+}
+
+pub fn render_history(ui: &mut Ui, history: &History, search: &mut String) -> HistoryInteraction {
+    let mut interaction = HistoryInteraction::default();
+
+    ui.horizontal(|ui| {
+        ui.label("Search:");
+        ui.text_edit_singleline(search);
+        if ui.button("✖").clicked() {
+            search.clear();
+        }
+    });
+
+    let results = history.search(search, MAX_RESULTS);
+
+    ui.horizontal(|ui| {
+        ui.label(format!("{} of {} runs", results.total, history.len()));
+        if results.total > results.entries.len() {
+            ui.weak(format!("(showing the newest {})", results.entries.len()));
+        }
+        if ui
+            .add_enabled(results.total > 0, egui::Button::new("Re-queue all"))
+            .clicked()
+        {
+            interaction.requeue = history
+                .search(search, usize::MAX)
+                .entries
+                .iter()
+                .map(|e| (e.input.clone(), e.script.clone()))
+                .collect();
+        }
+    });
+
+    ui.separator();
+
+    egui::ScrollArea::vertical()
+        .id_salt("history_scroll")
+        .max_height(ui.available_height() - 60.0)
+        .show(ui, |ui| {
+            for entry in &results.entries {
+                let (status_text, status_color) = match entry.outcome {
+                    HistoryOutcome::Success => ("✓", Color32::from_rgb(100, 200, 100)),
+                    HistoryOutcome::SucceededWithWarnings(_) => {
+                        ("⚠", Color32::from_rgb(200, 150, 50))
+                    }
+                    HistoryOutcome::Failed(_) => ("✗", Color32::from_rgb(255, 100, 100)),
+                };
+
+                egui::Frame::default()
+                    .fill(Color32::from_rgb(40, 40, 40))
+                    .inner_margin(8.0)
+                    .corner_radius(4.0)
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+
+                        ui.horizontal(|ui| {
+                            ui.colored_label(status_color, status_text);
+                            ui.label(entry.filename())
+                                .on_hover_text(entry.input.to_string_lossy());
+
+                            ui.with_layout(
+                                egui::Layout::right_to_left(egui::Align::Center),
+                                |ui| {
+                                    if ui.button("Re-queue").clicked() {
+                                        interaction
+                                            .requeue
+                                            .push((entry.input.clone(), entry.script.clone()));
+                                    }
+                                    if !entry.outputs.is_empty()
+                                        && let Some(action) =
+                                            render_outputs_menu(ui, &entry.outputs)
+                                    {
+                                        interaction.output_action = Some(action);
+                                    }
+                                },
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.weak(entry.started_at.format("%Y-%m-%d %H:%M").to_string());
+                            ui.weak(entry.script_name()).on_hover_text(&entry.script);
+                            ui.weak(format_duration(entry.duration()));
+                            ui.weak(entry.output_dir.to_string_lossy());
+                        });
+
+                        match entry.outcome {
+                            HistoryOutcome::Success => {}
+                            HistoryOutcome::SucceededWithWarnings(ref warnings) => {
+                                for warning in warnings {
+                                    ui.colored_label(Color32::from_rgb(200, 150, 50), warning);
+                                }
+                            }
+                            HistoryOutcome::Failed(ref msg) => {
+                                ui.colored_label(Color32::from_rgb(255, 100, 100), msg);
+                            }
+                        }
+                        if let Some(ref error) = entry.post_action_error {
+                            ui.colored_label(
+                                Color32::from_rgb(200, 150, 50),
+                                format!("Post-action: {}", error),
+                            );
+                        }
+                    });
+            }
+        });
+
+    interaction
+}
@@ -1,7 +1,15 @@
 mod controls;
 mod drop_zone;
+mod dry_run;
+mod history;
+mod outputs;
+mod preflight;
 mod queue_list;
 
 pub use controls::{ControlsState, render_controls};
 pub use drop_zone::{DropZoneResult, render_drop_zone};
-pub use queue_list::{QueueListInteraction, render_queue_list};
+pub use dry_run::{DryRunInteraction, render_dry_run};
+pub use history::{HistoryInteraction, render_history};
+pub use outputs::{OutputAction, render_outputs_menu};
+pub use preflight::{PreflightInteraction, render_finding, render_preflight};
+pub use queue_list::{QueueFilter, QueueListInteraction, render_queue_list};
@@ -0,0 +1,40 @@
+use egui::Ui;
+use std::path::PathBuf;
+
+/// A request to show one of an item's produced files.
+pub enum OutputAction {
+    Open(PathBuf),
+    Reveal(PathBuf),
+}
+
+/// A menu listing `outputs`, each of which can be opened, plus an entry to
+/// reveal them in the file manager.
+pub fn render_outputs_menu(ui: &mut Ui, outputs: &[PathBuf]) -> Option<OutputAction> {
+    let mut action = None;
+
+    ui.menu_button(format!("📂 {}", outputs.len()), |ui| {
+        if ui.button("Reveal outputs").clicked() {
+            action = Some(OutputAction::Reveal(outputs[0].clone()));
+            ui.close();
+        }
+        ui.separator();
+        for output in outputs {
+            let name = output
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| output.to_string_lossy().into_owned());
+            if ui
+                .button(format!("Open {}", name))
+                .on_hover_text(output.to_string_lossy())
+                .clicked()
+            {
+                action = Some(OutputAction::Open(output.clone()));
+                ui.close();
+            }
+        }
+    })
+    .response
+    .on_hover_text(format!("{} output file(s)", outputs.len()));
+
+    action
+}
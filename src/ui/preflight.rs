@@ -0,0 +1,65 @@
+use egui::{Color32, Context, Ui};
+use taskflow_core::preflight::{Finding, Preflight, Severity};
+
+#[derive(Default)]
+pub struct PreflightInteraction {
+    pub start_anyway_clicked: bool,
+    pub close_clicked: bool,
+}
+
+pub fn render_preflight(ctx: &Context, preflight: &Preflight) -> PreflightInteraction {
+    let mut interaction = PreflightInteraction::default();
+    let mut open = true;
+
+    egui::Window::new("Pre-flight Check")
+        .open(&mut open)
+        .collapsible(false)
+        .default_width(500.0)
+        .show(ctx, |ui| {
+            let errors = preflight.error_count();
+            let warnings = preflight.warning_count();
+            ui.label(format!("{} error(s), {} warning(s)", errors, warnings));
+            if errors > 0 {
+                ui.weak("Fix the errors before starting.");
+            }
+
+            ui.separator();
+
+            egui::ScrollArea::vertical()
+                .id_salt("preflight_scroll")
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for finding in &preflight.findings {
+                        render_finding(ui, finding);
+                    }
+                });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(errors == 0, egui::Button::new("Start anyway"))
+                    .clicked()
+                {
+                    interaction.start_anyway_clicked = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    interaction.close_clicked = true;
+                }
+            });
+        });
+
+    if !open {
+        interaction.close_clicked = true;
+    }
+
+    interaction
+}
+
+pub fn render_finding(ui: &mut Ui, finding: &Finding) {
+    let (icon, color) = match finding.severity {
+        Severity::Error => ("✗", Color32::from_rgb(255, 100, 100)),
+        Severity::Warning => ("⚠", Color32::from_rgb(200, 150, 50)),
+    };
+    ui.colored_label(color, format!("{} {}", icon, finding.message));
+}
@@ -1,29 +1,138 @@
-use crate::queue::{ItemState, QueueItem};
+use crate::ui::{OutputAction, render_outputs_menu};
 use egui::{Color32, Ui};
+use std::path::Path;
+use taskflow_core::config::CompletedItems;
+use taskflow_core::queue::{ItemState, QueueItem};
 
+/// Which items the queue shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum QueueFilter {
+    #[default]
+    All,
+    Pending,
+    Completed,
+    WithWarnings,
+    Failed,
+}
+
+impl QueueFilter {
+    const ALL: [QueueFilter; 5] = [
+        QueueFilter::All,
+        QueueFilter::Pending,
+        QueueFilter::Completed,
+        QueueFilter::WithWarnings,
+        QueueFilter::Failed,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            QueueFilter::All => "All",
+            QueueFilter::Pending => "Pending",
+            QueueFilter::Completed => "Completed",
+            QueueFilter::WithWarnings => "With warnings",
+            QueueFilter::Failed => "Failed",
+        }
+    }
+
+    fn shows(&self, item: &QueueItem) -> bool {
+        match self {
+            QueueFilter::All => true,
+            QueueFilter::Pending => {
+                matches!(item.state(), ItemState::Pending | ItemState::Processing)
+            }
+            QueueFilter::Completed => item.is_completed(),
+            QueueFilter::WithWarnings => *item.state() == ItemState::CompletedWithWarnings,
+            QueueFilter::Failed => item.is_failed(),
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct QueueListInteraction {
     pub clicked_index: Option<usize>,
     pub ctrl_held: bool,
     pub shift_held: bool,
     pub delete_pressed: bool,
     pub drag_target: Option<usize>,
+    pub clear_completed: bool,
+    pub clear_warnings: bool,
+    pub clear_failed: bool,
+    pub completed_items_changed: bool,
+    pub output_action: Option<OutputAction>,
 }
 
-impl Default for QueueListInteraction {
-    fn default() -> Self {
-        Self {
-            clicked_index: None,
-            ctrl_held: false,
-            shift_held: false,
-            delete_pressed: false,
-            drag_target: None,
-        }
-    }
-}
-
-pub fn render_queue_list(ui: &mut Ui, items: &mut [QueueItem]) -> QueueListInteraction {
+pub fn render_queue_list(
+    ui: &mut Ui,
+    items: &[QueueItem],
+    completed_items: &mut CompletedItems,
+    filter: &mut QueueFilter,
+) -> QueueListInteraction {
     let mut interaction = QueueListInteraction::default();
 
+    // Items with warnings aren't cleared with the completed ones
+    let completed_count = items
+        .iter()
+        .filter(|item| item.is_completed() && *item.state() != ItemState::CompletedWithWarnings)
+        .count();
+    let warned_count = items
+        .iter()
+        .filter(|item| *item.state() == ItemState::CompletedWithWarnings)
+        .count();
+    let failed_count = items.iter().filter(|item| item.is_failed()).count();
+
+    ui.horizontal(|ui| {
+        ui.label(format!("Queue ({} items)", items.len()));
+
+        egui::ComboBox::from_id_salt("queue_filter")
+            .selected_text(filter.label())
+            .show_ui(ui, |ui| {
+                for option in QueueFilter::ALL {
+                    ui.selectable_value(filter, option, option.label());
+                }
+            });
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            if ui
+                .add_enabled(failed_count > 0, egui::Button::new("Clear failed"))
+                .clicked()
+            {
+                interaction.clear_failed = true;
+            }
+
+            if ui
+                .add_enabled(warned_count > 0, egui::Button::new("Clear warnings"))
+                .clicked()
+            {
+                interaction.clear_warnings = true;
+            }
+
+            if ui
+                .add_enabled(completed_count > 0, egui::Button::new("Clear completed"))
+                .clicked()
+            {
+                interaction.clear_completed = true;
+            }
+
+            egui::ComboBox::from_id_salt("completed_items")
+                .selected_text(completed_items.label())
+                .show_ui(ui, |ui| {
+                    for option in [
+                        CompletedItems::Remove,
+                        CompletedItems::KeepInPlace,
+                        CompletedItems::MoveToDone,
+                    ] {
+                        if ui
+                            .selectable_value(completed_items, option, option.label())
+                            .changed()
+                        {
+                            interaction.completed_items_changed = true;
+                        }
+                    }
+                });
+            ui.label("Completed:");
+        });
+    });
+
     // Check for modifier keys and delete
     ui.input(|i| {
         interaction.ctrl_held = i.modifiers.ctrl;
@@ -35,41 +144,76 @@ pub fn render_queue_list(ui: &mut Ui, items: &mut [QueueItem]) -> QueueListInter
         .id_salt("queue_scroll")
         .max_height(ui.available_height() - 60.0)
         .show(ui, |ui| {
-            for (index, item) in items.iter_mut().enumerate() {
-                let item_interaction = render_queue_item(ui, index, item);
+            let separate_done = *completed_items == CompletedItems::MoveToDone;
+            let done_count = items
+                .iter()
+                .filter(|item| item.is_completed() && filter.shows(item))
+                .count();
 
-                if let Some(clicked) = item_interaction.clicked {
-                    interaction.clicked_index = Some(clicked);
+            for (index, item) in items.iter().enumerate() {
+                if (separate_done && item.is_completed()) || !filter.shows(item) {
+                    continue;
                 }
+                let item_interaction = render_queue_item(ui, index, item);
+                merge_item_interaction(&mut interaction, item_interaction);
+            }
 
-                if let Some(target) = item_interaction.drag_target {
-                    interaction.drag_target = Some(target);
-                }
+            if separate_done && done_count > 0 {
+                egui::CollapsingHeader::new(format!("Done ({})", done_count))
+                    .id_salt("done_section")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for (index, item) in items.iter().enumerate() {
+                            if !item.is_completed() || !filter.shows(item) {
+                                continue;
+                            }
+                            let item_interaction = render_queue_item(ui, index, item);
+                            merge_item_interaction(&mut interaction, item_interaction);
+                        }
+                    });
             }
         });
 
     interaction
 }
 
+fn merge_item_interaction(interaction: &mut QueueListInteraction, item: ItemInteraction) {
+    if let Some(clicked) = item.clicked {
+        interaction.clicked_index = Some(clicked);
+    }
+
+    if let Some(target) = item.drag_target {
+        interaction.drag_target = Some(target);
+    }
+
+    if item.output_action.is_some() {
+        interaction.output_action = item.output_action;
+    }
+}
+
 struct ItemInteraction {
     clicked: Option<usize>,
     drag_target: Option<usize>,
+    output_action: Option<OutputAction>,
 }
 
-fn render_queue_item(ui: &mut Ui, index: usize, item: &mut QueueItem) -> ItemInteraction {
+fn render_queue_item(ui: &mut Ui, index: usize, item: &QueueItem) -> ItemInteraction {
     let mut interaction = ItemInteraction {
         clicked: None,
         drag_target: None,
+        output_action: None,
     };
 
     let is_locked = item.is_locked();
 
     // Determine colors based on state
-    let (bg_color, text_color) = match &item.state {
-        ItemState::Pending if item.selected => (Color32::from_rgb(70, 100, 150), Color32::WHITE),
+    let (bg_color, text_color) = match item.state() {
+        _ if item.is_selected() && !is_locked => (Color32::from_rgb(70, 100, 150), Color32::WHITE),
         ItemState::Pending => (Color32::from_rgb(40, 40, 40), Color32::LIGHT_GRAY),
         ItemState::Processing => (Color32::from_rgb(60, 60, 60), Color32::DARK_GRAY),
-        ItemState::Completed => (Color32::from_rgb(50, 80, 50), Color32::LIGHT_GRAY),
+        ItemState::Completed => (Color32::from_rgb(35, 50, 35), Color32::GRAY),
+        ItemState::CompletedWithWarnings => (Color32::from_rgb(55, 50, 30), Color32::GRAY),
+        ItemState::Skipped(_) => (Color32::from_rgb(45, 45, 35), Color32::GRAY),
         ItemState::Error(_) => (Color32::from_rgb(100, 40, 40), Color32::LIGHT_GRAY),
     };
 
@@ -87,10 +231,12 @@ fn render_queue_item(ui: &mut Ui, index: usize, item: &mut QueueItem) -> ItemInt
             ui.set_width(ui.available_width());
 
             // Status indicator
-            let status_text = match &item.state {
+            let status_text = match item.state() {
                 ItemState::Pending => "⏸",
                 ItemState::Processing => "▶",
                 ItemState::Completed => "✓",
+                ItemState::CompletedWithWarnings => "⚠",
+                ItemState::Skipped(_) => "⏭",
                 ItemState::Error(_) => "✗",
             };
             ui.colored_label(text_color, status_text);
@@ -98,9 +244,44 @@ fn render_queue_item(ui: &mut Ui, index: usize, item: &mut QueueItem) -> ItemInt
             // Filename
             ui.colored_label(text_color, item.filename());
 
+            // Items re-queued from history keep their own script
+            if let Some(script) = item.script() {
+                let name = Path::new(script)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| script.to_string());
+                ui.weak(format!("({})", name)).on_hover_text(script);
+            }
+
             // Error message if present
-            if let ItemState::Error(ref msg) = item.state {
-                ui.colored_label(Color32::from_rgb(255, 100, 100), format!(" - {}", msg));
+            match item.state() {
+                ItemState::Error(msg) => {
+                    ui.colored_label(Color32::from_rgb(255, 100, 100), format!(" - {}", msg));
+                }
+                ItemState::Skipped(reason) => {
+                    ui.colored_label(text_color, format!(" - {}", reason));
+                }
+                _ => {}
+            }
+
+            if let Some(error) = item.post_action_error() {
+                ui.colored_label(Color32::from_rgb(200, 150, 50), "⚠ post-action")
+                    .on_hover_text(error);
+            }
+
+            if !item.warnings().is_empty() {
+                let label = match item.warnings().len() {
+                    1 => "⚠ 1 warning".to_string(),
+                    n => format!("⚠ {} warnings", n),
+                };
+                ui.colored_label(Color32::from_rgb(200, 150, 50), label)
+                    .on_hover_text(item.warnings().join("\n"));
+            }
+
+            if !item.outputs().is_empty() {
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    interaction.output_action = render_outputs_menu(ui, item.outputs());
+                });
             }
         })
     });
@@ -0,0 +1,698 @@
+//! `taskflow agent`: processes items sent by a coordinator over TCP.
+//!
+//! Each item gets its own connection. Messages are JSON lines; a `file`
+//! message is followed by exactly `size` bytes of content.
+//!
+//! 1. The agent sends a `challenge` nonce and the coordinator answers with
+//!    `auth`: the hex HMAC-SHA256 of the nonce keyed by the shared secret,
+//!    and a nonce of its own.
+//! 2. On `ready`, the coordinator sends the `job`. With upload staging it
+//!    follows with the script and input as `file`s, then `run`.
+//! 3. While the script runs the agent sends a `heartbeat` every few seconds.
+//!    With upload staging it then sends the outputs as `file`s, and finally
+//!    `finished` with the script's error, if any.
+//!
+//! After `ready`, every line starts with a hex HMAC-SHA256 of the sender,
+//! the line's sequence number in its direction and the message, keyed by a
+//! session key derived from the secret and both nonces. A file's content is
+//! followed by a line with the same MAC of the content. A line that fails
+//! the check ends the connection, so nobody on the path can alter a job or
+//! the files sent with it.
+
+use crate::config::ScriptSettings;
+use crate::events;
+use crate::executor::{self, Execution, Running};
+use crate::invocation::Invocation;
+use crate::secret;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Port an agent listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7979;
+/// How often an agent reports in while a script runs. A coordinator gives
+/// up on an agent it hasn't heard from in three intervals.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// How long either side waits on a peer that should be sending or reading
+pub(crate) const IO_TIMEOUT: Duration = Duration::from_secs(30);
+// Longest JSON line accepted, so an unauthenticated peer can't exhaust memory
+const MAX_LINE: u64 = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Message {
+    Challenge {
+        nonce: String,
+    },
+    Auth {
+        mac: String,
+        /// The coordinator's part of the session key.
+        nonce: String,
+    },
+    Ready,
+    Rejected {
+        reason: String,
+    },
+    /// Paths are the coordinator's; with upload staging the agent swaps
+    /// in its staged copies.
+    Job {
+        script: String,
+        input: PathBuf,
+        output_dir: PathBuf,
+        /// Relative to `output_dir`.
+        expected_output: Option<PathBuf>,
+        /// File name of the manifest in the output directory.
+        manifest: String,
+        settings: Box<ScriptSettings>,
+        upload: bool,
+    },
+    /// Relative to the staging directory (coordinator to agent) or the
+    /// output directory (agent to coordinator).
+    File {
+        path: String,
+        size: u64,
+    },
+    Run,
+    Heartbeat,
+    Finished {
+        error: Option<String>,
+        /// What the script printed.
+        #[serde(default)]
+        stdout: String,
+        #[serde(default)]
+        warnings: Vec<String>,
+    },
+}
+
+/// Which end of a connection sent a line, so a line can't be reflected
+/// back to its sender.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Side {
+    Agent,
+    Coordinator,
+}
+
+impl Side {
+    fn peer(self) -> Self {
+        match self {
+            Side::Agent => Side::Coordinator,
+            Side::Coordinator => Side::Agent,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Side::Agent => "agent",
+            Side::Coordinator => "coordinator",
+        }
+    }
+}
+
+/// The key and line counts of an authenticated connection.
+struct Session {
+    key: Vec<u8>,
+    side: Side,
+    sent: u64,
+    received: u64,
+}
+
+impl Session {
+    /// A MAC for the next line this side sends.
+    fn outgoing(&mut self) -> Hmac<Sha256> {
+        self.sent += 1;
+        self.mac(self.side, self.sent)
+    }
+
+    /// A MAC for the next line the other side sends.
+    fn incoming(&mut self) -> Hmac<Sha256> {
+        self.received += 1;
+        self.mac(self.side.peer(), self.received)
+    }
+
+    fn mac(&self, sender: Side, sequence: u64) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(format!("{} {}\n", sender.label(), sequence).as_bytes());
+        mac
+    }
+}
+
+/// One end of an agent connection.
+pub(crate) struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    session: Option<Session>,
+}
+
+impl Connection {
+    pub(crate) fn new(stream: TcpStream) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            session: None,
+        })
+    }
+
+    /// Authenticates every line from now on with `key`, as `side`.
+    pub(crate) fn secure(&mut self, key: Vec<u8>, side: Side) {
+        self.session = Some(Session {
+            key,
+            side,
+            sent: 0,
+            received: 0,
+        });
+    }
+
+    pub(crate) fn send(&mut self, message: &Message) -> io::Result<()> {
+        let json = serde_json::to_string(message)?;
+        let line = match self.session {
+            Some(ref mut session) => {
+                let mac = session.outgoing().chain_update(json.as_bytes());
+                format!("{} {}\n", hex(mac), json)
+            }
+            None => format!("{}\n", json),
+        };
+        self.writer.write_all(line.as_bytes())
+    }
+
+    pub(crate) fn recv(&mut self) -> io::Result<Message> {
+        let line = self.recv_line()?;
+        let json = match self.session {
+            Some(ref mut session) => {
+                let (mac, json) = line.split_once(' ').unwrap_or_default();
+                let expected = session.incoming().chain_update(json.as_bytes());
+                if !secret::constant_time_eq(mac, &hex(expected)) {
+                    return Err(forged());
+                }
+                json
+            }
+            None => &line,
+        };
+        serde_json::from_str(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The next line, without its newline.
+    fn recv_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        let read = (&mut self.reader).take(MAX_LINE).read_line(&mut line)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if line.pop() != Some('\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too long",
+            ));
+        }
+        Ok(line)
+    }
+
+    /// Sends `file` as `path`.
+    pub(crate) fn send_file(&mut self, path: &str, file: &Path) -> io::Result<()> {
+        let mut source = fs::File::open(file)?;
+        let size = source.metadata()?.len();
+        self.send(&Message::File {
+            path: path.to_string(),
+            size,
+        })?;
+        let mut writer = Signing {
+            inner: &mut self.writer,
+            mac: self.session.as_mut().map(Session::outgoing),
+        };
+        let copied = io::copy(&mut (&mut source).take(size), &mut writer)?;
+        if copied != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} shrank while being sent", file.display()),
+            ));
+        }
+        if let Some(mac) = writer.mac {
+            self.writer
+                .write_all(format!("{}\n", hex(mac)).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Receives the content of a `file` message into `dest`, which is
+    /// removed again if the content fails the session's check.
+    pub(crate) fn recv_file(&mut self, size: u64, dest: &Path) -> io::Result<()> {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = Signing {
+            inner: fs::File::create(dest)?,
+            mac: self.session.as_mut().map(Session::incoming),
+        };
+        let copied = io::copy(&mut (&mut self.reader).take(size), &mut file)?;
+        if copied != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(mac) = file.mac {
+            let line = self.recv_line()?;
+            if !secret::constant_time_eq(&line, &hex(mac)) {
+                let _ = fs::remove_file(dest);
+                return Err(forged());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes through to `inner`, adding what it writes to `mac`.
+struct Signing<W> {
+    inner: W,
+    mac: Option<Hmac<Sha256>>,
+}
+
+impl<W: Write> Write for Signing<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(ref mut mac) = self.mac {
+            mac.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn hex(mac: Hmac<Sha256>) -> String {
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn forged() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "message failed authentication")
+}
+
+/// `path` under `base`, provided it is relative and stays inside it.
+pub(crate) fn confined(base: &Path, path: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(path);
+    let plain = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if plain && !path.is_empty() {
+        Ok(base.join(relative))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("refusing path {}", path),
+        ))
+    }
+}
+
+/// The answer to a challenge `nonce` for `secret`.
+pub(crate) fn respond_to(secret: &str, nonce: &str) -> String {
+    events::sign(secret, nonce)
+}
+
+/// The key for a session's lines, from the secret and both sides' nonces.
+pub(crate) fn session_key(secret: &str, agent_nonce: &str, coordinator_nonce: &str) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("session {} {}", agent_nonce, coordinator_nonce).as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// How an agent started with [`serve`] listens and stages work.
+#[derive(Debug, Clone)]
+pub struct AgentOptions {
+    /// `host:port` to listen on.
+    pub listen: String,
+    /// Coordinators must prove they know this.
+    pub secret: String,
+    /// Where uploaded scripts, inputs and outputs are staged.
+    pub work_dir: PathBuf,
+}
+
+/// Accepts items from coordinators, handling each connection on its own
+/// thread. Only returns if the agent can't start. `log` is given a line per
+/// connection and item.
+pub fn serve(
+    options: AgentOptions,
+    log: impl Fn(&str) + Send + Sync + 'static,
+) -> Result<(), String> {
+    if options.secret.is_empty() {
+        return Err("Agent secret is not set".to_string());
+    }
+    fs::create_dir_all(&options.work_dir).map_err(|e| {
+        format!(
+            "Failed to create work directory {}: {}",
+            options.work_dir.display(),
+            e
+        )
+    })?;
+    let listener = TcpListener::bind(&options.listen)
+        .map_err(|e| format!("Failed to listen on {}: {}", options.listen, e))?;
+    log(&format!("Listening on {}", options.listen));
+    accept(listener, options, log);
+    Ok(())
+}
+
+/// Handles every connection to `listener`, each on its own thread.
+pub(crate) fn accept(
+    listener: TcpListener,
+    options: AgentOptions,
+    log: impl Fn(&str) + Send + Sync + 'static,
+) {
+    let options = Arc::new(options);
+    let log = Arc::new(log);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log(&format!("Failed to accept connection: {}", e));
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        let (options, log) = (Arc::clone(&options), Arc::clone(&log));
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &options, &*log) {
+                log(&format!("{}: {}", peer, e));
+            }
+        });
+    }
+}
+
+fn handle(stream: TcpStream, options: &AgentOptions, log: &dyn Fn(&str)) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut connection = Connection::new(stream)?;
+
+    let nonce = secret::random_token()?;
+    connection.send(&Message::Challenge {
+        nonce: nonce.clone(),
+    })?;
+    let key = match connection.recv()? {
+        Message::Auth {
+            mac,
+            nonce: coordinator_nonce,
+        } if secret::constant_time_eq(&mac, &respond_to(&options.secret, &nonce)) => {
+            Some(session_key(&options.secret, &nonce, &coordinator_nonce))
+        }
+        _ => None,
+    };
+    let Some(key) = key else {
+        connection.send(&Message::Rejected {
+            reason: "invalid secret".to_string(),
+        })?;
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "rejected: invalid secret",
+        ));
+    };
+    connection.send(&Message::Ready)?;
+    connection.secure(key, Side::Agent);
+
+    let Message::Job {
+        script,
+        input,
+        output_dir,
+        expected_output,
+        manifest,
+        settings,
+        upload,
+    } = connection.recv()?
+    else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a job"));
+    };
+
+    let stage = options.work_dir.join(executor::unique_name());
+    let expected_output = expected_output.as_deref();
+    let execution = if upload {
+        let staged = JobPaths::staged(&stage, &script, &input)?;
+        let result = receive_and_run(
+            &mut connection,
+            &stage,
+            &staged,
+            expected_output,
+            &manifest,
+            &settings,
+            log,
+        )
+        .and_then(|execution| {
+            if execution.result.is_ok() {
+                executor::localize_manifest(
+                    &staged.output_dir.join(&manifest),
+                    &staged.output_dir,
+                    &output_dir,
+                );
+                send_outputs(&mut connection, &staged.output_dir, &staged.output_dir)?;
+            }
+            Ok(execution)
+        });
+        let _ = fs::remove_dir_all(&stage);
+        result
+    } else {
+        let shared = JobPaths {
+            script: PathBuf::from(script),
+            input,
+            output_dir,
+        };
+        receive_and_run(
+            &mut connection,
+            &stage,
+            &shared,
+            expected_output,
+            &manifest,
+            &settings,
+            log,
+        )
+    }?;
+
+    connection.send(&Message::Finished {
+        error: execution.result.err(),
+        stdout: execution.stdout,
+        warnings: execution.warnings,
+    })
+}
+
+/// Where an item's files are on the agent.
+struct JobPaths {
+    script: PathBuf,
+    input: PathBuf,
+    output_dir: PathBuf,
+}
+
+impl JobPaths {
+    /// Paths for an uploaded item staged under `stage`.
+    fn staged(stage: &Path, script: &str, input: &Path) -> io::Result<Self> {
+        let output_dir = stage.join("out");
+        fs::create_dir_all(stage.join("in"))?;
+        fs::create_dir_all(&output_dir)?;
+        let name = |path: &Path| path.file_name().map(PathBuf::from).unwrap_or_default();
+        Ok(Self {
+            script: stage.join(name(Path::new(script))),
+            input: stage.join("in").join(name(input)),
+            output_dir,
+        })
+    }
+}
+
+/// Receives any uploaded files, then runs the script once told to. `Err`
+/// means the connection failed.
+fn receive_and_run(
+    connection: &mut Connection,
+    stage: &Path,
+    paths: &JobPaths,
+    expected_output: Option<&Path>,
+    manifest: &str,
+    settings: &ScriptSettings,
+    log: &dyn Fn(&str),
+) -> io::Result<Execution> {
+    loop {
+        match connection.recv()? {
+            Message::File { path, size } => {
+                let dest = confined(stage, &path)?;
+                connection.recv_file(size, &dest)?;
+            }
+            Message::Run => break,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected run")),
+        }
+    }
+
+    let JobPaths {
+        script,
+        input,
+        output_dir,
+    } = paths;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if script.starts_with(stage) {
+            fs::set_permissions(script, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+
+    let invocation = Invocation::resolve_with_output(
+        &script.to_string_lossy(),
+        input,
+        output_dir,
+        settings,
+        expected_output.map(|output| output_dir.join(output)),
+    );
+    let manifest = confined(output_dir, manifest)?;
+    log(&format!("Processing {}", input.display()));
+
+    // The script runs on its own thread so heartbeats keep going out
+    let running = Arc::new(Running::default());
+    let (done_tx, done_rx) = mpsc::channel();
+    let worker = {
+        let (running, settings) = (Arc::clone(&running), settings.clone());
+        thread::spawn(move || {
+            let executor = executor::for_settings(&settings.executor);
+            let execution = executor.execute(&invocation, &settings, &manifest, &running);
+            let _ = done_tx.send(execution);
+        })
+    };
+
+    let execution = loop {
+        match done_rx.recv_timeout(HEARTBEAT_INTERVAL) {
+            Ok(execution) => break execution,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Err(e) = connection.send(&Message::Heartbeat) {
+                    // Nobody is waiting for the result any more
+                    running.kill();
+                    let _ = worker.join();
+                    return Err(e);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                break Execution::failed("Script runner stopped unexpectedly".to_string());
+            }
+        }
+    };
+    let _ = worker.join();
+
+    match execution.result {
+        Ok(()) => log(&format!("Finished {}", input.display())),
+        Err(ref e) => log(&format!("Failed {}: {}", input.display(), e)),
+    }
+    Ok(execution)
+}
+
+/// Sends every file under `dir` as a path relative to `base`.
+fn send_outputs(connection: &mut Connection, dir: &Path, base: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            send_outputs(connection, &path, base)?;
+        } else if let Ok(relative) = path.strip_prefix(base) {
+            connection.send_file(&relative.to_string_lossy(), &path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Both ends of a connection, with a raw handle on the first end's
+    /// stream for writing lines the session didn't sign.
+    fn pair() -> (Connection, Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let coordinator = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (agent, _) = listener.accept().unwrap();
+        let raw = coordinator.try_clone().unwrap();
+        (
+            Connection::new(coordinator).unwrap(),
+            Connection::new(agent).unwrap(),
+            raw,
+        )
+    }
+
+    fn secured(key: &[u8]) -> (Connection, Connection, TcpStream) {
+        let (mut coordinator, mut agent, raw) = pair();
+        coordinator.secure(key.to_vec(), Side::Coordinator);
+        agent.secure(key.to_vec(), Side::Agent);
+        (coordinator, agent, raw)
+    }
+
+    fn rejected(result: io::Result<impl std::fmt::Debug>) -> bool {
+        result.is_err_and(|e| e.to_string() == "message failed authentication")
+    }
+
+    #[test]
+    fn session_lines_and_files_arrive_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, dest) = (dir.path().join("source"), dir.path().join("dest"));
+        fs::write(&source, "#!/bin/sh\necho hi\n").unwrap();
+        let key = session_key("secret", "agent nonce", "coordinator nonce");
+        let (mut coordinator, mut agent, _) = secured(&key);
+
+        coordinator.send(&Message::Run).unwrap();
+        assert!(matches!(agent.recv().unwrap(), Message::Run));
+        coordinator.send_file("script.sh", &source).unwrap();
+        let Message::File { path, size } = agent.recv().unwrap() else {
+            panic!("expected a file");
+        };
+        assert_eq!(path, "script.sh");
+        agent.recv_file(size, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), fs::read(&source).unwrap());
+
+        agent.send(&Message::Heartbeat).unwrap();
+        assert!(matches!(coordinator.recv().unwrap(), Message::Heartbeat));
+    }
+
+    #[test]
+    fn lines_from_outside_the_session_are_refused() {
+        let (_coordinator, mut agent, mut raw) = secured(b"key");
+        raw.write_all(b"{\"type\":\"run\"}\n").unwrap();
+        assert!(rejected(agent.recv()));
+
+        // A wrong key, or a line reflected back to its sender, fails too
+        let (mut coordinator_b, mut agent_b, _) = pair();
+        coordinator_b.secure(b"key".to_vec(), Side::Coordinator);
+        agent_b.secure(b"other key".to_vec(), Side::Agent);
+        coordinator_b.send(&Message::Run).unwrap();
+        assert!(rejected(agent_b.recv()));
+
+        let (mut left, mut right, _) = pair();
+        left.secure(b"key".to_vec(), Side::Coordinator);
+        right.secure(b"key".to_vec(), Side::Coordinator);
+        left.send(&Message::Run).unwrap();
+        assert!(rejected(right.recv()));
+    }
+
+    #[test]
+    fn altered_file_content_is_refused_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("script.sh");
+        let (mut coordinator, mut agent, mut raw) = secured(b"key");
+
+        coordinator
+            .send(&Message::File {
+                path: "script.sh".to_string(),
+                size: 3,
+            })
+            .unwrap();
+        raw.write_all(format!("abc{}\n", "0".repeat(64)).as_bytes())
+            .unwrap();
+
+        let Message::File { size, .. } = agent.recv().unwrap() else {
+            panic!("expected a file");
+        };
+        assert!(rejected(agent.recv_file(size, &dest)));
+        assert!(!dest.exists());
+    }
+}
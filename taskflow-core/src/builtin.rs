@@ -0,0 +1,346 @@
+//! Processors that run inside TaskFlow instead of as a script. They are
+//! selected like scripts, by names such as `builtin:gzip`, and take their
+//! output from the script settings' `output_name`.
+
+use crate::config::ScriptSettings;
+use crate::executor::{Execution, Executor, Running};
+use crate::invocation::Invocation;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// What script names of built-ins start with.
+pub const PREFIX: &str = "builtin:";
+
+// Batch files are appended to by every worker
+static BATCH_FILES: Mutex<()> = Mutex::new(());
+
+/// A processor built into TaskFlow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// Copies the input to the output.
+    Copy,
+    /// Moves the input to the output.
+    Move,
+    /// Compresses the input with gzip.
+    Gzip,
+    /// Compresses the input with zstd.
+    Zstd,
+    /// Appends the input's SHA-256 to a `sha256sum`-style manifest of the run.
+    Sha256,
+    /// Appends the input to a zip archive of the run.
+    Zip,
+}
+
+impl Builtin {
+    /// Every built-in, in the order they are offered.
+    pub const ALL: [Builtin; 6] = [
+        Builtin::Copy,
+        Builtin::Move,
+        Builtin::Gzip,
+        Builtin::Zstd,
+        Builtin::Sha256,
+        Builtin::Zip,
+    ];
+
+    /// The built-in a script name refers to, if any.
+    pub fn parse(script: &str) -> Option<Self> {
+        let name = script.strip_prefix(PREFIX)?;
+        Self::ALL.into_iter().find(|builtin| builtin.name() == name)
+    }
+
+    /// The name after [`PREFIX`], e.g. `gzip`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Copy => "copy",
+            Builtin::Move => "move",
+            Builtin::Gzip => "gzip",
+            Builtin::Zstd => "zstd",
+            Builtin::Sha256 => "sha256",
+            Builtin::Zip => "zip",
+        }
+    }
+
+    /// The name to select it by, e.g. `builtin:gzip`.
+    pub fn script(&self) -> String {
+        format!("{}{}", PREFIX, self.name())
+    }
+
+    /// Name shown in the UI.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Builtin::Copy => "Copy",
+            Builtin::Move => "Move",
+            Builtin::Gzip => "Compress (gzip)",
+            Builtin::Zstd => "Compress (zstd)",
+            Builtin::Sha256 => "SHA-256 manifest",
+            Builtin::Zip => "Zip the batch",
+        }
+    }
+
+    /// The output used when the script settings don't name one.
+    pub fn default_output_name(&self) -> &'static str {
+        match self {
+            Builtin::Copy | Builtin::Move => "{name}",
+            Builtin::Gzip => "{name}.gz",
+            Builtin::Zstd => "{name}.zst",
+            Builtin::Sha256 => "SHA256SUMS",
+            Builtin::Zip => "batch.zip",
+        }
+    }
+
+    /// Whether every item of a run adds to the same output, whose
+    /// collision policy is therefore applied once per run.
+    pub fn is_batch(&self) -> bool {
+        matches!(self, Builtin::Sha256 | Builtin::Zip)
+    }
+
+    fn run(&self, input: &Path, output: &Path) -> io::Result<()> {
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match self {
+            Builtin::Copy => write_atomically(output, |file| {
+                io::copy(&mut File::open(input)?, file).map(drop)
+            }),
+            Builtin::Move => match fs::rename(input, output) {
+                Ok(()) => Ok(()),
+                // Across filesystems a rename is a copy and delete
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                    write_atomically(output, |file| {
+                        io::copy(&mut File::open(input)?, file).map(drop)
+                    })?;
+                    fs::remove_file(input)
+                }
+                Err(e) => Err(e),
+            },
+            Builtin::Gzip => write_atomically(output, |file| {
+                let mut encoder = GzEncoder::new(file, Compression::default());
+                io::copy(&mut File::open(input)?, &mut encoder)?;
+                encoder.finish().map(drop)
+            }),
+            Builtin::Zstd => write_atomically(output, |file| {
+                zstd::stream::copy_encode(File::open(input)?, file, 0)
+            }),
+            Builtin::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut File::open(input)?, &mut hasher)?;
+                let hash: String = hasher
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+
+                let _guard = BATCH_FILES.lock().unwrap();
+                let mut manifest = OpenOptions::new().create(true).append(true).open(output)?;
+                writeln!(manifest, "{}  {}", hash, file_name(input))
+            }
+            Builtin::Zip => {
+                let _guard = BATCH_FILES.lock().unwrap();
+                append_to_zip(input, output)
+            }
+        }
+    }
+}
+
+impl Executor for Builtin {
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        _settings: &ScriptSettings,
+        manifest: &Path,
+        _running: &Running,
+    ) -> Execution {
+        self.execute_builtin(invocation, manifest).into()
+    }
+}
+
+impl Builtin {
+    /// Where the built-in writes for `invocation`.
+    pub(crate) fn output(&self, invocation: &Invocation) -> PathBuf {
+        invocation.expected_output.clone().unwrap_or_else(|| {
+            let name = invocation.expand(self.default_output_name());
+            invocation.output_dir.join(name)
+        })
+    }
+
+    fn execute_builtin(&self, invocation: &Invocation, manifest: &Path) -> Result<(), String> {
+        let output = self.output(invocation);
+        if output == invocation.input {
+            return Err(format!(
+                "Script failed: {} would overwrite its input",
+                self.script()
+            ));
+        }
+
+        self.run(&invocation.input, &output)
+            .map_err(|e| format!("Script failed: {}: {}", self.script(), e))?;
+        // The manifest is best effort; without it only the expected output
+        // is attributed to the item
+        let _ = fs::write(manifest, format!("{}\n", output.display()));
+        Ok(())
+    }
+}
+
+/// Removes a batch output left by an earlier run that the collision policy
+/// lets the run overwrite, so the file holds only this run's items.
+pub(crate) fn start_batch(output: &Path) -> io::Result<()> {
+    let _guard = BATCH_FILES.lock().unwrap();
+    match fs::remove_file(output) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Writes `path` through a temporary file, so a failure never leaves a
+/// partial output behind.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> io::Result<()> {
+    let partial = PathBuf::from(format!("{}.part", path.display()));
+    let result = File::create(&partial).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match result {
+        Ok(()) => fs::rename(&partial, path),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+fn append_to_zip(input: &Path, archive: &Path) -> io::Result<()> {
+    let exists = archive.exists();
+    let taken: HashSet<String> = if exists {
+        ZipArchive::new(File::open(archive)?)
+            .map_err(io::Error::other)?
+            .file_names()
+            .map(str::to_string)
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(archive)?;
+    let mut zip = if exists {
+        ZipWriter::new_append(file).map_err(io::Error::other)?
+    } else {
+        ZipWriter::new(file)
+    };
+
+    let mut source = File::open(input)?;
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(source.metadata()?.len() >= u32::MAX as u64);
+    zip.start_file(entry_name(input, &taken), options)
+        .map_err(io::Error::other)?;
+    io::copy(&mut source, &mut zip)?;
+    zip.finish().map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// The input's name, numbered like an auto-renamed output if an input of
+/// the same name from another directory is already in the archive.
+fn entry_name(input: &Path, taken: &HashSet<String>) -> String {
+    let name = file_name(input);
+    if !taken.contains(&name) {
+        return name;
+    }
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = input
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| format!("{}_{}{}", stem, n, extension))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Runs `builtin` on an input holding `hello\n`, returning the input
+    /// and where the built-in wrote.
+    fn run(builtin: Builtin, dir: &Path) -> (PathBuf, PathBuf) {
+        let input = dir.join("in").join("a.txt");
+        fs::create_dir_all(input.parent().unwrap()).unwrap();
+        fs::write(&input, "hello\n").unwrap();
+        let invocation = Invocation::resolve(
+            &builtin.script(),
+            &input,
+            &dir.join("out"),
+            &ScriptSettings::default(),
+        );
+        builtin
+            .execute_builtin(&invocation, &dir.join("manifest"))
+            .unwrap();
+        (input, builtin.output(&invocation))
+    }
+
+    #[test]
+    fn copy_keeps_the_source_and_move_removes_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = run(Builtin::Copy, dir.path());
+        assert!(input.exists());
+        assert_eq!(fs::read_to_string(output).unwrap(), "hello\n");
+
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = run(Builtin::Move, dir.path());
+        assert!(!input.exists());
+        assert_eq!(fs::read_to_string(output).unwrap(), "hello\n");
+    }
+
+    #[test]
+    fn compressed_outputs_decompress_to_the_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, output) = run(Builtin::Gzip, dir.path());
+        assert_eq!(output.file_name().unwrap(), "a.txt.gz");
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(File::open(output).unwrap())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "hello\n");
+
+        let (_, output) = run(Builtin::Zstd, dir.path());
+        let decoded = zstd::stream::decode_all(File::open(output).unwrap()).unwrap();
+        assert_eq!(decoded, b"hello\n");
+    }
+
+    #[test]
+    fn sha256_lines_match_sha256sum() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, output) = run(Builtin::Sha256, dir.path());
+        // `printf 'hello\n' | sha256sum`
+        assert_eq!(
+            fs::read_to_string(output).unwrap(),
+            "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03  a.txt\n"
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! Settings saved between sessions, and how each script is run.
+
+use crate::builtin::Builtin;
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Everything TaskFlow remembers between sessions, stored as JSON in the
+/// platform config directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Script run for items queued without one of their own.
+    pub selected_script: Option<String>,
+    /// Where scripts write their outputs. Required to start a run.
+    pub output_directory: Option<PathBuf>,
+    /// Write a log of each run.
+    pub logging_enabled: bool,
+    /// Scripts offered in the script picker.
+    pub available_scripts: Vec<String>,
+    /// Where the log goes and how it is rotated.
+    pub log: LogSettings,
+    /// Write a run report into the output directory when the queue drains.
+    pub report_on_completion: bool,
+    /// What happens to items once they complete.
+    pub completed_items: CompletedItems,
+    /// Suspend the running script on pause instead of letting it finish.
+    pub suspend_on_pause: bool,
+    /// When a started run may dispatch items.
+    pub schedule: ScheduleSettings,
+    /// Per-script settings, keyed by script path.
+    pub script_settings: HashMap<String, ScriptSettings>,
+    /// Commands run around the run and each item.
+    pub hooks: Hooks,
+    /// The local HTTP API.
+    pub api: ApiSettings,
+    /// Where events such as finished items are delivered.
+    pub event_sinks: Vec<EventSink>,
+    /// Where items are processed. Read when the engine is created.
+    pub workers: WorkerSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            selected_script: None,
+            output_directory: None,
+            logging_enabled: false,
+            available_scripts: vec![],
+            log: LogSettings::default(),
+            report_on_completion: true,
+            completed_items: CompletedItems::default(),
+            suspend_on_pause: false,
+            schedule: ScheduleSettings::default(),
+            script_settings: HashMap::new(),
+            hooks: Hooks::default(),
+            api: ApiSettings::default(),
+            event_sinks: Vec::new(),
+            workers: WorkerSettings::default(),
+        }
+    }
+}
+
+/// What happens to queue items once they complete successfully.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum CompletedItems {
+    /// Remove them from the queue.
+    #[default]
+    Remove,
+    /// Leave them in place, dimmed.
+    KeepInPlace,
+    /// Show them in a separate "Done" section below the queue.
+    MoveToDone,
+}
+
+impl CompletedItems {
+    /// Name shown in the UI.
+    pub fn label(&self) -> &'static str {
+        match self {
+            CompletedItems::Remove => "Remove",
+            CompletedItems::KeepInPlace => "Keep in place",
+            CompletedItems::MoveToDone => "Move to Done",
+        }
+    }
+}
+
+/// How one script is run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptSettings {
+    /// Arguments passed to the script. See `invocation` for placeholders.
+    pub args: Vec<String>,
+    /// Extra environment variables; values may use placeholders.
+    pub env: HashMap<String, String>,
+    /// Directory the script runs in. Inherited from TaskFlow when unset.
+    pub working_dir: Option<String>,
+    /// Expected output file, relative to the output directory, e.g. `{stem}.mp4`.
+    pub output_name: Option<String>,
+    /// What to do when the expected output already exists or another item in
+    /// the run writes it. Only applies when `output_name` is set.
+    pub on_collision: CollisionPolicy,
+    /// Checks run on the outputs after the script exits successfully.
+    pub verify: VerifySettings,
+    /// What to do with the source file once the item has run.
+    pub post_actions: PostActions,
+    /// Kill the script if it runs longer than this. Time spent suspended by
+    /// a pause does not count.
+    pub timeout_secs: Option<u64>,
+    /// Local only; ignored by other executors.
+    pub limits: ResourceLimits,
+    /// Local only; ignored by other executors.
+    pub sandbox: SandboxSettings,
+    /// Where the script runs.
+    pub executor: ExecutorSettings,
+    /// Run the script once for all pending items, handing it their paths
+    /// this way, instead of once per item.
+    pub batch: Option<BatchInput>,
+    /// Most items a batch script is given per run; all pending items when
+    /// unset. Each run is a separate job, so runs can go to different workers.
+    pub batch_size: Option<usize>,
+    /// How the script reports the result, outputs and warnings of each item.
+    pub results: ResultProtocol,
+    /// Exit codes that mean the script succeeded with warnings. Each line
+    /// it wrote to stderr becomes one.
+    pub warning_exit_codes: Vec<i32>,
+}
+
+impl Default for ScriptSettings {
+    fn default() -> Self {
+        Self {
+            args: vec!["{input}".to_string(), "{output_dir}".to_string()],
+            env: HashMap::new(),
+            working_dir: None,
+            output_name: None,
+            on_collision: CollisionPolicy::default(),
+            verify: VerifySettings::default(),
+            post_actions: PostActions::default(),
+            timeout_secs: None,
+            limits: ResourceLimits::default(),
+            sandbox: SandboxSettings::default(),
+            executor: ExecutorSettings::default(),
+            batch: None,
+            batch_size: None,
+            results: ResultProtocol::default(),
+            warning_exit_codes: Vec::new(),
+        }
+    }
+}
+
+/// What to do when an item's expected output already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum CollisionPolicy {
+    /// Run the script and let it replace the file.
+    #[default]
+    Overwrite,
+    /// Leave the file alone and skip the item.
+    Skip,
+    /// Write to `name_1.ext`, `name_2.ext`, ... instead. The script only
+    /// learns the new name through `{output}` or `TASKFLOW_OUTPUT`.
+    AutoRename,
+    /// Fail the item without running the script.
+    Fail,
+}
+
+impl CollisionPolicy {
+    /// Name shown in the UI.
+    pub fn label(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Overwrite => "Overwrite",
+            CollisionPolicy::Skip => "Skip",
+            CollisionPolicy::AutoRename => "Auto-rename",
+            CollisionPolicy::Fail => "Fail",
+        }
+    }
+}
+
+/// Verification of the files a script produced. Nothing is checked unless
+/// `outputs` is set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerifySettings {
+    /// Glob patterns relative to the output directory, e.g. `{stem}*.mp4`.
+    /// Each must match at least one file written during the run.
+    pub outputs: Vec<String>,
+    /// Smallest acceptable size of each matched file.
+    pub min_size_bytes: Option<u64>,
+    /// Command run for each matched file, e.g. `["ffprobe", "-v", "error", "{file}"]`.
+    /// A non-zero exit fails the item.
+    pub command: Vec<String>,
+}
+
+/// Actions applied in order to an item's source file after its script runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostActions {
+    /// Run after the item completes.
+    pub on_success: Vec<PostAction>,
+    /// Run after the item fails.
+    pub on_failure: Vec<PostAction>,
+}
+
+/// Destinations may use the same placeholders as script arguments. Relative
+/// destinations are resolved against the source file's directory, and a
+/// destination ending in `/` keeps the file name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PostAction {
+    /// Move to a destination.
+    Move(String),
+    /// Copy to a destination.
+    Copy(String),
+    /// Rename within the same directory, e.g. `{stem}.done.{ext}`.
+    Rename(String),
+    /// Move to the desktop trash.
+    Trash,
+}
+
+/// Commands run around a processing run and each of its items.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Before the first item of a run is dispatched.
+    pub on_run_start: Option<Hook>,
+    /// After a run finishes or is cancelled.
+    pub on_run_end: Option<Hook>,
+    /// Before each item's script runs.
+    pub before_item: Option<Hook>,
+    /// After an item completes.
+    pub after_item_success: Option<Hook>,
+    /// After an item fails.
+    pub after_item_failure: Option<Hook>,
+}
+
+/// A command run at one point of a run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hook {
+    /// Program and arguments. Item hooks may use the script argument
+    /// placeholders.
+    pub command: Vec<String>,
+    /// Kill the hook if it runs longer than this.
+    pub timeout_secs: Option<u64>,
+    /// Stop the run (`on_run_start`) or fail the item (`before_item`) when
+    /// the hook fails. Other hook failures are only logged.
+    pub abort_on_failure: bool,
+}
+
+/// Limits applied to a script process when it is spawned. Unset limits are
+/// inherited from TaskFlow.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Scheduling niceness, -20 (highest priority) to 19 (lowest).
+    pub nice: Option<i32>,
+    /// I/O scheduling class (Linux only).
+    pub ionice: Option<IoPriority>,
+    /// Maximum memory in bytes; a cgroup v2 `memory.max` when one can be
+    /// created, otherwise `RLIMIT_AS`.
+    pub max_memory_bytes: Option<u64>,
+    /// Maximum CPU time in seconds (`RLIMIT_CPU`).
+    pub cpu_time_secs: Option<u64>,
+    /// Maximum number of open files (`RLIMIT_NOFILE`).
+    pub max_open_files: Option<u64>,
+}
+
+/// An I/O scheduling class and level, as set by `ionice`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum IoPriority {
+    /// Priority level 0 (highest) to 7 (lowest).
+    Realtime(u8),
+    /// Priority level 0 (highest) to 7 (lowest).
+    BestEffort(u8),
+    /// Only when no other process needs the disk.
+    Idle,
+}
+
+/// Filesystem and network confinement for a script (Linux, via Landlock).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxSettings {
+    /// Confine the script to the paths it needs.
+    pub enabled: bool,
+    /// Block TCP connections and listening sockets (Linux 6.7+).
+    pub block_network: bool,
+    /// What to do when the kernel cannot enforce the sandbox.
+    pub fallback: SandboxFallback,
+    /// Extra paths the script may read, beyond system directories.
+    pub read_paths: Vec<PathBuf>,
+    /// Extra paths the script may write, beyond the output directory.
+    pub write_paths: Vec<PathBuf>,
+}
+
+/// What to do when the sandbox can't be enforced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SandboxFallback {
+    /// Fail the item.
+    #[default]
+    Fail,
+    /// Run the script without the sandbox.
+    RunUnsandboxed,
+}
+
+/// How a batch script is given the paths of its inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BatchInput {
+    /// Appended to the arguments.
+    #[default]
+    Args,
+    /// Listed one per line in a file, whose path is appended to the
+    /// arguments and set in `TASKFLOW_FILE_LIST`.
+    ListFile,
+    /// Written to the script's stdin, one per line.
+    Stdin,
+}
+
+impl BatchInput {
+    /// Name shown in the UI.
+    pub fn label(&self) -> &'static str {
+        match self {
+            BatchInput::Args => "Arguments",
+            BatchInput::ListFile => "List file",
+            BatchInput::Stdin => "Stdin",
+        }
+    }
+}
+
+/// How the result of each item a script ran for is told apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ResultProtocol {
+    /// Every item takes the script's exit status.
+    #[default]
+    ExitStatus,
+    /// The script prints a JSON line per item on stdout, e.g.
+    /// `{"file": "/in/a.mov", "status": "ok"}`. Items it doesn't mention
+    /// take the exit status and the outputs TaskFlow found.
+    JsonLines,
+}
+
+/// Where a script runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum ExecutorSettings {
+    /// As a process on this machine.
+    #[default]
+    Local,
+    /// In a throwaway container. The script, input and output directory are
+    /// mounted at the same paths inside it.
+    Container {
+        /// Image the script runs in.
+        image: String,
+        /// Program that runs the container.
+        #[serde(default)]
+        runtime: ContainerRuntime,
+        /// Extra arguments to `run`, e.g. `["--network=none"]`.
+        #[serde(default)]
+        run_args: Vec<String>,
+    },
+    /// On another host over SSH. The script and input are copied to a
+    /// staging directory under `remote_dir`, and outputs copied back.
+    Ssh {
+        /// `host` or `user@host`; anything in `~/.ssh/config` applies.
+        host: String,
+        /// Port to connect to, if not the default.
+        #[serde(default)]
+        port: Option<u16>,
+        /// Private key to authenticate with.
+        #[serde(default)]
+        identity_file: Option<PathBuf>,
+        /// Where staging directories are created on the host.
+        #[serde(default = "default_remote_dir")]
+        remote_dir: String,
+    },
+}
+
+fn default_remote_dir() -> String {
+    "/tmp/taskflow".to_string()
+}
+
+/// The container runtime a container executor uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ContainerRuntime {
+    /// `podman`
+    #[default]
+    Podman,
+    /// `docker`
+    Docker,
+}
+
+impl ContainerRuntime {
+    /// The runtime's command.
+    pub fn program(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Podman => "podman",
+            ContainerRuntime::Docker => "docker",
+        }
+    }
+}
+
+/// The workers items are dispatched to. Each runs one item at a time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkerSettings {
+    /// Process items on this machine as well as on agents.
+    pub local: bool,
+    /// Agents to send items to.
+    pub agents: Vec<AgentSettings>,
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        Self {
+            local: true,
+            agents: Vec::new(),
+        }
+    }
+}
+
+/// A remote `taskflow agent` to send items to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentSettings {
+    /// `host:port` the agent listens on.
+    pub address: String,
+    /// The secret the agent was started with.
+    pub secret: String,
+    /// How the agent gets at inputs and writes outputs.
+    #[serde(default)]
+    pub staging: Staging,
+    /// Items sent to the agent at once.
+    #[serde(default = "default_agent_slots")]
+    pub slots: usize,
+}
+
+fn default_agent_slots() -> usize {
+    1
+}
+
+/// How an agent gets at inputs and writes outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Staging {
+    /// Stream the script and input to the agent and its outputs back.
+    #[default]
+    Upload,
+    /// The script, inputs and output directory are at the same paths on the
+    /// agent, e.g. on a network share.
+    Shared,
+}
+
+/// The local HTTP API, served on 127.0.0.1 only.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiSettings {
+    /// Serve the API while the app is open.
+    pub enabled: bool,
+    /// Port on 127.0.0.1.
+    pub port: u16,
+    /// Bearer token required on every request. Generated when empty.
+    pub token: String,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7878,
+            token: String::new(),
+        }
+    }
+}
+
+/// A destination for events. `events` limits which events are delivered;
+/// empty means all.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EventSink {
+    /// POST each event as JSON. With a `secret`, the body is signed with
+    /// HMAC-SHA256 in the `X-TaskFlow-Signature` header.
+    Webhook {
+        /// Where events are POSTed.
+        url: String,
+        /// Key for the HMAC signature.
+        #[serde(default)]
+        secret: Option<String>,
+        /// Attempts after the first when delivery fails.
+        #[serde(default = "default_webhook_retries")]
+        retries: u32,
+        /// Events to deliver; all when empty.
+        #[serde(default)]
+        events: Vec<String>,
+    },
+    /// Run a command with the event JSON on stdin and in `TASKFLOW_EVENT`.
+    Command {
+        /// Program and arguments.
+        command: Vec<String>,
+        /// Events to deliver; all when empty.
+        #[serde(default)]
+        events: Vec<String>,
+    },
+    /// Append each event as a JSON line to a file or named pipe.
+    File {
+        /// File or named pipe to append to.
+        path: PathBuf,
+        /// Events to deliver; all when empty.
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+fn default_webhook_retries() -> u32 {
+    3
+}
+
+/// When a run is allowed to dispatch items.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleSettings {
+    /// Hold a started run until this time of day.
+    pub start_at: Option<NaiveTime>,
+    /// Only dispatch items while inside one of `windows`.
+    pub windows_enabled: bool,
+    /// Daily windows items may be dispatched in.
+    pub windows: Vec<TimeWindow>,
+}
+
+/// A daily time window; an `end` before `start` spans midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    /// Time of day the window opens.
+    pub start: NaiveTime,
+    /// Time of day the window closes.
+    pub end: NaiveTime,
+}
+
+/// Where log files are written.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum LogLocation {
+    /// A new timestamped log in the output directory each session.
+    #[default]
+    OutputDirectory,
+    /// A rolling `taskflow.log` in the platform state directory
+    /// (`$XDG_STATE_HOME/taskflow/logs` on Linux).
+    StateDirectory,
+    /// A rolling `taskflow.log` in a fixed directory.
+    Custom(PathBuf),
+}
+
+impl LogLocation {
+    /// Name shown in the UI.
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogLocation::OutputDirectory => "Output directory",
+            LogLocation::StateDirectory => "State directory",
+            LogLocation::Custom(_) => "Custom directory",
+        }
+    }
+
+    /// Resolves the directory logs go to, if one is available.
+    pub fn directory(&self, output_dir: Option<&Path>) -> Option<PathBuf> {
+        match self {
+            LogLocation::OutputDirectory => output_dir.map(Path::to_path_buf),
+            LogLocation::StateDirectory => dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .map(|dir| dir.join("taskflow").join("logs")),
+            LogLocation::Custom(dir) => Some(dir.clone()),
+        }
+    }
+}
+
+/// When the rolling log in a fixed directory is rotated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum LogRotation {
+    /// Keep writing to the same log.
+    #[default]
+    Never,
+    /// Rotate once the log reaches `max_bytes`.
+    Size {
+        /// Size in bytes that triggers a rotation.
+        max_bytes: u64,
+    },
+    /// Rotate when the first line of a new day is written.
+    Daily,
+}
+
+/// Where the log is written and how it is rotated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogSettings {
+    /// Where the log is written.
+    pub location: LogLocation,
+    /// When a rolling log is rotated.
+    pub rotation: LogRotation,
+    /// Number of rotated logs to keep; older ones are deleted.
+    pub retention: usize,
+    /// Gzip rotated logs.
+    pub compress: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            location: LogLocation::default(),
+            rotation: LogRotation::default(),
+            retention: 7,
+            compress: false,
+        }
+    }
+}
+
+impl Config {
+    /// Settings for `script`. Built-ins get their default output name when
+    /// none is set, and always run per item.
+    pub fn script_settings(&self, script: &str) -> ScriptSettings {
+        let mut settings = self
+            .script_settings
+            .get(script)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(builtin) = Builtin::parse(script) {
+            settings
+                .output_name
+                .get_or_insert_with(|| builtin.default_output_name().to_string());
+            settings.batch = None;
+        }
+        settings
+    }
+
+    /// Reads the saved config, or the defaults if there is none or it can't
+    /// be read.
+    pub fn load() -> Self {
+        if let Some(path) = Self::config_path()
+            && let Ok(contents) = std::fs::read_to_string(path)
+            && let Ok(config) = serde_json::from_str(&contents)
+        {
+            return config;
+        }
+        Self::default()
+    }
+
+    /// Writes the config to the platform config directory.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = Self::config_path() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let contents = serde_json::to_string_pretty(self)?;
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("taskflow").join("config.json"))
+    }
+}
@@ -0,0 +1,480 @@
+//! Previews what a run would do without running anything.
+
+use crate::builtin::Builtin;
+use crate::config::{Config, ScriptSettings};
+use crate::invocation::{Invocation, OutputPlan, plan_output};
+use crate::outputs;
+use crate::preflight::{self, Finding};
+use crate::queue::{ItemState, Queue, QueueItem};
+use crate::report::escape_csv;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What a run would do for one pending item.
+pub struct PreviewRow {
+    /// The item's input file.
+    pub input: PathBuf,
+    /// None when the command cannot be resolved, e.g. no script is selected.
+    /// Every row of a batch script shares the batch's invocation.
+    pub invocation: Option<Invocation>,
+    /// Problems particular to this item, e.g. an output collision.
+    pub problems: Vec<Finding>,
+}
+
+impl PreviewRow {
+    /// The input's file name, for display.
+    pub fn filename(&self) -> String {
+        self.input
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("Unknown")
+            .to_string()
+    }
+}
+
+/// The commands a run would execute, resolved without running anything.
+pub struct DryRun {
+    /// Problems with the script or output directory, shared by every row.
+    pub problems: Vec<Finding>,
+    /// One row per pending item, in dispatch order.
+    pub rows: Vec<PreviewRow>,
+}
+
+impl DryRun {
+    /// Resolves the invocation for every pending item in `queue`. Items
+    /// with a script of their own are resolved with it.
+    pub fn new(queue: &Queue, config: &Config) -> Self {
+        let selected = config.selected_script.as_deref();
+        let pending: Vec<&QueueItem> = queue
+            .items()
+            .iter()
+            .filter(|item| item.state == ItemState::Pending)
+            .collect();
+
+        let input_bytes = pending
+            .iter()
+            .filter_map(|item| item.path.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let mut scripts: Vec<Option<&str>> = Vec::new();
+        for item in &pending {
+            let script = item.script_or(selected);
+            if !scripts.contains(&script) {
+                scripts.push(script);
+            }
+        }
+        if scripts.is_empty() {
+            scripts.push(selected);
+        }
+        let problems = preflight::check_run(config, &scripts, input_bytes);
+
+        // Outputs are claimed across scripts, as in a run
+        let mut claimed = HashSet::new();
+        let mut rows = Vec::new();
+        for script in scripts {
+            let items: Vec<&QueueItem> = pending
+                .iter()
+                .copied()
+                .filter(|item| item.script_or(selected) == script)
+                .collect();
+            rows.extend(preview(script, &items, config, &mut claimed));
+        }
+
+        Self { problems, rows }
+    }
+
+    /// Number of problems, shared and per row.
+    pub fn problem_count(&self) -> usize {
+        self.problems.len() + self.rows.iter().map(|r| r.problems.len()).sum::<usize>()
+    }
+
+    /// Writes [`to_csv`](Self::to_csv) to `path`.
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+
+    /// One line per row: the command, working directory, environment,
+    /// expected output and problems.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("input,command,working_dir,environment,expected_output,problems\n");
+
+        for row in &self.rows {
+            let (command, working_dir, env, output) = match row.invocation {
+                Some(ref inv) => (
+                    inv.command_line(),
+                    inv.working_dir
+                        .as_ref()
+                        .map(|d| d.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    inv.env
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", k, v))
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                    inv.expected_output
+                        .as_ref()
+                        .map(|o| o.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                ),
+                None => Default::default(),
+            };
+
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                escape_csv(&row.input.to_string_lossy()),
+                escape_csv(&command),
+                escape_csv(&working_dir),
+                escape_csv(&env),
+                escape_csv(&output),
+                escape_csv(
+                    &row.problems
+                        .iter()
+                        .map(|p| p.message.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+            ));
+        }
+
+        csv
+    }
+}
+
+/// The rows for the pending items run with `script`.
+fn preview(
+    script: Option<&str>,
+    pending: &[&QueueItem],
+    config: &Config,
+    claimed: &mut HashSet<PathBuf>,
+) -> Vec<PreviewRow> {
+    let output_dir = config.output_directory.as_deref();
+    let settings = script.map(|s| config.script_settings(s));
+
+    // Batch scripts run once per chunk of `batch_size` items
+    let batch_size = settings
+        .as_ref()
+        .filter(|settings| settings.batch.is_some())
+        .map(|settings| settings.batch_size.unwrap_or(usize::MAX).max(1));
+    let batch_invocations: Vec<Invocation> = match (script, output_dir, &settings, batch_size) {
+        (Some(script), Some(output_dir), Some(settings), Some(size)) => pending
+            .chunks(size)
+            .map(|chunk| {
+                let inputs: Vec<PathBuf> = chunk.iter().map(|item| item.path.clone()).collect();
+                let file_list = outputs::file_list_path(output_dir, chunk[0].id);
+                Invocation::resolve_batch(script, &inputs, output_dir, settings, None, &file_list)
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut rows: Vec<PreviewRow> = pending
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let invocation = match (batch_size, script, output_dir, &settings) {
+                (Some(size), ..) => batch_invocations.get(index / size).cloned(),
+                (None, Some(script), Some(output_dir), Some(settings)) => Some(
+                    Invocation::resolve(script, &item.path, output_dir, settings),
+                ),
+                _ => None,
+            };
+
+            let mut problems = Vec::new();
+            if !item.path.is_file() {
+                problems.push(Finding::error("Input file not found"));
+            } else if let Err(e) = File::open(&item.path) {
+                problems.push(Finding::error(format!("Input file is not readable: {}", e)));
+            }
+            if let Some(ref invocation) = invocation
+                && let Some(ref dir) = invocation.working_dir
+                && !dir.is_dir()
+            {
+                problems.push(Finding::warning(format!(
+                    "Working directory {} does not exist",
+                    dir.display()
+                )));
+            }
+
+            PreviewRow {
+                input: item.path.clone(),
+                invocation,
+                problems,
+            }
+        })
+        .collect();
+
+    // Batch built-ins add every item to the same output
+    let batch = script
+        .and_then(Builtin::parse)
+        .is_some_and(|builtin| builtin.is_batch());
+    if let Some(ref settings) = settings
+        && !batch
+    {
+        match batch_size {
+            // Each run's output is planned once, on its first row
+            Some(size) => {
+                plan_outputs(rows.iter_mut().step_by(size), settings, claimed);
+                for chunk in rows.chunks_mut(size) {
+                    let planned = chunk[0].invocation.clone();
+                    for row in &mut chunk[1..] {
+                        row.invocation = planned.clone();
+                    }
+                }
+            }
+            None => plan_outputs(rows.iter_mut(), settings, claimed),
+        }
+    }
+
+    rows
+}
+
+/// Applies the collision policy to each row's expected output in queue
+/// order, as a run would, and flags what it will do.
+fn plan_outputs<'a>(
+    rows: impl Iterator<Item = &'a mut PreviewRow>,
+    settings: &ScriptSettings,
+    claimed: &mut HashSet<PathBuf>,
+) {
+    for row in rows {
+        let Some(ref mut invocation) = row.invocation else {
+            continue;
+        };
+        let Some(output) = invocation.expected_output.clone() else {
+            continue;
+        };
+
+        match plan_output(&output, settings.on_collision, claimed) {
+            OutputPlan::Write => {
+                if claimed.contains(&output) {
+                    row.problems.push(Finding::error(
+                        "Output is also written by an earlier item and will be overwritten",
+                    ));
+                } else if output.exists() {
+                    row.problems.push(Finding::warning(
+                        "Output already exists and will be overwritten",
+                    ));
+                }
+            }
+            OutputPlan::Skip => {
+                row.problems.push(Finding::warning(
+                    "Output already exists; item will be skipped",
+                ));
+                continue;
+            }
+            OutputPlan::Rename(renamed) => {
+                let renamed = match settings.batch {
+                    Some(_) => Invocation::resolve_batch(
+                        &invocation.script,
+                        &invocation.inputs,
+                        &invocation.output_dir,
+                        settings,
+                        Some(renamed),
+                        &invocation.file_list.clone().unwrap_or_default(),
+                    ),
+                    None => Invocation::resolve_with_output(
+                        &invocation.script,
+                        &invocation.input,
+                        &invocation.output_dir,
+                        settings,
+                        Some(renamed),
+                    ),
+                };
+                *invocation = renamed;
+            }
+            OutputPlan::Fail => {
+                row.problems
+                    .push(Finding::warning("Output already exists; item will fail"));
+                continue;
+            }
+        }
+
+        claimed.extend(invocation.expected_output.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BatchInput, CollisionPolicy};
+    use std::fs;
+
+    const SCRIPT: &str = "/scripts/convert.sh";
+
+    /// A config running SCRIPT into `dir/out`, which is created.
+    fn config(dir: &Path, settings: ScriptSettings) -> Config {
+        fs::create_dir_all(dir.join("out")).unwrap();
+        Config {
+            selected_script: Some(SCRIPT.to_string()),
+            output_directory: Some(dir.join("out")),
+            script_settings: [(SCRIPT.to_string(), settings)].into(),
+            ..Config::default()
+        }
+    }
+
+    fn writing(policy: CollisionPolicy) -> ScriptSettings {
+        ScriptSettings {
+            args: vec!["{input}".to_string(), "{output}".to_string()],
+            output_name: Some("{stem}.out".to_string()),
+            on_collision: policy,
+            ..ScriptSettings::default()
+        }
+    }
+
+    /// A queue of `names` in `dir`; names with a `/` are in subdirectories.
+    fn queue(dir: &Path, names: &[&str]) -> Queue {
+        let mut queue = Queue::new();
+        for name in names {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "input").unwrap();
+            queue.add(path);
+        }
+        queue
+    }
+
+    fn outputs(dry_run: &DryRun) -> Vec<Option<PathBuf>> {
+        dry_run
+            .rows
+            .iter()
+            .map(|row| row.invocation.as_ref()?.expected_output.clone())
+            .collect()
+    }
+
+    fn problems(row: &PreviewRow) -> Vec<&str> {
+        row.problems.iter().map(|p| p.message.as_str()).collect()
+    }
+
+    #[test]
+    fn auto_rename_numbers_past_existing_and_claimed_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), writing(CollisionPolicy::AutoRename));
+        let out = dir.path().join("out");
+        fs::write(out.join("a.out"), "earlier run").unwrap();
+        fs::write(out.join("a_2.out"), "earlier run").unwrap();
+        let queue = queue(dir.path(), &["a.txt", "more/a.txt", "b.txt"]);
+
+        let dry_run = DryRun::new(&queue, &config);
+        assert_eq!(
+            outputs(&dry_run),
+            [
+                Some(out.join("a_1.out")),
+                Some(out.join("a_3.out")),
+                Some(out.join("b.out"))
+            ]
+        );
+        // The renamed output is what the script is given
+        let command = dry_run.rows[0].invocation.as_ref().unwrap().command_line();
+        assert!(command.ends_with("a_1.out"), "{}", command);
+        assert_eq!(
+            dry_run.rows.iter().map(|r| r.problems.len()).sum::<usize>(),
+            0
+        );
+    }
+
+    #[test]
+    fn collisions_are_flagged_by_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        fs::write(out.join("a.out"), "earlier run").unwrap();
+        let queue = queue(dir.path(), &["a.txt", "more/b.txt", "b.txt"]);
+
+        let expected = [
+            (
+                CollisionPolicy::Overwrite,
+                "Output already exists and will be overwritten",
+            ),
+            (
+                CollisionPolicy::Skip,
+                "Output already exists; item will be skipped",
+            ),
+            (
+                CollisionPolicy::Fail,
+                "Output already exists; item will fail",
+            ),
+        ];
+        for (policy, problem) in expected {
+            let dry_run = DryRun::new(&queue, &config(dir.path(), writing(policy)));
+            assert_eq!(problems(&dry_run.rows[0]), [problem]);
+            assert!(dry_run.rows[1].problems.is_empty());
+        }
+
+        // Only overwriting lets two items of the run write the same output
+        let dry_run = DryRun::new(
+            &queue,
+            &config(dir.path(), writing(CollisionPolicy::Overwrite)),
+        );
+        assert_eq!(
+            problems(&dry_run.rows[2]),
+            ["Output is also written by an earlier item and will be overwritten"]
+        );
+        let dry_run = DryRun::new(&queue, &config(dir.path(), writing(CollisionPolicy::Fail)));
+        assert_eq!(
+            problems(&dry_run.rows[2]),
+            ["Output already exists; item will fail"]
+        );
+    }
+
+    #[test]
+    fn batch_rows_share_their_runs_invocation() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = ScriptSettings {
+            args: vec!["{output_dir}".to_string()],
+            batch: Some(BatchInput::Args),
+            batch_size: Some(2),
+            ..ScriptSettings::default()
+        };
+        let config = config(dir.path(), settings);
+        let queue = queue(dir.path(), &["a.txt", "b.txt", "c.txt"]);
+
+        let dry_run = DryRun::new(&queue, &config);
+        let inputs: Vec<Vec<PathBuf>> = dry_run
+            .rows
+            .iter()
+            .map(|row| row.invocation.as_ref().unwrap().inputs.clone())
+            .collect();
+        let path = |name: &str| dir.path().join(name);
+        assert_eq!(
+            inputs,
+            [
+                vec![path("a.txt"), path("b.txt")],
+                vec![path("a.txt"), path("b.txt")],
+                vec![path("c.txt")]
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_has_one_quoted_line_per_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = ScriptSettings {
+            env: [("MODE".to_string(), "fast,small".to_string())].into(),
+            ..writing(CollisionPolicy::Overwrite)
+        };
+        let config = config(dir.path(), settings);
+        let queue = queue(dir.path(), &["a,b.txt"]);
+
+        let csv = DryRun::new(&queue, &config).to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "input,command,working_dir,environment,expected_output,problems"
+        );
+        assert_eq!(lines.len(), 2);
+        let input = dir.path().join("a,b.txt").display().to_string();
+        let output = dir.path().join("out/a,b.out").display().to_string();
+        assert_eq!(
+            lines[1],
+            format!(
+                "\"{input}\",\"{SCRIPT} {input} {output}\",,\"MODE=fast,small; \
+                 TASKFLOW_INPUT={input}; TASKFLOW_OUTPUT={output}; \
+                 TASKFLOW_OUTPUT_DIR={}\",\"{output}\",",
+                dir.path().join("out").display()
+            )
+        );
+    }
+}
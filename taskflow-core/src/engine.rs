@@ -0,0 +1,1327 @@
+use crate::builtin::{self, Builtin};
+use crate::config::{CompletedItems, Config, ScriptSettings};
+use crate::events::{self, Event, EventBus};
+use crate::executor::Executor;
+use crate::history::{History, HistoryEntry, HistoryOutcome};
+use crate::hooks::{self, HookKind, RunSummary};
+use crate::invocation::{Invocation, OutputPlan, plan_output};
+use crate::logger::Logger;
+use crate::outputs;
+use crate::preflight::Preflight;
+use crate::processor::{Processor, ProcessorResult};
+use crate::queue::{ItemId, ItemState, Queue};
+use crate::report::{ReportOutcome, RunReport};
+use crate::scheduler;
+use chrono::{DateTime, Local};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// An item handed to the processor.
+struct ActiveItem {
+    id: ItemId,
+    input: PathBuf,
+    expected_output: Option<PathBuf>,
+    script: String,
+    output_dir: PathBuf,
+    started_at: DateTime<Local>,
+    post_action_error: Option<String>,
+}
+
+/// Runs the queue through the selected script, one item at a time on each
+/// worker.
+///
+/// The engine owns the queue, configuration, log, run report and history.
+/// Scripts run on a background thread; call [`poll`](Self::poll) regularly
+/// to collect their results and dispatch the next item.
+pub struct Engine {
+    queue: Queue,
+    config: Config,
+    logger: Logger,
+    processor: Processor,
+    report: RunReport,
+    history: History,
+    events: EventBus,
+    subscribers: Vec<Sender<Event>>,
+    active_items: Vec<ActiveItem>,
+    // Outputs written or reserved by earlier items of the current run
+    claimed_outputs: HashSet<PathBuf>,
+    // What batch built-ins write to in the current run, by default output
+    batch_outputs: HashMap<PathBuf, PathBuf>,
+    is_processing: bool,
+    is_paused: bool,
+    // Whether the running script was stopped by a pause
+    script_suspended: bool,
+    // Dispatching is held by the schedule
+    waiting_for_schedule: bool,
+    // Dispatching is held until the on_run_start hook finishes
+    awaiting_run_hook: bool,
+    scheduled_start: Option<DateTime<Local>>,
+    status: String,
+}
+
+impl Engine {
+    /// Creates an idle engine with an empty queue. History is loaded from
+    /// the user's data directory.
+    pub fn new(config: Config) -> Self {
+        let processor = Processor::new(&config.workers);
+        Self::with_processor(config, processor, History::load())
+    }
+
+    /// An engine that runs scripts on this machine with `executor` instead
+    /// of the one their settings ask for, e.g. a
+    /// [`MockExecutor`](crate::executor::MockExecutor), and records them in
+    /// `history` instead of the user's.
+    pub fn with_executor(config: Config, executor: Arc<dyn Executor>, history: History) -> Self {
+        let processor = Processor::with_executor(&config.workers, executor);
+        Self::with_processor(config, processor, history)
+    }
+
+    fn with_processor(config: Config, processor: Processor, history: History) -> Self {
+        let logger = Logger::new(
+            config.output_directory.clone(),
+            config.logging_enabled,
+            &config.log,
+        );
+        let events = EventBus::new(&config.event_sinks);
+
+        Self {
+            queue: Queue::new(),
+            config,
+            logger,
+            processor,
+            report: RunReport::new(),
+            history,
+            events,
+            subscribers: Vec::new(),
+            active_items: Vec::new(),
+            claimed_outputs: HashSet::new(),
+            batch_outputs: HashMap::new(),
+            is_processing: false,
+            is_paused: false,
+            script_suspended: false,
+            waiting_for_schedule: false,
+            awaiting_run_hook: false,
+            scheduled_start: None,
+            status: "Ready".to_string(),
+        }
+    }
+
+    /// The settings the engine runs with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Settings changed here apply from the next item. Call
+    /// [`reload_logger`](Self::reload_logger) after changing log settings.
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// The items of the current and next run.
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    /// For reordering, selecting and removing items. Items that are
+    /// processing are locked and ignored by the removal methods.
+    pub fn queue_mut(&mut self) -> &mut Queue {
+        &mut self.queue
+    }
+
+    /// Every item processed, across sessions.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// The report of the current or last run.
+    pub fn report(&self) -> &RunReport {
+        &self.report
+    }
+
+    /// The run log.
+    pub fn logger(&self) -> &Logger {
+        &self.logger
+    }
+
+    /// Applies changed log settings from the configuration.
+    pub fn reload_logger(&mut self) {
+        self.logger.update(
+            self.config.output_directory.clone(),
+            self.config.logging_enabled,
+            &self.config.log,
+        );
+    }
+
+    /// A one-line description of what the engine is doing.
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Replaces the status line, e.g. with the result of a UI action.
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = status.into();
+    }
+
+    /// Whether a run is in progress, including while paused or waiting.
+    pub fn is_processing(&self) -> bool {
+        self.is_processing
+    }
+
+    /// Whether the run is paused. Items already running may still finish.
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// Whether the schedule is holding the run.
+    pub fn is_waiting_for_schedule(&self) -> bool {
+        self.waiting_for_schedule
+    }
+
+    /// The earliest dispatched item whose script is running.
+    pub fn current_item(&self) -> Option<ItemId> {
+        self.active_items.first().map(|active| active.id)
+    }
+
+    /// Every item whose script is running, in the order they were dispatched.
+    pub fn current_items(&self) -> Vec<ItemId> {
+        self.active_items.iter().map(|active| active.id).collect()
+    }
+
+    /// Returns a receiver for every event the engine publishes from now on.
+    /// Dropping the receiver unsubscribes it.
+    pub fn subscribe(&mut self) -> Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// Adds files to the end of the queue and returns their ids.
+    pub fn enqueue(&mut self, paths: Vec<PathBuf>) -> Vec<ItemId> {
+        let ids = paths.into_iter().map(|p| self.queue.add(p)).collect();
+        self.publish_added(ids)
+    }
+
+    /// Adds files that run with `script` instead of the selected script,
+    /// e.g. when re-queued from history, and returns their ids.
+    pub fn enqueue_with_script(&mut self, paths: Vec<PathBuf>, script: &str) -> Vec<ItemId> {
+        let ids = paths
+            .into_iter()
+            .map(|p| self.queue.add_with_script(p, script.to_string()))
+            .collect();
+        self.publish_added(ids)
+    }
+
+    fn publish_added(&mut self, ids: Vec<ItemId>) -> Vec<ItemId> {
+        let items: Vec<Value> = ids
+            .iter()
+            .filter_map(|&id| self.queue.get(id))
+            .map(events::item_json)
+            .collect();
+        self.publish("items_added", json!({ "items": items }));
+        ids
+    }
+
+    /// Checks the configuration and pending items without starting.
+    pub fn preflight(&self) -> Preflight {
+        Preflight::check(&self.queue, &self.config)
+    }
+
+    /// Starts processing the pending items. This doesn't run the
+    /// [`preflight`](Self::preflight) check; callers decide what to do
+    /// with its findings.
+    pub fn start(&mut self) -> Result<(), String> {
+        if self.is_processing {
+            return Err("A run is already in progress".to_string());
+        }
+        let selected = self.config.selected_script.as_deref();
+        if self
+            .queue
+            .items()
+            .iter()
+            .any(|item| item.state == ItemState::Pending && item.script_or(selected).is_none())
+        {
+            return Err(self.fail_start("No script selected"));
+        }
+        if self.config.output_directory.is_none() {
+            return Err(self.fail_start("No output directory selected"));
+        }
+        if self.queue.is_empty() {
+            return Err(self.fail_start("Queue is empty"));
+        }
+        if self.processor.worker_count() == 0 {
+            return Err(self.fail_start("No workers are configured"));
+        }
+
+        self.is_processing = true;
+        self.is_paused = false;
+        self.scheduled_start = self
+            .config
+            .schedule
+            .start_at
+            .map(|time| scheduler::next_occurrence(time, Local::now()));
+        self.report = RunReport::new();
+        self.claimed_outputs.clear();
+        self.batch_outputs.clear();
+        self.publish("run_started", json!({}));
+
+        if self.run_hook(HookKind::RunStart, None) {
+            self.awaiting_run_hook = true;
+            self.status = "Running on_run_start hook".to_string();
+            return Ok(());
+        }
+        self.process_next_item();
+        Ok(())
+    }
+
+    fn fail_start(&mut self, error: &str) -> String {
+        self.status = format!("Error: {}", error);
+        error.to_string()
+    }
+
+    /// Stops dispatching after the current items, or suspends their scripts
+    /// when `suspend_on_pause` is set.
+    pub fn pause(&mut self) {
+        if !self.is_processing || self.is_paused {
+            return;
+        }
+        self.is_paused = true;
+        self.publish("paused", json!({}));
+
+        if self.active_items.is_empty() {
+            self.status = "Paused".to_string();
+        } else if self.config.suspend_on_pause {
+            match self.processor.suspend() {
+                Ok(()) => {
+                    self.script_suspended = true;
+                    self.status = "Paused (script suspended)".to_string();
+                }
+                Err(e) => {
+                    // Some scripts may have stopped; resume them later
+                    self.script_suspended = true;
+                    self.status = format!("{}; pausing after current item", e);
+                }
+            }
+        } else {
+            self.status = "Pausing after current item".to_string();
+        }
+    }
+
+    /// Continues a paused run, resuming a suspended script.
+    pub fn resume(&mut self) {
+        if !self.is_paused {
+            return;
+        }
+        self.is_paused = false;
+        self.resume_suspended_script();
+        self.publish("resumed", json!({}));
+
+        if !self.active_items.is_empty() {
+            self.status = self.processing_status();
+        }
+        self.process_next_item();
+    }
+
+    fn resume_suspended_script(&mut self) {
+        if self.script_suspended {
+            if let Err(e) = self.processor.resume() {
+                self.logger.log(&e);
+            }
+            self.script_suspended = false;
+        }
+    }
+
+    /// Ends the run. The running script is left to finish, but no further
+    /// items are dispatched.
+    pub fn cancel(&mut self) {
+        // A suspended script must be continued so it can finish
+        self.resume_suspended_script();
+        if self.is_processing {
+            self.end_run(true, None);
+        }
+        self.is_processing = false;
+        self.awaiting_run_hook = false;
+        self.is_paused = false;
+        self.waiting_for_schedule = false;
+        self.scheduled_start = None;
+        self.status = "Processing cancelled".to_string();
+    }
+
+    /// Writes the run report into the output directory, returning the
+    /// path of its main file.
+    pub fn write_report(&mut self) -> Option<PathBuf> {
+        let Some(output_dir) = self.config.output_directory.clone() else {
+            self.status = "Error: No output directory selected".to_string();
+            return None;
+        };
+
+        match self.report.write(&output_dir) {
+            Ok(paths) => {
+                let path = paths.into_iter().next()?;
+                self.status = format!("Report written to {}", path.display());
+                Some(path)
+            }
+            Err(e) => {
+                self.status = format!("Error: Failed to write report: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Collects script results, dispatches the next item and releases a
+    /// run held by the schedule. Never blocks.
+    pub fn poll(&mut self) {
+        self.poll_processor();
+
+        // Agents that were unreachable become available again over time
+        if self.is_processing
+            && !self.is_paused
+            && !self.waiting_for_schedule
+            && self.queue.get_next_pending().is_some()
+            && self.processor.has_idle_worker()
+        {
+            self.process_next_item();
+        }
+
+        for failure in self.events.failures() {
+            self.logger
+                .log(&format!("Event delivery failed: {}", failure));
+        }
+
+        self.tick_schedule();
+    }
+
+    /// Hands a run-level hook to the processor. Returns whether one was sent.
+    fn run_hook(&mut self, kind: HookKind, summary: Option<RunSummary>) -> bool {
+        let Some(hook) = self.config.hooks.get(kind).cloned() else {
+            return false;
+        };
+        let script = self.config.selected_script.clone().unwrap_or_default();
+        let output_dir = self.config.output_directory.clone().unwrap_or_default();
+        let env = hooks::run_env(kind, &script, &output_dir, summary.as_ref());
+
+        match self.processor.run_hook(kind, hook, env) {
+            Ok(()) => true,
+            Err(e) => {
+                self.logger.log_hook(kind.name(), None, &Err(e));
+                false
+            }
+        }
+    }
+
+    /// Runs the on_run_end hook for a run that finished or was cancelled.
+    fn end_run(&mut self, cancelled: bool, report: Option<PathBuf>) {
+        let summary = RunSummary {
+            cancelled,
+            succeeded: self.report.success_count(),
+            skipped: self.report.skipped_count(),
+            warnings: self.report.warning_count(),
+            failed: self.report.failure_count(),
+            report,
+        };
+        self.publish(
+            "run_finished",
+            json!({
+                "cancelled": summary.cancelled,
+                "succeeded": summary.succeeded,
+                "with_warnings": summary.warnings,
+                "skipped": summary.skipped,
+                "failed": summary.failed,
+                "report": summary.report,
+            }),
+        );
+        self.run_hook(HookKind::RunEnd, Some(summary));
+    }
+
+    /// Starts dispatching once on_run_start is done, or stops the run if
+    /// the hook failed and is marked abort_on_failure.
+    fn finish_run_start_hook(&mut self, result: Result<(), String>) {
+        if !self.awaiting_run_hook {
+            return;
+        }
+        self.awaiting_run_hook = false;
+
+        if let Err(e) = result {
+            let abort = self
+                .config
+                .hooks
+                .get(HookKind::RunStart)
+                .is_some_and(|hook| hook.abort_on_failure);
+            if abort {
+                self.is_processing = false;
+                self.status = format!("Run aborted: on_run_start hook failed: {}", e);
+                return;
+            }
+        }
+        self.process_next_item();
+    }
+
+    /// Why the schedule is holding dispatch at `now`, if it is.
+    fn schedule_wait(&self, now: DateTime<Local>) -> Option<String> {
+        if let Some(start) = self.scheduled_start
+            && now < start
+        {
+            return Some(format!(
+                "Scheduled to start {}",
+                scheduler::describe(start, now)
+            ));
+        }
+
+        if !scheduler::window_is_open(&self.config.schedule, now) {
+            return Some(
+                match scheduler::next_window_start(&self.config.schedule, now) {
+                    Some(next) => {
+                        format!(
+                            "Waiting: next window starts {}",
+                            scheduler::describe(next, now)
+                        )
+                    }
+                    None => "Waiting for processing window".to_string(),
+                },
+            );
+        }
+
+        None
+    }
+
+    /// Releases a run held by the schedule once it is allowed to continue.
+    fn tick_schedule(&mut self) {
+        if !self.is_processing || !self.waiting_for_schedule {
+            return;
+        }
+
+        match self.schedule_wait(Local::now()) {
+            Some(wait) => {
+                if !self.is_paused {
+                    self.status = wait;
+                }
+            }
+            None => {
+                self.waiting_for_schedule = false;
+                self.scheduled_start = None;
+                self.process_next_item();
+            }
+        }
+    }
+
+    /// Dispatches pending items until every idle worker has one, and ends
+    /// the run once nothing is pending or running.
+    fn process_next_item(&mut self) {
+        while self.dispatch_next() {}
+    }
+
+    /// Hands the next pending item (or batch) to a worker, or settles it
+    /// without running. Returns whether another item may be dispatched.
+    fn dispatch_next(&mut self) -> bool {
+        if !self.is_processing || self.awaiting_run_hook {
+            return false;
+        }
+
+        if self.is_paused {
+            if self.active_items.is_empty() {
+                self.status = "Paused".to_string();
+            }
+            return false;
+        }
+
+        let Some(index) = self.queue.get_next_pending() else {
+            if self.active_items.is_empty() {
+                self.is_processing = false;
+                self.status = "All items processed".to_string();
+                let mut report = None;
+                if self.config.report_on_completion && !self.report.is_empty() {
+                    report = self.write_report();
+                }
+                self.end_run(false, report);
+            }
+            return false;
+        };
+
+        if let Some(wait) = self.schedule_wait(Local::now()) {
+            self.waiting_for_schedule = true;
+            self.status = wait;
+            return false;
+        }
+
+        let Some(script) = self.queue.items()[index]
+            .script_or(self.config.selected_script.as_deref())
+            .map(str::to_string)
+        else {
+            self.finish_unstarted(&[index], ItemState::Error("No script selected".to_string()));
+            return true;
+        };
+        // The config is public, so the directory may be cleared mid-run
+        let Some(output_dir) = self.config.output_directory.clone() else {
+            let error = "No output directory selected".to_string();
+            self.finish_unstarted(&[index], ItemState::Error(error));
+            return true;
+        };
+        let settings = self.config.script_settings(&script);
+
+        let idle = match settings.batch {
+            Some(_) => self.processor.has_idle_batch_worker(),
+            None => self.processor.has_idle_worker(),
+        };
+        if !idle {
+            if self.active_items.is_empty() {
+                self.status = "Waiting for an available worker".to_string();
+            }
+            return false;
+        }
+
+        if settings.batch.is_some() {
+            self.process_batch(script, output_dir, settings);
+            return true;
+        }
+
+        let item = &self.queue.items()[index];
+        let mut invocation = Invocation::resolve(&script, &item.path, &output_dir, &settings);
+
+        // Batch built-ins add every item to the same output, planned once
+        // per run by its first item
+        let batch = Builtin::parse(&script).filter(Builtin::is_batch);
+        let output = match batch {
+            Some(builtin) => Some(builtin.output(&invocation)),
+            None => invocation.expected_output.clone(),
+        };
+        if let Some(output) = output {
+            let resolve = |output| {
+                Invocation::resolve_with_output(
+                    &script,
+                    &item.path,
+                    &output_dir,
+                    &settings,
+                    Some(output),
+                )
+            };
+            if batch.is_some()
+                && let Some(planned) = self.batch_outputs.get(&output)
+            {
+                invocation = resolve(planned.clone());
+            } else {
+                match plan_output(&output, settings.on_collision, &self.claimed_outputs) {
+                    OutputPlan::Write => {}
+                    OutputPlan::Rename(renamed) => invocation = resolve(renamed),
+                    OutputPlan::Skip => {
+                        let reason = format!("{} already exists", output.display());
+                        self.finish_unstarted(&[index], ItemState::Skipped(reason));
+                        return true;
+                    }
+                    OutputPlan::Fail => {
+                        let error = format!("Output {} already exists", output.display());
+                        self.finish_unstarted(&[index], ItemState::Error(error));
+                        return true;
+                    }
+                }
+                if let Some(builtin) = batch {
+                    // A previous run's file is replaced, not added to
+                    let planned = builtin.output(&invocation);
+                    if let Err(e) = builtin::start_batch(&planned) {
+                        let error = format!("Could not replace {}: {}", planned.display(), e);
+                        self.finish_unstarted(&[index], ItemState::Error(error));
+                        return true;
+                    }
+                    self.batch_outputs.insert(output, planned.clone());
+                    self.claimed_outputs.insert(planned);
+                } else {
+                    self.claimed_outputs
+                        .extend(invocation.expected_output.clone());
+                }
+            }
+        }
+
+        self.logger.log_start(&item.filename(), &script);
+
+        let (id, input) = (item.id, item.path.clone());
+        let expected_output = invocation.expected_output.clone();
+        let hooks = self.config.hooks.clone();
+        match self.processor.process(id, invocation, settings, hooks) {
+            Ok(()) => {
+                // Marked now so the next pass doesn't pick it again
+                self.queue.set_state(index, ItemState::Processing);
+                self.active_items.push(ActiveItem {
+                    id,
+                    input,
+                    expected_output,
+                    script,
+                    output_dir,
+                    started_at: Local::now(),
+                    post_action_error: None,
+                });
+            }
+            Err(e) => {
+                let filename = self.queue.items()[index].filename();
+                self.logger.log_error(&filename, &e);
+                self.queue.set_state(index, ItemState::Error(e));
+            }
+        }
+        true
+    }
+
+    /// Hands the next `batch_size` pending items of `script` (all of them by
+    /// default) to a single run of it.
+    fn process_batch(&mut self, script: String, output_dir: PathBuf, settings: ScriptSettings) {
+        let size = settings.batch_size.unwrap_or(usize::MAX).max(1);
+        let selected = self.config.selected_script.as_deref();
+        let (indices, items): (Vec<usize>, Vec<(ItemId, PathBuf)>) = self
+            .queue
+            .items()
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| {
+                item.state == ItemState::Pending && item.script_or(selected) == Some(&script)
+            })
+            .take(size)
+            .map(|(index, item)| (index, (item.id, item.path.clone())))
+            .unzip();
+        let Some(&(batch, _)) = items.first() else {
+            return;
+        };
+
+        let inputs: Vec<PathBuf> = items.iter().map(|(_, path)| path.clone()).collect();
+        let file_list = outputs::file_list_path(&output_dir, batch);
+        let resolve = |output| {
+            Invocation::resolve_batch(&script, &inputs, &output_dir, &settings, output, &file_list)
+        };
+        let mut invocation = resolve(None);
+
+        // The batch has one output, so it is planned once for every item
+        if let Some(output) = invocation.expected_output.clone() {
+            match plan_output(&output, settings.on_collision, &self.claimed_outputs) {
+                OutputPlan::Write => {}
+                OutputPlan::Rename(renamed) => invocation = resolve(Some(renamed)),
+                OutputPlan::Skip => {
+                    let reason = format!("{} already exists", output.display());
+                    self.finish_unstarted(&indices, ItemState::Skipped(reason));
+                    return;
+                }
+                OutputPlan::Fail => {
+                    let error = format!("Output {} already exists", output.display());
+                    self.finish_unstarted(&indices, ItemState::Error(error));
+                    return;
+                }
+            }
+            self.claimed_outputs
+                .extend(invocation.expected_output.clone());
+        }
+
+        // Hooks and post-actions still see each item on its own
+        let invocations = items
+            .iter()
+            .map(|(id, path)| {
+                (
+                    *id,
+                    Invocation::resolve(&script, path, &output_dir, &settings),
+                )
+            })
+            .collect();
+        for (id, _) in &items {
+            let filename = self.item_filename(*id);
+            self.logger.log_start(&filename, &script);
+        }
+
+        let expected_output = invocation.expected_output.clone();
+        let hooks = self.config.hooks.clone();
+        match self
+            .processor
+            .process_batch(invocations, invocation, settings, hooks)
+        {
+            Ok(()) => {
+                for (id, input) in items {
+                    self.queue.set_state_by_id(id, ItemState::Processing);
+                    self.queue.set_batch_by_id(id, batch);
+                    self.active_items.push(ActiveItem {
+                        id,
+                        input,
+                        expected_output: expected_output.clone(),
+                        script: script.clone(),
+                        output_dir: output_dir.clone(),
+                        started_at: Local::now(),
+                        post_action_error: None,
+                    });
+                }
+            }
+            Err(e) => {
+                for index in indices {
+                    let filename = self.queue.items()[index].filename();
+                    self.logger.log_error(&filename, &e);
+                    self.queue.set_state(index, ItemState::Error(e.clone()));
+                }
+            }
+        }
+    }
+
+    /// Settles items without running their script.
+    fn finish_unstarted(&mut self, indices: &[usize], state: ItemState) {
+        for &index in indices {
+            let item = &self.queue.items()[index];
+            let (path, filename) = (item.path.clone(), item.filename());
+
+            let outcome = match state {
+                ItemState::Skipped(ref reason) => {
+                    self.logger.log_skipped(&filename, reason);
+                    ReportOutcome::Skipped(reason.clone())
+                }
+                ItemState::Error(ref error) => {
+                    self.logger.log_error(&filename, error);
+                    ReportOutcome::Failed(error.clone())
+                }
+                _ => return,
+            };
+            self.report.record_unstarted(&path, outcome);
+
+            self.queue.set_state(index, state.clone());
+            self.publish_item(self.queue.items()[index].id);
+        }
+        if self.config.completed_items == CompletedItems::Remove {
+            self.queue.remove_completed();
+        }
+    }
+
+    fn record_history(&mut self, id: ItemId, outcome: HistoryOutcome, outputs: Vec<PathBuf>) {
+        let Some(active) = self.take_active(id) else {
+            return;
+        };
+
+        let entry = HistoryEntry {
+            input: active.input,
+            script: active.script,
+            outcome,
+            started_at: active.started_at,
+            finished_at: Local::now(),
+            output_dir: active.output_dir,
+            outputs,
+            post_action_error: active.post_action_error,
+        };
+        if let Err(e) = self.history.append(entry) {
+            self.logger.log(&format!("Failed to write history: {}", e));
+        }
+    }
+
+    fn take_active(&mut self, id: ItemId) -> Option<ActiveItem> {
+        let index = self
+            .active_items
+            .iter()
+            .position(|active| active.id == id)?;
+        Some(self.active_items.remove(index))
+    }
+
+    /// E.g. "Processing: a.mov" or "Processing: a.mov and 2 more".
+    fn processing_status(&self) -> String {
+        let Some(first) = self.current_item() else {
+            return "Processing".to_string();
+        };
+        let filename = self.item_filename(first);
+        match self.active_items.len() {
+            1 => format!("Processing: {}", filename),
+            count => format!("Processing: {} and {} more", filename, count - 1),
+        }
+    }
+
+    /// Sends an event to subscribers and the configured sinks.
+    fn publish(&mut self, name: &str, data: Value) {
+        let event = Event::new(name, data);
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        self.events.publish(event);
+    }
+
+    /// Publishes an item's current state, e.g. `item_completed`.
+    fn publish_item(&mut self, id: ItemId) {
+        let Some(item) = self.queue.get(id) else {
+            return;
+        };
+        let name = match item.state {
+            ItemState::Pending => "item_pending",
+            ItemState::Processing => "item_started",
+            ItemState::Completed | ItemState::CompletedWithWarnings => "item_completed",
+            ItemState::Skipped(_) => "item_skipped",
+            ItemState::Error(_) => "item_failed",
+        };
+        let data = events::item_json(item);
+        self.publish(name, data);
+    }
+
+    fn item_filename(&self, id: ItemId) -> String {
+        self.queue
+            .get(id)
+            .map(|item| item.filename())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    fn poll_processor(&mut self) {
+        while let Some(result) = self.processor.try_recv_result() {
+            self.report.record(&result, &self.queue);
+
+            match result {
+                ProcessorResult::Started(id) => {
+                    self.queue.set_warnings_by_id(id, Vec::new());
+                    self.queue.set_state_by_id(id, ItemState::Processing);
+                    self.publish_item(id);
+                    self.status = self.processing_status();
+                }
+                ProcessorResult::Success(id, outputs) => {
+                    let filename = self.item_filename(id);
+                    self.logger.log_success(&filename);
+                    let warnings = self
+                        .queue
+                        .get(id)
+                        .map(|item| item.warnings.clone())
+                        .unwrap_or_default();
+                    let (outcome, state) = if warnings.is_empty() {
+                        (HistoryOutcome::Success, ItemState::Completed)
+                    } else {
+                        (
+                            HistoryOutcome::SucceededWithWarnings(warnings),
+                            ItemState::CompletedWithWarnings,
+                        )
+                    };
+                    self.record_history(id, outcome, outputs.clone());
+                    self.queue.set_outputs_by_id(id, outputs);
+                    self.queue.set_state_by_id(id, state);
+                    self.publish_item(id);
+                    if self.config.completed_items == CompletedItems::Remove {
+                        self.queue.remove_completed();
+                    }
+                    if !self.active_items.is_empty() {
+                        self.status = self.processing_status();
+                    }
+                    self.process_next_item();
+                }
+                ProcessorResult::Error(id, error) => {
+                    let filename = self.item_filename(id);
+                    self.logger.log_error(&filename, &error);
+                    self.record_history(id, HistoryOutcome::Failed(error.clone()), Vec::new());
+                    self.queue.set_state_by_id(id, ItemState::Error(error));
+                    self.publish_item(id);
+                    if !self.active_items.is_empty() {
+                        self.status = self.processing_status();
+                    }
+                    self.process_next_item();
+                }
+                ProcessorResult::Requeued(id, reason) => {
+                    let filename = self.item_filename(id);
+                    self.logger
+                        .log(&format!("{}: returned to the queue: {}", filename, reason));
+                    // Its output is no longer spoken for until it is dispatched again
+                    if let Some(output) = self.take_active(id).and_then(|a| a.expected_output) {
+                        self.claimed_outputs.remove(&output);
+                    }
+                    self.queue.set_state_by_id(id, ItemState::Pending);
+                    self.publish_item(id);
+                    self.process_next_item();
+                }
+                ProcessorResult::PostActionFailed(id, error) => {
+                    let filename = self.item_filename(id);
+                    self.logger.log_post_action_error(&filename, &error);
+                    self.status = format!("Post-action failed for {}: {}", filename, error);
+                    if let Some(active) =
+                        self.active_items.iter_mut().find(|active| active.id == id)
+                    {
+                        active.post_action_error = Some(error.clone());
+                    }
+                    self.publish("post_action_failed", json!({ "id": id, "error": error }));
+                    self.queue.set_post_action_error_by_id(id, error);
+                }
+                ProcessorResult::Warnings(id, warnings) => {
+                    let filename = self.item_filename(id);
+                    for warning in &warnings {
+                        self.logger.log_warning(&filename, warning);
+                    }
+                    self.queue.set_warnings_by_id(id, warnings);
+                }
+                ProcessorResult::HookFinished { kind, item, result } => {
+                    let subject = item.map(|id| self.item_filename(id));
+                    self.logger
+                        .log_hook(kind.name(), subject.as_deref(), &result);
+                    self.publish(
+                        "hook_finished",
+                        json!({
+                            "hook": kind.name(),
+                            "id": item,
+                            "error": result.as_ref().err(),
+                        }),
+                    );
+                    if kind == HookKind::RunStart {
+                        self.finish_run_start_hook(result);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BatchInput, CollisionPolicy};
+    use crate::executor::MockExecutor;
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const SCRIPT: &str = "/scripts/convert.sh";
+
+    struct Fixture {
+        dir: tempfile::TempDir,
+        mock: MockExecutor,
+        engine: Engine,
+    }
+
+    impl Fixture {
+        /// An engine whose items write `{stem}.out` with `policy`, keeping
+        /// its history, inputs and outputs in a temporary directory.
+        fn new(policy: CollisionPolicy) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            fs::create_dir(dir.path().join("out")).unwrap();
+            let settings = ScriptSettings {
+                output_name: Some("{stem}.out".to_string()),
+                on_collision: policy,
+                ..ScriptSettings::default()
+            };
+            let config = Config {
+                selected_script: Some(SCRIPT.to_string()),
+                output_directory: Some(dir.path().join("out")),
+                completed_items: CompletedItems::KeepInPlace,
+                report_on_completion: false,
+                script_settings: [(SCRIPT.to_string(), settings)].into(),
+                ..Config::default()
+            };
+            let mock = MockExecutor::new();
+            let history = History::open(dir.path().join("history.jsonl"));
+            let engine = Engine::with_executor(config, Arc::new(mock.clone()), history);
+            Self { dir, mock, engine }
+        }
+
+        /// Creates inputs at `names`, relative to the fixture, and queues them.
+        fn enqueue(&mut self, names: &[&str]) -> Vec<PathBuf> {
+            let paths: Vec<PathBuf> = names
+                .iter()
+                .map(|name| self.dir.path().join(name))
+                .collect();
+            for path in &paths {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "input").unwrap();
+            }
+            self.engine.enqueue(paths.clone());
+            paths
+        }
+
+        fn out(&self, name: &str) -> PathBuf {
+            self.dir.path().join("out").join(name)
+        }
+
+        fn poll_until(&mut self, done: impl Fn(&Engine) -> bool) {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !done(&self.engine) {
+                assert!(
+                    Instant::now() < deadline,
+                    "timed out: {}",
+                    self.engine.status()
+                );
+                self.engine.poll();
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        fn run(&mut self) {
+            self.engine.start().unwrap();
+            self.poll_until(|engine| !engine.is_processing());
+        }
+
+        fn states(&self) -> Vec<ItemState> {
+            let items = self.engine.queue().items();
+            items.iter().map(|item| item.state.clone()).collect()
+        }
+
+        fn calls_for(&self, input: &Path) -> usize {
+            let calls = self.mock.calls();
+            calls.iter().filter(|call| call.input == input).count()
+        }
+    }
+
+    #[test]
+    fn successful_items_complete_with_their_outputs() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        fixture.enqueue(&["in/a.txt", "in/b.txt"]);
+
+        fixture.run();
+
+        assert_eq!(
+            fixture.states(),
+            [ItemState::Completed, ItemState::Completed]
+        );
+        let outputs = &fixture.engine.queue().items()[1].outputs;
+        assert_eq!(outputs, &[fixture.out("b.out")]);
+        assert_eq!(fixture.engine.report().success_count(), 2);
+
+        // Recorded in the fixture's history, not the user's
+        assert_eq!(fixture.engine.history().len(), 2);
+        let history = fs::read_to_string(fixture.dir.path().join("history.jsonl")).unwrap();
+        assert_eq!(history.lines().count(), 2);
+    }
+
+    #[test]
+    fn failed_items_keep_the_error() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        let inputs = fixture.enqueue(&["in/a.txt", "in/b.txt"]);
+        fixture.mock.fail(&inputs[0], "unsupported codec");
+
+        fixture.run();
+
+        let error = "Script failed: unsupported codec".to_string();
+        assert_eq!(
+            fixture.states(),
+            [ItemState::Error(error.clone()), ItemState::Completed]
+        );
+        let results = fixture.engine.history().search("a.txt", 10);
+        assert_eq!(results.entries[0].outcome, HistoryOutcome::Failed(error));
+    }
+
+    #[test]
+    fn warnings_are_kept_on_completed_items() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        let inputs = fixture.enqueue(&["in/a.txt"]);
+        fixture
+            .mock
+            .warn(&inputs[0], vec!["colour profile dropped".to_string()]);
+
+        fixture.run();
+
+        let item = &fixture.engine.queue().items()[0];
+        assert_eq!(item.state, ItemState::CompletedWithWarnings);
+        assert_eq!(item.warnings, ["colour profile dropped"]);
+        assert_eq!(fixture.engine.report().warning_count(), 1);
+        let results = fixture.engine.history().search("a.txt", 10);
+        assert_eq!(
+            results.entries[0].outcome,
+            HistoryOutcome::SucceededWithWarnings(vec!["colour profile dropped".to_string()])
+        );
+    }
+
+    #[test]
+    fn removing_completed_items_keeps_those_with_warnings() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        fixture.engine.config_mut().completed_items = CompletedItems::Remove;
+        let inputs = fixture.enqueue(&["in/a.txt", "in/b.txt"]);
+        fixture.mock.warn(&inputs[1], vec!["clipped".to_string()]);
+
+        fixture.run();
+
+        let items = fixture.engine.queue().items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, inputs[1]);
+        assert_eq!(items[0].state, ItemState::CompletedWithWarnings);
+    }
+
+    #[test]
+    fn items_lost_with_their_worker_are_requeued() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        let inputs = fixture.enqueue(&["in/a.txt"]);
+        fixture.mock.lose(&inputs[0]);
+
+        fixture.engine.start().unwrap();
+        let mock = fixture.mock.clone();
+        fixture.poll_until(|engine| {
+            !mock.calls().is_empty() && engine.queue().items()[0].state == ItemState::Pending
+        });
+        assert!(fixture.engine.is_processing());
+
+        fixture.mock.recover();
+        fixture.poll_until(|engine| !engine.is_processing());
+
+        assert_eq!(fixture.states(), [ItemState::Completed]);
+        assert_eq!(fixture.calls_for(&inputs[0]), 2);
+        assert_eq!(fixture.engine.history().len(), 1);
+    }
+
+    #[test]
+    fn existing_outputs_are_skipped_without_running() {
+        let mut fixture = Fixture::new(CollisionPolicy::Skip);
+        fs::write(fixture.out("a.out"), "earlier").unwrap();
+        let inputs = fixture.enqueue(&["in/a.txt", "in/b.txt"]);
+
+        fixture.run();
+
+        let reason = format!("{} already exists", fixture.out("a.out").display());
+        assert_eq!(
+            fixture.states(),
+            [ItemState::Skipped(reason), ItemState::Completed]
+        );
+        assert_eq!(fixture.calls_for(&inputs[0]), 0);
+        assert_eq!(fs::read_to_string(fixture.out("a.out")).unwrap(), "earlier");
+    }
+
+    #[test]
+    fn colliding_outputs_in_a_run_are_renamed() {
+        let mut fixture = Fixture::new(CollisionPolicy::AutoRename);
+        fixture.enqueue(&["one/a.txt", "two/a.txt"]);
+
+        fixture.run();
+
+        let calls = fixture.mock.calls();
+        let outputs: Vec<_> = calls
+            .iter()
+            .map(|call| call.expected_output.clone())
+            .collect();
+        assert_eq!(
+            outputs,
+            [Some(fixture.out("a.out")), Some(fixture.out("a_1.out"))]
+        );
+        assert_eq!(
+            fixture.states(),
+            [ItemState::Completed, ItemState::Completed]
+        );
+    }
+
+    #[test]
+    fn cancelling_lets_the_running_item_finish_and_dispatches_no_more() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        fixture.enqueue(&["in/a.txt", "in/b.txt", "in/c.txt"]);
+        fixture.mock.delay(Duration::from_millis(200));
+
+        fixture.engine.start().unwrap();
+        fixture.engine.cancel();
+        assert!(!fixture.engine.is_processing());
+        fixture.poll_until(|engine| engine.current_item().is_none());
+
+        assert_eq!(
+            fixture.states(),
+            [ItemState::Completed, ItemState::Pending, ItemState::Pending]
+        );
+        assert_eq!(fixture.mock.calls().len(), 1);
+    }
+
+    #[test]
+    fn batch_builtins_start_a_fresh_output_each_run() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        fixture.engine.config_mut().selected_script = Some(Builtin::Zip.script());
+        let entries = |fixture: &Fixture| {
+            let archive = fs::File::open(fixture.out("batch.zip")).unwrap();
+            let archive = zip::ZipArchive::new(archive).unwrap();
+            let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+            names.sort();
+            names
+        };
+
+        // Inputs of the same name from different directories are both kept
+        fixture.enqueue(&["in/a.txt", "more/a.txt"]);
+        fixture.run();
+        assert_eq!(entries(&fixture), ["a.txt", "a_1.txt"]);
+
+        fixture.enqueue(&["in/b.txt"]);
+        fixture.run();
+        assert_eq!(
+            fixture.states(),
+            [
+                ItemState::Completed,
+                ItemState::Completed,
+                ItemState::Completed
+            ]
+        );
+        assert_eq!(entries(&fixture), ["b.txt"]);
+    }
+
+    #[test]
+    fn clearing_the_output_directory_mid_run_fails_the_remaining_items() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        fixture.enqueue(&["in/a.txt", "in/b.txt"]);
+        fixture.mock.delay(Duration::from_millis(100));
+
+        fixture.engine.start().unwrap();
+        fixture.engine.config_mut().output_directory = None;
+        fixture.poll_until(|engine| !engine.is_processing());
+
+        let error = "No output directory selected".to_string();
+        assert_eq!(
+            fixture.states(),
+            [ItemState::Completed, ItemState::Error(error)]
+        );
+    }
+
+    #[test]
+    fn batch_builtins_plan_their_output_with_the_collision_policy() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        let script = Builtin::Sha256.script();
+        let set_policy = |fixture: &mut Fixture, policy| {
+            let config = fixture.engine.config_mut();
+            config.selected_script = Some(script.clone());
+            let settings = ScriptSettings {
+                on_collision: policy,
+                ..ScriptSettings::default()
+            };
+            config.script_settings.insert(script.clone(), settings);
+        };
+        let previous = "previous run\n";
+        fs::write(fixture.out("SHA256SUMS"), previous).unwrap();
+
+        set_policy(&mut fixture, CollisionPolicy::Skip);
+        fixture.enqueue(&["in/a.txt", "in/b.txt"]);
+        fixture.run();
+        assert!(matches!(
+            fixture.states()[..],
+            [ItemState::Skipped(_), ItemState::Skipped(_)]
+        ));
+        assert_eq!(
+            fs::read_to_string(fixture.out("SHA256SUMS")).unwrap(),
+            previous
+        );
+
+        set_policy(&mut fixture, CollisionPolicy::AutoRename);
+        fixture.enqueue(&["in/c.txt", "in/d.txt"]);
+        fixture.run();
+        assert_eq!(
+            fs::read_to_string(fixture.out("SHA256SUMS")).unwrap(),
+            previous
+        );
+        let renamed = fs::read_to_string(fixture.out("SHA256SUMS_1")).unwrap();
+        let names: Vec<&str> = renamed.lines().map(|line| &line[66..]).collect();
+        assert_eq!(names, ["c.txt", "d.txt"]);
+    }
+
+    #[test]
+    fn batch_runs_of_batch_size_settle_their_own_items() {
+        let mut fixture = Fixture::new(CollisionPolicy::Overwrite);
+        let config = fixture.engine.config_mut();
+        let settings = config.script_settings.get_mut(SCRIPT).unwrap();
+        settings.output_name = None;
+        settings.batch = Some(BatchInput::Args);
+        settings.batch_size = Some(2);
+        let inputs =
+            fixture.enqueue(&["in/a.txt", "more/a.txt", "in/b.txt", "in/c.txt", "in/d.txt"]);
+        let clipped = format!("{}: clipped", inputs[1].display());
+        fixture
+            .mock
+            .writes(&inputs[0], &["a.out", "sheet.pdf"])
+            .warn(&inputs[0], vec![clipped.clone(), "slow disk".to_string()]);
+        fixture.mock.fail(&inputs[2], "unsupported codec");
+        fixture.mock.writes(&inputs[4], &["d.out"]);
+
+        fixture.run();
+
+        let calls = fixture.mock.calls();
+        let runs: Vec<&[PathBuf]> = calls.iter().map(|call| call.inputs.as_slice()).collect();
+        assert_eq!(runs, [&inputs[..2], &inputs[2..4], &inputs[4..]]);
+        let error = ItemState::Error("Script failed: unsupported codec".to_string());
+        assert_eq!(
+            fixture.states(),
+            [
+                ItemState::CompletedWithWarnings,
+                ItemState::CompletedWithWarnings,
+                error.clone(),
+                error,
+                ItemState::Completed
+            ]
+        );
+
+        // Both a.txt inputs could have written a.out, so neither claims it
+        let items = fixture.engine.queue().items();
+        assert!(items[0].outputs.is_empty() && items[1].outputs.is_empty());
+        assert_eq!(items[4].outputs, [fixture.out("d.out")]);
+        assert_eq!(items[0].warnings, ["slow disk"]);
+        assert_eq!(items[1].warnings, [clipped.as_str(), "slow disk"]);
+    }
+}
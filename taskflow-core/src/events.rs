@@ -0,0 +1,429 @@
+//! Events published as a run progresses, and their delivery to sinks.
+
+use crate::config::EventSink;
+use crate::queue::{ItemState, QueueItem};
+use chrono::{DateTime, Local};
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Something that happened during a run, e.g. `item_completed`.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// What happened, e.g. `item_completed`.
+    pub name: String,
+    /// Details, such as the item as [`item_json`] describes it.
+    pub data: Value,
+    /// When it happened.
+    pub time: DateTime<Local>,
+}
+
+impl Event {
+    /// An event that happened now.
+    pub fn new(name: &str, data: Value) -> Self {
+        Self {
+            name: name.to_string(),
+            data,
+            time: Local::now(),
+        }
+    }
+
+    /// The payload delivered to sinks.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "event": self.name,
+            "time": self.time.to_rfc3339(),
+            "data": self.data,
+        })
+    }
+}
+
+/// How a queue item appears in events and to API clients.
+pub fn item_json(item: &QueueItem) -> Value {
+    let (state, reason) = match &item.state {
+        ItemState::Pending => ("pending", None),
+        ItemState::Processing => ("processing", None),
+        ItemState::Completed => ("completed", None),
+        ItemState::CompletedWithWarnings => ("completed_with_warnings", None),
+        ItemState::Skipped(reason) => ("skipped", Some(reason)),
+        ItemState::Error(error) => ("failed", Some(error)),
+    };
+    json!({
+        "id": item.id,
+        "path": item.path,
+        "state": state,
+        "reason": reason,
+        "outputs": item.outputs,
+        "post_action_error": item.post_action_error,
+        "warnings": item.warnings,
+        "script": item.script,
+        "batch": item.batch,
+    })
+}
+
+/// Delivers events to the configured sinks, each on its own thread so a
+/// slow webhook doesn't hold up the others.
+pub(crate) struct EventBus {
+    sinks: Vec<Sender<Arc<Event>>>,
+    failures: Receiver<String>,
+}
+
+impl EventBus {
+    pub(crate) fn new(sinks: &[EventSink]) -> Self {
+        let (failure_tx, failures) = mpsc::channel();
+
+        let sinks = sinks
+            .iter()
+            .map(|sink| {
+                let (tx, rx) = mpsc::channel::<Arc<Event>>();
+                let sink = sink.clone();
+                let failure_tx = failure_tx.clone();
+                thread::spawn(move || {
+                    for event in rx {
+                        if !accepts(&sink, &event.name) {
+                            continue;
+                        }
+                        if let Err(e) = deliver(&sink, &event) {
+                            let _ = failure_tx.send(format!("{}: {}", describe(&sink), e));
+                        }
+                    }
+                });
+                tx
+            })
+            .collect();
+
+        Self { sinks, failures }
+    }
+
+    pub(crate) fn publish(&self, event: Event) {
+        let event = Arc::new(event);
+        for sink in &self.sinks {
+            let _ = sink.send(Arc::clone(&event));
+        }
+    }
+
+    /// Delivery errors since the last call.
+    pub(crate) fn failures(&self) -> Vec<String> {
+        self.failures.try_iter().collect()
+    }
+}
+
+fn accepts(sink: &EventSink, name: &str) -> bool {
+    let events = match sink {
+        EventSink::Webhook { events, .. }
+        | EventSink::Command { events, .. }
+        | EventSink::File { events, .. } => events,
+    };
+    events.is_empty() || events.iter().any(|event| event == name)
+}
+
+fn describe(sink: &EventSink) -> String {
+    match sink {
+        EventSink::Webhook { url, .. } => format!("Webhook {}", url),
+        EventSink::Command { command, .. } => {
+            format!(
+                "Event command '{}'",
+                command.first().map_or("", String::as_str)
+            )
+        }
+        EventSink::File { path, .. } => format!("Event file {}", path.display()),
+    }
+}
+
+fn deliver(sink: &EventSink, event: &Event) -> Result<(), String> {
+    let body = event.to_json().to_string();
+    match sink {
+        EventSink::Webhook {
+            url,
+            secret,
+            retries,
+            ..
+        } => post(url, secret.as_deref(), *retries, &event.name, &body),
+        EventSink::Command { command, .. } => run_command(command, &event.name, &body),
+        EventSink::File { path, .. } => append_line(path, &body),
+    }
+}
+
+/// POSTs `body`, retrying with exponential backoff on network errors, 5xx
+/// and 429 responses.
+fn post(
+    url: &str,
+    secret: Option<&str>,
+    retries: u32,
+    name: &str,
+    body: &str,
+) -> Result<(), String> {
+    let mut request = ureq::post(url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .set("X-TaskFlow-Event", name);
+    if let Some(secret) = secret {
+        request = request.set(
+            "X-TaskFlow-Signature",
+            &format!("sha256={}", sign(secret, body)),
+        );
+    }
+
+    let mut backoff = Duration::from_secs(1);
+    let mut last_error = String::new();
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+
+        match request.clone().send_string(body) {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(code, _)) if code < 500 && code != 429 => {
+                return Err(format!("server answered {}", code));
+            }
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+
+    Err(format!(
+        "{} (gave up after {} attempts)",
+        last_error,
+        retries + 1
+    ))
+}
+
+/// Hex HMAC-SHA256 of `body`.
+pub(crate) fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn run_command(command: &[String], name: &str, body: &str) -> Result<(), String> {
+    let Some(program) = command.first() else {
+        return Err("no command set".to_string());
+    };
+
+    let mut child = Command::new(program)
+        .args(&command[1..])
+        .env("TASKFLOW_EVENT", body)
+        .env("TASKFLOW_EVENT_NAME", name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run '{}': {}", program, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores stdin may exit before reading it
+        let _ = writeln!(stdin, "{}", body);
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(match stderr.trim() {
+        "" => output.status.to_string(),
+        message => message.to_string(),
+    })
+}
+
+fn append_line(path: &Path, body: &str) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        // Fail instead of blocking when a named pipe has no reader
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NONBLOCK);
+    }
+
+    let mut file = options.open(path).map_err(|e| e.to_string())?;
+    writeln!(file, "{}", body).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    struct Received {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Answers one request per connection with each of `statuses` in turn,
+    /// and returns its URL and the requests it saw.
+    fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            statuses
+                .into_iter()
+                .map(|status| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut headers = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((field, value)) = line.split_once(':') {
+                            headers.push((field.to_string(), value.trim().to_string()));
+                        }
+                    }
+                    let mut received = Received {
+                        headers,
+                        body: String::new(),
+                    };
+                    let length = received
+                        .header("Content-Length")
+                        .map_or(0, |value| value.parse().unwrap());
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    received.body = String::from_utf8(body).unwrap();
+
+                    write!(
+                        &stream,
+                        "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    )
+                    .unwrap();
+                    received
+                })
+                .collect()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn webhooks_are_signed_and_retried_after_a_server_error() {
+        let (url, server) = serve(vec![500, 200]);
+        let sink = EventSink::Webhook {
+            url,
+            secret: Some("hunter2".to_string()),
+            retries: 1,
+            events: Vec::new(),
+        };
+        let event = Event::new("item_completed", json!({ "id": 7 }));
+
+        assert_eq!(deliver(&sink, &event), Ok(()));
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in &requests {
+            let body: Value = serde_json::from_str(&request.body).unwrap();
+            assert_eq!(body["event"], "item_completed");
+            assert_eq!(body["data"]["id"], 7);
+            assert_eq!(request.header("X-TaskFlow-Event"), Some("item_completed"));
+            let signature = format!("sha256={}", sign("hunter2", &request.body));
+            assert_eq!(
+                request.header("X-TaskFlow-Signature"),
+                Some(signature.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn webhooks_give_up_on_client_errors() {
+        let (url, server) = serve(vec![404]);
+        let sink = EventSink::Webhook {
+            url,
+            secret: None,
+            retries: 3,
+            events: Vec::new(),
+        };
+
+        let result = deliver(&sink, &Event::new("run_started", json!({})));
+
+        assert_eq!(result, Err("server answered 404".to_string()));
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].header("X-TaskFlow-Signature"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_get_the_event_on_stdin_and_in_the_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let (stdin, name) = (dir.path().join("stdin"), dir.path().join("name"));
+        let script = format!(
+            "cat > '{}' && printf %s \"$TASKFLOW_EVENT_NAME\" > '{}'",
+            stdin.display(),
+            name.display()
+        );
+        let sink = EventSink::Command {
+            command: vec!["sh".to_string(), "-c".to_string(), script],
+            events: Vec::new(),
+        };
+        let event = Event::new("run_finished", json!({ "completed": 2 }));
+
+        assert_eq!(deliver(&sink, &event), Ok(()));
+
+        let body: Value = serde_json::from_str(&fs::read_to_string(stdin).unwrap()).unwrap();
+        assert_eq!(body, event.to_json());
+        assert_eq!(fs::read_to_string(name).unwrap(), "run_finished");
+
+        let failing = EventSink::Command {
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                "echo nope >&2; exit 1".to_string(),
+            ],
+            events: Vec::new(),
+        };
+        assert_eq!(deliver(&failing, &event), Err("nope".to_string()));
+    }
+
+    #[test]
+    fn files_get_one_line_per_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let sink = EventSink::File {
+            path: path.clone(),
+            events: vec!["item_added".to_string()],
+        };
+
+        for id in [1, 2] {
+            let event = Event::new("item_added", json!({ "id": id }));
+            assert_eq!(deliver(&sink, &event), Ok(()));
+        }
+
+        let lines: Vec<Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["data"]["id"], 2);
+        assert!(accepts(&sink, "item_added"));
+        assert!(!accepts(&sink, "item_completed"));
+    }
+}
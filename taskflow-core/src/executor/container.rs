@@ -0,0 +1,120 @@
+use super::{Execution, Executor, Exit, Running};
+use crate::config::{ContainerRuntime, ScriptSettings};
+use crate::invocation::Invocation;
+use crate::outputs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Runs scripts in a throwaway container with podman or docker.
+///
+/// The script, the inputs' directories (read-only) and the output directory
+/// are bind-mounted at the same paths they have on the host, so the
+/// invocation's paths work unchanged inside the container.
+#[derive(Debug, Clone)]
+pub struct ContainerExecutor {
+    pub image: String,
+    pub runtime: ContainerRuntime,
+    pub run_args: Vec<String>,
+}
+
+impl ContainerExecutor {
+    fn command(&self, invocation: &Invocation, manifest: &Path, name: &str) -> Command {
+        let mut command = Command::new(self.runtime.program());
+        command.args(["run", "--rm", "--name", name]);
+
+        let output_dir = &invocation.output_dir;
+        let mut input_dirs: Vec<&Path> = invocation
+            .inputs
+            .iter()
+            .filter_map(|input| input.parent())
+            .filter(|dir| dir != output_dir)
+            .collect();
+        input_dirs.sort();
+        input_dirs.dedup();
+        for input_dir in input_dirs {
+            command.arg("-v").arg(mount(input_dir, true));
+        }
+        command.arg("-v").arg(mount(output_dir, false));
+        let script = Path::new(&invocation.script);
+        if script.is_absolute() {
+            command.arg("-v").arg(mount(script, true));
+        }
+
+        // Rootless podman maps the caller to root in the container already;
+        // docker needs to be told, or outputs end up owned by root
+        #[cfg(unix)]
+        if self.runtime == ContainerRuntime::Docker {
+            // SAFETY: getuid and getgid have no memory safety requirements
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            command.arg("--user").arg(format!("{}:{}", uid, gid));
+        }
+
+        if invocation.stdin.is_some() {
+            command.arg("-i");
+        }
+        if let Some(ref dir) = invocation.working_dir {
+            command.arg("-w").arg(dir);
+        }
+        for (key, value) in &invocation.env {
+            command.arg("-e").arg(format!("{}={}", key, value));
+        }
+        command
+            .arg("-e")
+            .arg(format!("{}={}", outputs::MANIFEST_VAR, manifest.display()));
+
+        command
+            .args(&self.run_args)
+            .arg(&self.image)
+            .arg(&invocation.script)
+            .args(&invocation.args);
+        command
+    }
+}
+
+impl Executor for ContainerExecutor {
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        running: &Running,
+    ) -> Execution {
+        let name = super::unique_name();
+        let mut command = self.command(invocation, manifest, &name);
+
+        let exit = match super::run_with_input(
+            &mut command,
+            invocation.stdin.as_deref(),
+            settings.timeout_secs,
+            running,
+        ) {
+            Ok(exit) => exit,
+            Err(e) => {
+                return Execution::failed(format!(
+                    "Failed to execute script: could not run {}: {}",
+                    self.runtime.program(),
+                    e
+                ));
+            }
+        };
+
+        // Killing the client leaves the container running
+        if let Exit::TimedOut(_) = exit {
+            let _ = Command::new(self.runtime.program())
+                .args(["rm", "-f", &name])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+        super::script_result(exit, &settings.warning_exit_codes)
+    }
+}
+
+fn mount(path: &Path, read_only: bool) -> String {
+    let path = path.display();
+    if read_only {
+        format!("{}:{}:ro", path, path)
+    } else {
+        format!("{}:{}", path, path)
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Execution, Executor, Exit, Running};
+use crate::config::ScriptSettings;
+use crate::invocation::Invocation;
+use crate::limits;
+use crate::outputs;
+use crate::sandbox;
+use std::path::Path;
+
+/// Runs scripts as processes on this machine, with resource limits and
+/// the sandbox applied.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalExecutor;
+
+impl Executor for LocalExecutor {
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        running: &Running,
+    ) -> Execution {
+        Self::run(invocation, settings, manifest, running).unwrap_or_else(Execution::failed)
+    }
+}
+
+impl LocalExecutor {
+    fn run(
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        running: &Running,
+    ) -> Result<Execution, String> {
+        let mut command = invocation.command();
+        command.env(outputs::MANIFEST_VAR, manifest);
+
+        // Limits must be applied before the sandbox cuts off access to cgroupfs
+        let limit_guard = limits::apply(&mut command, &settings.limits)?;
+        sandbox::apply(
+            &mut command,
+            &settings.sandbox,
+            Path::new(&invocation.script),
+            &invocation.inputs,
+            &invocation.output_dir,
+        )?;
+
+        let exit = super::run_with_input(
+            &mut command,
+            invocation.stdin.as_deref(),
+            settings.timeout_secs,
+            running,
+        )
+        .map_err(|e| format!("Failed to execute script: {}", e))?;
+
+        if let Exit::Finished(ref output) = exit
+            && !output.status.success()
+        {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if let Some(limit) = limit_guard.exceeded(output.status, &stderr) {
+                return Ok(Execution {
+                    result: Err(format!("Resource limit exceeded: {}", limit)),
+                    stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                    warnings: Vec::new(),
+                });
+            }
+        }
+        Ok(super::script_result(exit, &settings.warning_exit_codes))
+    }
+}
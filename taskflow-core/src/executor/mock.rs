@@ -0,0 +1,152 @@
+use super::{Execution, Executor, Running};
+use crate::config::ScriptSettings;
+use crate::invocation::Invocation;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Stands in for a real executor so queue handling can be exercised
+/// without running processes.
+///
+/// Each call is recorded and, unless the input was set to fail, succeeds
+/// after touching the invocation's expected output. Clones share their
+/// configuration and calls, so one can be handed to an `Engine` and the
+/// other inspected.
+#[derive(Debug, Clone, Default)]
+pub struct MockExecutor {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    failures: HashMap<PathBuf, String>,
+    warnings: HashMap<PathBuf, Vec<String>>,
+    writes: HashMap<PathBuf, Vec<String>>,
+    // Inputs whose next call is lost along with the worker
+    lost: Vec<PathBuf>,
+    unhealthy: bool,
+    delay: Duration,
+    calls: Vec<Invocation>,
+}
+
+impl MockExecutor {
+    /// An executor where every item succeeds straight away.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes items for `input` fail with `error` as the script's stderr.
+    pub fn fail(&self, input: impl Into<PathBuf>, error: impl Into<String>) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.failures.insert(input.into(), error.into());
+        self
+    }
+
+    /// Makes items for `input` succeed with `warnings`.
+    pub fn warn(&self, input: impl Into<PathBuf>, warnings: Vec<String>) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.warnings.insert(input.into(), warnings);
+        self
+    }
+
+    /// Makes items for `input` write the files `names` in the output
+    /// directory and list them in the manifest, as a batch script might.
+    pub fn writes(&self, input: impl Into<PathBuf>, names: &[&str]) -> &Self {
+        let names = names.iter().map(|name| name.to_string()).collect();
+        self.state
+            .lock()
+            .unwrap()
+            .writes
+            .insert(input.into(), names);
+        self
+    }
+
+    /// Makes the next call for `input` fail as though the worker was lost.
+    /// The executor then reports itself unhealthy until
+    /// [`recover`](Self::recover) is called.
+    pub fn lose(&self, input: impl Into<PathBuf>) -> &Self {
+        self.state.lock().unwrap().lost.push(input.into());
+        self
+    }
+
+    /// Makes the executor healthy again after a [`lose`](Self::lose).
+    pub fn recover(&self) -> &Self {
+        self.state.lock().unwrap().unhealthy = false;
+        self
+    }
+
+    /// Makes every call take `delay`, e.g. to pause or cancel mid-item.
+    pub fn delay(&self, delay: Duration) -> &Self {
+        self.state.lock().unwrap().delay = delay;
+        self
+    }
+
+    /// Invocations received so far, in order.
+    pub fn calls(&self) -> Vec<Invocation> {
+        self.state.lock().unwrap().calls.clone()
+    }
+}
+
+impl Executor for MockExecutor {
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        _settings: &ScriptSettings,
+        manifest: &Path,
+        _running: &Running,
+    ) -> Execution {
+        let (delay, failure, warnings, writes, lost) = {
+            let mut state = self.state.lock().unwrap();
+            state.calls.push(invocation.clone());
+            let lost = match state
+                .lost
+                .iter()
+                .position(|input| *input == invocation.input)
+            {
+                Some(index) => {
+                    state.lost.remove(index);
+                    true
+                }
+                None => false,
+            };
+            (
+                state.delay,
+                state.failures.get(&invocation.input).cloned(),
+                state.warnings.get(&invocation.input).cloned(),
+                state.writes.get(&invocation.input).cloned(),
+                lost,
+            )
+        };
+        thread::sleep(delay);
+
+        if lost {
+            self.state.lock().unwrap().unhealthy = true;
+            return Execution::failed("Failed to execute script: worker lost".to_string());
+        }
+        if let Some(error) = failure {
+            return Execution::failed(format!("Script failed: {}", error));
+        }
+        if let Some(ref output) = invocation.expected_output
+            && let Err(e) = fs::write(output, b"")
+        {
+            return Execution::failed(format!("Failed to execute script: {}", e));
+        }
+        if let Some(names) = writes {
+            for name in &names {
+                let _ = fs::write(invocation.output_dir.join(name), b"");
+            }
+            let _ = fs::write(manifest, names.join("\n"));
+        }
+        Execution {
+            warnings: warnings.unwrap_or_default(),
+            ..Ok(()).into()
+        }
+    }
+
+    fn healthy(&self) -> bool {
+        !self.state.lock().unwrap().unhealthy
+    }
+}
@@ -0,0 +1,383 @@
+//! Where and how scripts are run: locally, in a container, over SSH or on
+//! an agent.
+
+mod container;
+mod local;
+mod mock;
+mod remote;
+mod ssh;
+
+pub(crate) use container::ContainerExecutor;
+pub(crate) use local::LocalExecutor;
+pub use mock::MockExecutor;
+pub(crate) use remote::RemoteExecutor;
+pub(crate) use ssh::SshExecutor;
+
+use crate::config::{ExecutorSettings, ScriptSettings};
+use crate::invocation::Invocation;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a process with a timeout is polled
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs a script on one input, or a batch script on several.
+pub trait Executor: Send + Sync {
+    /// Runs the script for `invocation` and waits for it. When it succeeds,
+    /// the script's outputs must be in `invocation.output_dir`, and any list
+    /// of them it wrote must be at `manifest`.
+    ///
+    /// Processes should be started with [`run`] so that pausing, timeouts
+    /// and `running` work the same for every executor.
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        running: &Running,
+    ) -> Execution;
+
+    /// Whether this executor can take work. An item that fails while its
+    /// executor is unhealthy, e.g. because an agent went away, is returned
+    /// to the queue instead of failing.
+    fn healthy(&self) -> bool {
+        true
+    }
+}
+
+/// How a script run by an [`Executor`] ended.
+#[derive(Debug, Clone)]
+pub struct Execution {
+    /// Why the script failed, e.g. "Script failed: ...".
+    pub result: Result<(), String>,
+    /// What the script printed, whether or not it succeeded.
+    pub stdout: String,
+    /// Warnings from a script that exited with one of its
+    /// `warning_exit_codes`.
+    pub warnings: Vec<String>,
+}
+
+impl Execution {
+    /// A run that failed without the script printing anything, e.g.
+    /// because it could not be started.
+    pub fn failed(error: String) -> Self {
+        Self {
+            result: Err(error),
+            stdout: String::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl From<Result<(), String>> for Execution {
+    fn from(result: Result<(), String>) -> Self {
+        Self {
+            result,
+            stdout: String::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// The executor a script's settings ask for.
+pub fn for_settings(settings: &ExecutorSettings) -> Box<dyn Executor> {
+    match settings {
+        ExecutorSettings::Local => Box::new(LocalExecutor),
+        ExecutorSettings::Container {
+            image,
+            runtime,
+            run_args,
+        } => Box::new(ContainerExecutor {
+            image: image.clone(),
+            runtime: *runtime,
+            run_args: run_args.clone(),
+        }),
+        ExecutorSettings::Ssh {
+            host,
+            port,
+            identity_file,
+            remote_dir,
+        } => Box::new(SshExecutor {
+            host: host.clone(),
+            port: *port,
+            identity_file: identity_file.clone(),
+            remote_dir: remote_dir.clone(),
+        }),
+    }
+}
+
+/// The process a worker is waiting on, shared so it can be signalled.
+#[derive(Default)]
+pub struct Running {
+    pub(crate) pid: Mutex<Option<u32>>,
+    // Stopped by `suspend`; timeouts don't advance meanwhile
+    pub(crate) suspended: AtomicBool,
+}
+
+impl Running {
+    /// Kills the running process and anything it spawned, if there is one.
+    #[cfg(unix)]
+    pub(crate) fn kill(&self) {
+        if let Some(pid) = *self.pid.lock().unwrap() {
+            // SAFETY: kill has no memory safety requirements
+            unsafe {
+                libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn kill(&self) {}
+}
+
+/// How a process started with [`run`] ended.
+pub enum Exit {
+    /// The process exited, or was killed by a signal.
+    Finished(Output),
+    /// The process was killed after running this long.
+    TimedOut(Duration),
+}
+
+/// Spawns `command` in its own process group and waits for it, killing
+/// the group if it runs past `timeout_secs`. stdout and stderr are captured.
+pub fn run(
+    command: &mut Command,
+    timeout_secs: Option<u64>,
+    running: &Running,
+) -> io::Result<Exit> {
+    run_with_input(command, None, timeout_secs, running)
+}
+
+/// Like [`run`], but with `input` written to the process's stdin.
+pub fn run_with_input(
+    command: &mut Command,
+    input: Option<&str>,
+    timeout_secs: Option<u64>,
+    running: &Running,
+) -> io::Result<Exit> {
+    let stdin = match input {
+        Some(_) => Stdio::piped(),
+        None => Stdio::null(),
+    };
+    command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let mut child = command.spawn()?;
+    *running.pid.lock().unwrap() = Some(child.id());
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // Written alongside the wait so a process that never reads it all
+        // can't hold us up; one that exits early just breaks the pipe
+        let input = input.to_string();
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let exit = match timeout_secs {
+        Some(secs) => wait_with_timeout(child, Duration::from_secs(secs), running),
+        None => child.wait_with_output().map(Exit::Finished),
+    };
+    *running.pid.lock().unwrap() = None;
+    running.suspended.store(false, Ordering::SeqCst);
+
+    exit
+}
+
+/// The usual result of a script that ran to completion or timed out.
+/// Exiting with one of `warning_exit_codes` counts as success, with the
+/// lines of stderr as warnings; otherwise a successful script's warnings
+/// are the stderr lines it starts with `warning:`.
+fn script_result(exit: Exit, warning_exit_codes: &[i32]) -> Execution {
+    match exit {
+        Exit::Finished(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let (result, warnings) = match output.status.code() {
+                _ if output.status.success() => (Ok(()), warning_lines(&stderr)),
+                Some(code) if warning_exit_codes.contains(&code) => {
+                    let mut warnings: Vec<String> = stderr
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    if warnings.is_empty() {
+                        warnings.push(format!("Exited with code {}", code));
+                    }
+                    (Ok(()), warnings)
+                }
+                _ => (Err(format!("Script failed: {}", stderr)), Vec::new()),
+            };
+            Execution {
+                result,
+                stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                warnings,
+            }
+        }
+        Exit::TimedOut(after) => {
+            Execution::failed(format!("Script timed out after {}s", after.as_secs()))
+        }
+    }
+}
+
+/// Lines of `stderr` starting with `warning:`, in any case, without it.
+fn warning_lines(stderr: &str) -> Vec<String> {
+    const PREFIX: &str = "warning:";
+    stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            line.get(..PREFIX.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(PREFIX))
+        })
+        .map(|line| line[PREFIX.len()..].trim().to_string())
+        .filter(|warning| !warning.is_empty())
+        .collect()
+}
+
+/// Rewrites absolute paths under `remote_out` in a manifest written on
+/// another machine to the matching paths under `local_out`.
+pub(crate) fn localize_manifest(manifest: &Path, remote_out: &Path, local_out: &Path) {
+    let Ok(contents) = fs::read_to_string(manifest) else {
+        return;
+    };
+    let localized: String = contents
+        .lines()
+        .map(
+            |line| match Path::new(line.trim()).strip_prefix(remote_out) {
+                Ok(relative) => format!("{}\n", local_out.join(relative).display()),
+                Err(_) => format!("{}\n", line),
+            },
+        )
+        .collect();
+    let _ = fs::write(manifest, localized);
+}
+
+/// A name unique to this process for staging areas and containers.
+pub(crate) fn unique_name() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    format!(
+        "taskflow-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn wait_with_timeout(mut child: Child, timeout: Duration, running: &Running) -> io::Result<Exit> {
+    // Drain the pipes so a chatty process can't block on a full buffer
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+
+    let mut remaining = timeout;
+    let mut last_tick = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+
+        let now = Instant::now();
+        if !running.suspended.load(Ordering::SeqCst) {
+            remaining = remaining.saturating_sub(now - last_tick);
+        }
+        last_tick = now;
+
+        if remaining.is_zero() {
+            kill(&mut child);
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let collect = |reader: Option<thread::JoinHandle<Vec<u8>>>| {
+        reader.and_then(|r| r.join().ok()).unwrap_or_default()
+    };
+    let (stdout, stderr) = (collect(stdout), collect(stderr));
+
+    Ok(match status {
+        Some(status) => Exit::Finished(Output {
+            status,
+            stdout,
+            stderr,
+        }),
+        None => Exit::TimedOut(timeout),
+    })
+}
+
+fn drain(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+/// Kills the process and anything it spawned.
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // SAFETY: kill has no memory safety requirements
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+
+    fn finished(code: i32, stderr: &str) -> Exit {
+        Exit::Finished(Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        })
+    }
+
+    #[test]
+    fn successful_scripts_warn_with_prefixed_stderr_lines() {
+        let stderr = "decoding\nWarning: audio track missing\n  warning: colour profile dropped\n";
+        let execution = script_result(finished(0, stderr), &[]);
+
+        assert_eq!(execution.result, Ok(()));
+        assert_eq!(
+            execution.warnings,
+            ["audio track missing", "colour profile dropped"]
+        );
+    }
+
+    #[test]
+    fn warning_exit_codes_turn_all_of_stderr_into_warnings() {
+        let execution = script_result(finished(2, "audio track missing\n"), &[2]);
+        assert_eq!(execution.result, Ok(()));
+        assert_eq!(execution.warnings, ["audio track missing"]);
+
+        let execution = script_result(finished(2, ""), &[2]);
+        assert_eq!(execution.warnings, ["Exited with code 2"]);
+
+        let execution = script_result(finished(1, "warning: disk full\n"), &[2]);
+        assert_eq!(
+            execution.result,
+            Err("Script failed: warning: disk full\n".to_string())
+        );
+        assert!(execution.warnings.is_empty());
+    }
+}
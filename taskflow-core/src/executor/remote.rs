@@ -0,0 +1,174 @@
+use super::{Execution, Executor, Running};
+use crate::agent::{self, Connection, HEARTBEAT_INTERVAL, IO_TIMEOUT, Message, Side};
+use crate::config::{AgentSettings, ScriptSettings, Staging};
+use crate::invocation::Invocation;
+use crate::secret;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// How long an agent that failed is left alone before being tried again
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Runs scripts on a `taskflow agent`. See [`agent`](crate::agent) for the
+/// protocol.
+///
+/// An agent that can't be reached, rejects the secret or stops sending
+/// heartbeats is unhealthy for a while, so its item goes back to the queue
+/// for another worker.
+#[derive(Debug)]
+pub struct RemoteExecutor {
+    settings: AgentSettings,
+    failed_at: Mutex<Option<Instant>>,
+}
+
+impl RemoteExecutor {
+    pub fn new(settings: AgentSettings) -> Self {
+        Self {
+            settings,
+            failed_at: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let address = self
+            .settings
+            .address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address not found"))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        // Three missed heartbeats and the agent is presumed dead
+        stream.set_read_timeout(Some(HEARTBEAT_INTERVAL * 3))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut connection = Connection::new(stream)?;
+
+        let Message::Challenge { nonce } = connection.recv()? else {
+            return Err(unexpected("challenge"));
+        };
+        let secret = &self.settings.secret;
+        let own_nonce = secret::random_token()?;
+        connection.send(&Message::Auth {
+            mac: agent::respond_to(secret, &nonce),
+            nonce: own_nonce.clone(),
+        })?;
+        match connection.recv()? {
+            Message::Ready => {
+                let key = agent::session_key(secret, &nonce, &own_nonce);
+                connection.secure(key, Side::Coordinator);
+                Ok(connection)
+            }
+            Message::Rejected { reason } => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("rejected: {}", reason),
+            )),
+            _ => Err(unexpected("ready")),
+        }
+    }
+
+    /// Runs one item. An error means the agent was lost; the script's own
+    /// failure is in the `Execution`.
+    fn session(
+        &self,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+    ) -> io::Result<Execution> {
+        let mut connection = self.connect()?;
+        let upload = self.settings.staging == Staging::Upload;
+        let output_dir = &invocation.output_dir;
+
+        connection.send(&Message::Job {
+            script: invocation.script.clone(),
+            input: invocation.input.clone(),
+            output_dir: output_dir.clone(),
+            expected_output: invocation
+                .expected_output
+                .as_ref()
+                .and_then(|output| output.strip_prefix(output_dir).ok())
+                .map(Path::to_path_buf),
+            manifest: file_name(manifest),
+            settings: Box::new(settings.clone()),
+            upload,
+        })?;
+        if upload {
+            let script = Path::new(&invocation.script);
+            connection.send_file(&file_name(script), script)?;
+            let input = format!("in/{}", file_name(&invocation.input));
+            connection.send_file(&input, &invocation.input)?;
+        }
+        connection.send(&Message::Run)?;
+
+        loop {
+            match connection.recv()? {
+                Message::Heartbeat => {}
+                Message::File { path, size } if upload => {
+                    let dest = agent::confined(output_dir, &path)?;
+                    connection.recv_file(size, &dest)?;
+                }
+                Message::Finished {
+                    error,
+                    stdout,
+                    warnings,
+                } => {
+                    return Ok(Execution {
+                        result: error.map_or(Ok(()), Err),
+                        stdout,
+                        warnings,
+                    });
+                }
+                _ => return Err(unexpected("heartbeat or result")),
+            }
+        }
+    }
+}
+
+impl Executor for RemoteExecutor {
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        _running: &Running,
+    ) -> Execution {
+        match self.session(invocation, settings, manifest) {
+            Ok(execution) => {
+                *self.failed_at.lock().unwrap() = None;
+                execution
+            }
+            Err(e) => {
+                *self.failed_at.lock().unwrap() = Some(Instant::now());
+                let reason = match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                        "stopped responding".to_string()
+                    }
+                    _ => e.to_string(),
+                };
+                Execution::failed(format!("Lost agent {}: {}", self.settings.address, reason))
+            }
+        }
+    }
+
+    fn healthy(&self) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap()
+            .is_none_or(|failed| failed.elapsed() >= RETRY_AFTER)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn unexpected(expected: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected message, expected {}", expected),
+    )
+}
@@ -0,0 +1,262 @@
+use super::{Execution, Executor, Running};
+use crate::config::ScriptSettings;
+use crate::invocation::{self, Invocation};
+use crate::outputs;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Runs scripts on another host with `ssh`, copying files with `scp` and
+/// `tar`.
+///
+/// Each item gets its own staging directory under `remote_dir` holding the
+/// script, the input in `in/` (a batch's in `in/0/`, `in/1/`, ...) and the
+/// outputs in `out/`. Placeholders are
+/// resolved against the staged paths. Once the script succeeds, everything
+/// in `out/` is copied into the local output directory, and the staging
+/// directory is removed either way.
+#[derive(Debug, Clone)]
+pub struct SshExecutor {
+    pub host: String,
+    pub port: Option<u16>,
+    pub identity_file: Option<PathBuf>,
+    pub remote_dir: String,
+}
+
+impl SshExecutor {
+    /// `program` with the options shared by `ssh` and `scp`.
+    fn client(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        // Never stop to ask for a password or host key confirmation
+        command.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            let flag = if program == "scp" { "-P" } else { "-p" };
+            command.arg(flag).arg(port.to_string());
+        }
+        if let Some(ref identity) = self.identity_file {
+            command.arg("-i").arg(identity);
+        }
+        command
+    }
+
+    /// Runs `words` on the host as a shell command line.
+    fn remote<'a>(&self, words: impl IntoIterator<Item = &'a str>) -> Command {
+        let mut command = self.client("ssh");
+        command
+            .arg(&self.host)
+            .arg("--")
+            .arg(invocation::command_line(words));
+        command
+    }
+
+    fn upload(&self, local: &Path, remote: &Path) -> Result<(), String> {
+        let mut command = self.client("scp");
+        command
+            .arg("-p")
+            .arg("-q")
+            .arg(local)
+            .arg(format!("{}:{}", self.host, remote.display()));
+        run_quiet(command).map_err(|e| format!("could not copy {}: {}", local.display(), e))
+    }
+
+    /// Copies the contents of `remote` into `local`.
+    fn download(&self, remote: &Path, local: &Path) -> Result<(), String> {
+        let remote = remote.to_string_lossy();
+        let mut sender = self
+            .remote(["tar", "-C", &remote, "-cf", "-", "."])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not run ssh: {}", e))?;
+
+        let receiver = Command::new("tar")
+            .arg("-C")
+            .arg(local)
+            .arg("-xf")
+            .arg("-")
+            .stdin(sender.stdout.take().unwrap())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| format!("could not run tar: {}", e))?;
+        let sender = sender
+            .wait_with_output()
+            .map_err(|e| format!("could not run ssh: {}", e))?;
+
+        for output in [sender, receiver] {
+            if !output.status.success() {
+                return Err(error_message(&output.status, &output.stderr));
+            }
+        }
+        Ok(())
+    }
+
+    fn stage_and_run(
+        &self,
+        stage: &Path,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        running: &Running,
+    ) -> Result<Execution, String> {
+        let staging = |e: String| format!("Failed to stage on {}: {}", self.host, e);
+        let remote_in = stage.join("in");
+        let remote_out = stage.join("out");
+        // A batch's inputs may share names, so each gets its own directory
+        let remote_inputs: Vec<PathBuf> = match invocation.inputs.as_slice() {
+            [input] => vec![remote_in.join(input.file_name().unwrap_or_default())],
+            inputs => inputs
+                .iter()
+                .enumerate()
+                .map(|(index, input)| {
+                    remote_in
+                        .join(index.to_string())
+                        .join(input.file_name().unwrap_or_default())
+                })
+                .collect(),
+        };
+        let dirs: Vec<String> = remote_inputs
+            .iter()
+            .filter_map(|input| input.parent())
+            .chain([remote_out.as_path()])
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect();
+        run_quiet(
+            self.remote(
+                ["mkdir", "-p"]
+                    .into_iter()
+                    .chain(dirs.iter().map(String::as_str)),
+            ),
+        )
+        .map_err(|e| staging(format!("could not create {}: {}", stage.display(), e)))?;
+
+        let script = Path::new(&invocation.script);
+        let remote_script = stage.join(script.file_name().unwrap_or_default());
+        self.upload(script, &remote_script).map_err(staging)?;
+        for (input, remote_input) in invocation.inputs.iter().zip(&remote_inputs) {
+            self.upload(input, remote_input).map_err(staging)?;
+        }
+
+        // Placeholders must point at the staged copies
+        let remote_output = invocation.expected_output.as_ref().map(|output| {
+            remote_out.join(
+                output
+                    .strip_prefix(&invocation.output_dir)
+                    .unwrap_or(output),
+            )
+        });
+        let remote = match settings.batch {
+            Some(_) => {
+                let list_name = invocation.file_list.as_ref().and_then(|l| l.file_name());
+                let remote_list = remote_out.join(list_name.unwrap_or_default());
+                // The local list names local paths; stage one naming the copies
+                if let Some(ref list) = invocation.file_list {
+                    let staged: String = remote_inputs
+                        .iter()
+                        .map(|input| format!("{}\n", input.display()))
+                        .collect();
+                    fs::write(list, staged)
+                        .map_err(|e| staging(format!("could not write file list: {}", e)))?;
+                    self.upload(list, &remote_list).map_err(staging)?;
+                }
+                Invocation::resolve_batch(
+                    &remote_script.to_string_lossy(),
+                    &remote_inputs,
+                    &remote_out,
+                    settings,
+                    remote_output,
+                    &remote_list,
+                )
+            }
+            None => Invocation::resolve_with_output(
+                &remote_script.to_string_lossy(),
+                &remote_inputs[0],
+                &remote_out,
+                settings,
+                remote_output,
+            ),
+        };
+        let remote_manifest = remote_out.join(manifest.file_name().unwrap_or_default());
+
+        let working_dir = remote
+            .working_dir
+            .clone()
+            .unwrap_or_else(|| stage.to_path_buf());
+        let env: Vec<String> = remote
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .chain([format!(
+                "{}={}",
+                outputs::MANIFEST_VAR,
+                remote_manifest.display()
+            )])
+            .collect();
+        let script_line = invocation::command_line(
+            ["env"]
+                .into_iter()
+                .chain(env.iter().map(String::as_str))
+                .chain([remote.script.as_str()])
+                .chain(remote.args.iter().map(String::as_str)),
+        );
+        let line = format!(
+            "cd {} && {}",
+            invocation::command_line([working_dir.to_string_lossy().as_ref()]),
+            script_line
+        );
+
+        let mut command = self.client("ssh");
+        command.arg(&self.host).arg("--").arg(line);
+        let exit = super::run_with_input(
+            &mut command,
+            remote.stdin.as_deref(),
+            settings.timeout_secs,
+            running,
+        )
+        .map_err(|e| format!("Failed to execute script: could not run ssh: {}", e))?;
+        let execution = super::script_result(exit, &settings.warning_exit_codes);
+        if execution.result.is_err() {
+            return Ok(execution);
+        }
+
+        self.download(&remote_out, &invocation.output_dir)
+            .map_err(|e| format!("Failed to copy outputs from {}: {}", self.host, e))?;
+        super::localize_manifest(manifest, &remote_out, &invocation.output_dir);
+        Ok(execution)
+    }
+}
+
+impl Executor for SshExecutor {
+    fn execute(
+        &self,
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        manifest: &Path,
+        running: &Running,
+    ) -> Execution {
+        let stage = Path::new(&self.remote_dir).join(super::unique_name());
+        let result = self.stage_and_run(&stage, invocation, settings, manifest, running);
+        // Best effort; a leftover stage is only wasted space
+        let _ = run_quiet(self.remote(["rm", "-rf", &stage.to_string_lossy()]));
+        result.unwrap_or_else(Execution::failed)
+    }
+}
+
+fn run_quiet(mut command: Command) -> Result<(), String> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(error_message(&output.status, &output.stderr))
+    }
+}
+
+fn error_message(status: &std::process::ExitStatus, stderr: &[u8]) -> String {
+    match String::from_utf8_lossy(stderr).trim() {
+        "" => status.to_string(),
+        message => message.to_string(),
+    }
+}
@@ -0,0 +1,236 @@
+//! A record of every item processed, kept across sessions.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// How a recorded run ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HistoryOutcome {
+    /// The script succeeded.
+    Success,
+    /// The script succeeded, with these warnings.
+    SucceededWithWarnings(Vec<String>),
+    /// The script failed, with why.
+    Failed(String),
+}
+
+/// A single item run, as recorded in the history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The input file.
+    pub input: PathBuf,
+    /// The script it ran with.
+    pub script: String,
+    /// How it ended.
+    pub outcome: HistoryOutcome,
+    /// When it was dispatched.
+    pub started_at: DateTime<Local>,
+    /// When its result came back.
+    pub finished_at: DateTime<Local>,
+    /// The output directory it ran with.
+    pub output_dir: PathBuf,
+    /// Files the run produced. Empty for failures and older entries.
+    #[serde(default)]
+    pub outputs: Vec<PathBuf>,
+    /// Why a post-action on the source file failed, if one did.
+    #[serde(default)]
+    pub post_action_error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// The input's file name, for display.
+    pub fn filename(&self) -> String {
+        self.input
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown")
+            .to_string()
+    }
+
+    /// The script's file name, for display.
+    pub fn script_name(&self) -> String {
+        PathBuf::from(&self.script)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(&self.script)
+            .to_string()
+    }
+
+    /// How long the run took.
+    pub fn duration(&self) -> std::time::Duration {
+        (self.finished_at - self.started_at)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// The lowercased text searches look in.
+    fn haystack(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.input.to_string_lossy(),
+            self.script,
+            self.output_dir.to_string_lossy()
+        )
+        .to_lowercase()
+    }
+}
+
+/// The entries matching a search, newest first.
+pub struct SearchResults<'a> {
+    /// At most the requested number of entries.
+    pub entries: Vec<&'a HistoryEntry>,
+    /// How many entries matched in all.
+    pub total: usize,
+}
+
+/// Append-only JSON lines store of every item run, kept in the data directory.
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<HistoryEntry>,
+    // Each entry's haystack, so searching doesn't rebuild them
+    haystacks: Vec<String>,
+}
+
+impl History {
+    /// Reads the history from the data directory. Lines that can't be read
+    /// are skipped.
+    pub fn load() -> Self {
+        Self::read(Self::history_path())
+    }
+
+    /// Reads the history kept in `path` instead, e.g. to keep a test or
+    /// an embedded engine away from the user's history.
+    pub fn open(path: PathBuf) -> Self {
+        Self::read(Some(path))
+    }
+
+    fn read(path: Option<PathBuf>) -> Self {
+        let entries: Vec<HistoryEntry> = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let haystacks = entries.iter().map(HistoryEntry::haystack).collect();
+
+        Self {
+            path,
+            entries,
+            haystacks,
+        }
+    }
+
+    /// Records `entry` in memory and in the history file.
+    pub fn append(&mut self, entry: HistoryEntry) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(ref path) = self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let line = serde_json::to_string(&entry)?;
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+        }
+        self.haystacks.push(entry.haystack());
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// The newest `limit` entries matching every whitespace-separated term
+    /// of `query`, and how many matched in all.
+    pub fn search(&self, query: &str, limit: usize) -> SearchResults<'_> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        let mut results = SearchResults {
+            entries: Vec::new(),
+            total: 0,
+        };
+        for (entry, haystack) in self.entries.iter().zip(&self.haystacks).rev() {
+            if terms.iter().all(|term| haystack.contains(term.as_str())) {
+                results.total += 1;
+                if results.entries.len() < limit {
+                    results.entries.push(entry);
+                }
+            }
+        }
+        results
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn history_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("taskflow").join("history.jsonl"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(input: &str, script: &str) -> HistoryEntry {
+        let now = Local::now();
+        HistoryEntry {
+            input: PathBuf::from(input),
+            script: script.to_string(),
+            outcome: HistoryOutcome::Success,
+            started_at: now,
+            finished_at: now,
+            output_dir: PathBuf::from("/out"),
+            outputs: Vec::new(),
+            post_action_error: None,
+        }
+    }
+
+    fn history(entries: Vec<HistoryEntry>) -> History {
+        let mut history = History {
+            path: None,
+            entries: Vec::new(),
+            haystacks: Vec::new(),
+        };
+        for entry in entries {
+            history.append(entry).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn search_is_case_insensitive_and_newest_first() {
+        let history = history(vec![
+            entry("/videos/Holiday.mov", "/scripts/encode.sh"),
+            entry("/photos/cat.jpg", "/scripts/resize.sh"),
+            entry("/videos/party.mov", "/scripts/encode.sh"),
+        ]);
+
+        let results = history.search("VIDEOS encode", 10);
+        let inputs: Vec<_> = results.entries.iter().map(|e| e.filename()).collect();
+        assert_eq!(inputs, ["party.mov", "Holiday.mov"]);
+        assert_eq!(results.total, 2);
+    }
+
+    #[test]
+    fn search_caps_entries_but_counts_every_match() {
+        let history = history(
+            (0..20)
+                .map(|i| entry(&format!("/in/{}.txt", i), "/scripts/a.sh"))
+                .collect(),
+        );
+
+        let results = history.search("", 5);
+        assert_eq!(results.entries.len(), 5);
+        assert_eq!(results.total, 20);
+        assert_eq!(results.entries[0].filename(), "19.txt");
+    }
+}
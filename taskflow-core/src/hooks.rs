@@ -0,0 +1,116 @@
+use crate::config::{Hook, Hooks};
+use crate::invocation::Invocation;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HookKind {
+    RunStart,
+    RunEnd,
+    BeforeItem,
+    AfterItemSuccess,
+    AfterItemFailure,
+}
+
+impl HookKind {
+    /// The name used in the config file and passed as `TASKFLOW_HOOK`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookKind::RunStart => "on_run_start",
+            HookKind::RunEnd => "on_run_end",
+            HookKind::BeforeItem => "before_item",
+            HookKind::AfterItemSuccess => "after_item_success",
+            HookKind::AfterItemFailure => "after_item_failure",
+        }
+    }
+}
+
+impl Hooks {
+    pub(crate) fn get(&self, kind: HookKind) -> Option<&Hook> {
+        let hook = match kind {
+            HookKind::RunStart => &self.on_run_start,
+            HookKind::RunEnd => &self.on_run_end,
+            HookKind::BeforeItem => &self.before_item,
+            HookKind::AfterItemSuccess => &self.after_item_success,
+            HookKind::AfterItemFailure => &self.after_item_failure,
+        };
+        hook.as_ref().filter(|hook| !hook.command.is_empty())
+    }
+}
+
+/// How a run ended, for `on_run_end`.
+pub struct RunSummary {
+    pub cancelled: bool,
+    pub succeeded: usize,
+    /// Succeeded with warnings; not included in `succeeded`.
+    pub warnings: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub report: Option<PathBuf>,
+}
+
+/// Environment for a run-level hook.
+pub fn run_env(
+    kind: HookKind,
+    script: &str,
+    output_dir: &Path,
+    summary: Option<&RunSummary>,
+) -> Vec<(String, String)> {
+    let mut env = vec![
+        var("TASKFLOW_HOOK", kind.name()),
+        var("TASKFLOW_SCRIPT", script),
+        var("TASKFLOW_OUTPUT_DIR", &output_dir.to_string_lossy()),
+    ];
+
+    if let Some(summary) = summary {
+        let status = if summary.cancelled {
+            "cancelled"
+        } else {
+            "completed"
+        };
+        env.push(var("TASKFLOW_RUN_STATUS", status));
+        env.push(var("TASKFLOW_SUCCEEDED", &summary.succeeded.to_string()));
+        env.push(var("TASKFLOW_WARNINGS", &summary.warnings.to_string()));
+        env.push(var("TASKFLOW_SKIPPED", &summary.skipped.to_string()));
+        env.push(var("TASKFLOW_FAILED", &summary.failed.to_string()));
+        if let Some(ref report) = summary.report {
+            env.push(var("TASKFLOW_REPORT", &report.to_string_lossy()));
+        }
+    }
+
+    env
+}
+
+/// Environment for an item hook. `outputs` and `error` describe the
+/// finished item for the `after_item_*` hooks.
+pub fn item_env(
+    kind: HookKind,
+    invocation: &Invocation,
+    outputs: &[PathBuf],
+    error: Option<&str>,
+) -> Vec<(String, String)> {
+    let mut env = vec![
+        var("TASKFLOW_HOOK", kind.name()),
+        var("TASKFLOW_SCRIPT", &invocation.script),
+    ];
+    env.extend(
+        invocation
+            .env
+            .iter()
+            .filter(|(key, _)| key.starts_with("TASKFLOW_"))
+            .cloned(),
+    );
+
+    if !outputs.is_empty() {
+        let outputs: Vec<_> = outputs.iter().map(|p| p.to_string_lossy()).collect();
+        env.push(var("TASKFLOW_OUTPUTS", &outputs.join("\n")));
+    }
+    if let Some(error) = error {
+        env.push(var("TASKFLOW_ERROR", error));
+    }
+
+    env
+}
+
+fn var(key: &str, value: &str) -> (String, String) {
+    (key.to_string(), value.to_string())
+}
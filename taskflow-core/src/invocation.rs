@@ -0,0 +1,367 @@
+use crate::config::{BatchInput, CollisionPolicy, ScriptSettings};
+use crate::outputs;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Everything needed to run a script on one input, or a batch script on
+/// several, resolved from the script's settings.
+///
+/// Argument, environment and path templates may use these placeholders:
+///
+/// | Placeholder    | Value                                   |
+/// |----------------|-----------------------------------------|
+/// | `{input}`      | full path of the input file             |
+/// | `{input_dir}`  | directory containing the input file     |
+/// | `{name}`       | input file name, e.g. `clip.mov`        |
+/// | `{stem}`       | input file name without extension       |
+/// | `{ext}`        | input file extension, without the dot   |
+/// | `{output_dir}` | selected output directory               |
+/// | `{output}`     | expected output path, if one is set     |
+/// | `{files}`      | every input of a batch, space-separated |
+/// | `{file_list}`  | path of a batch's list of inputs        |
+///
+/// For a batch script, the other input placeholders refer to its first
+/// input. An argument that is just `{files}` becomes one argument per input.
+#[derive(Debug, Clone)]
+pub struct Invocation {
+    /// Path of the script, or a built-in's name.
+    pub script: String,
+    /// The first input of a batch.
+    pub input: PathBuf,
+    /// Every input of a batch; otherwise just `input`.
+    pub inputs: Vec<PathBuf>,
+    /// Where outputs are written.
+    pub output_dir: PathBuf,
+    /// Arguments with placeholders expanded.
+    pub args: Vec<String>,
+    /// Variables set on top of TaskFlow's own environment, sorted by name.
+    pub env: Vec<(String, String)>,
+    /// Directory the script runs in, if not TaskFlow's own.
+    pub working_dir: Option<PathBuf>,
+    /// Full path of the output the script is expected to write.
+    pub expected_output: Option<PathBuf>,
+    /// Written to the script's stdin, which is otherwise empty.
+    pub stdin: Option<String>,
+    /// Where `inputs` must be listed, one per line, before the script runs.
+    pub file_list: Option<PathBuf>,
+}
+
+impl Invocation {
+    pub(crate) fn resolve(
+        script: &str,
+        input: &Path,
+        output_dir: &Path,
+        settings: &ScriptSettings,
+    ) -> Self {
+        Self::resolve_with_output(script, input, output_dir, settings, None)
+    }
+
+    /// Like `resolve`, but with the expected output replaced by `output`,
+    /// e.g. after renaming it to avoid a collision.
+    pub(crate) fn resolve_with_output(
+        script: &str,
+        input: &Path,
+        output_dir: &Path,
+        settings: &ScriptSettings,
+        output: Option<PathBuf>,
+    ) -> Self {
+        let vars = Vars::new(input, output_dir);
+        Self::resolve_vars(script, vars, input, output_dir, settings, output)
+    }
+
+    fn resolve_vars(
+        script: &str,
+        mut vars: Vars,
+        input: &Path,
+        output_dir: &Path,
+        settings: &ScriptSettings,
+        output: Option<PathBuf>,
+    ) -> Self {
+        let expected_output = output.or_else(|| {
+            settings
+                .output_name
+                .as_deref()
+                .map(|name| output_dir.join(vars.expand(name)))
+        });
+        if let Some(ref output) = expected_output {
+            vars.output = output.to_string_lossy().into_owned();
+        }
+
+        let args = settings
+            .args
+            .iter()
+            .flat_map(|arg| vars.expand_arg(arg))
+            .collect();
+
+        let mut env = vec![
+            ("TASKFLOW_INPUT".to_string(), vars.input.clone()),
+            ("TASKFLOW_OUTPUT_DIR".to_string(), vars.output_dir.clone()),
+        ];
+        if expected_output.is_some() {
+            env.push(("TASKFLOW_OUTPUT".to_string(), vars.output.clone()));
+        }
+        env.extend(
+            settings
+                .env
+                .iter()
+                .map(|(key, value)| (key.clone(), vars.expand(value))),
+        );
+        env.sort();
+
+        let working_dir = settings
+            .working_dir
+            .as_deref()
+            .map(|dir| PathBuf::from(vars.expand(dir)));
+
+        Self {
+            script: script.to_string(),
+            input: input.to_path_buf(),
+            inputs: vec![input.to_path_buf()],
+            output_dir: output_dir.to_path_buf(),
+            args,
+            env,
+            working_dir,
+            expected_output,
+            stdin: None,
+            file_list: None,
+        }
+    }
+
+    /// Resolves a single run of a batch script over all of `inputs`, which
+    /// are handed to it as `settings.batch` asks. Placeholders refer to the
+    /// first input; `file_list` is where a list file would be written.
+    pub(crate) fn resolve_batch(
+        script: &str,
+        inputs: &[PathBuf],
+        output_dir: &Path,
+        settings: &ScriptSettings,
+        output: Option<PathBuf>,
+        file_list: &Path,
+    ) -> Self {
+        let first = inputs
+            .first()
+            .map(PathBuf::as_path)
+            .unwrap_or(Path::new(""));
+        let paths: Vec<String> = inputs
+            .iter()
+            .map(|input| input.to_string_lossy().into_owned())
+            .collect();
+        let list = file_list.to_string_lossy().into_owned();
+        let batch = settings.batch.unwrap_or_default();
+
+        let mut vars = Vars::new(first, output_dir);
+        vars.set_files(paths.clone());
+        if batch == BatchInput::ListFile {
+            vars.file_list = Some(list.clone());
+        }
+        let mut invocation = Self::resolve_vars(script, vars, first, output_dir, settings, output);
+        invocation.inputs = inputs.to_vec();
+
+        // Appended unless the arguments already place them
+        let placed = |placeholder: &str| settings.args.iter().any(|arg| arg.contains(placeholder));
+        match batch {
+            BatchInput::Args => {
+                if !placed("{files}") {
+                    invocation.args.extend(paths);
+                }
+            }
+            BatchInput::ListFile => {
+                if !placed("{file_list}") {
+                    invocation.args.push(list.clone());
+                }
+                invocation
+                    .env
+                    .push((outputs::FILE_LIST_VAR.to_string(), list));
+                invocation.env.sort();
+                invocation.file_list = Some(file_list.to_path_buf());
+            }
+            BatchInput::Stdin => {
+                invocation.stdin = Some(paths.into_iter().map(|path| path + "\n").collect());
+            }
+        }
+        invocation
+    }
+
+    /// A `Command` for this invocation. Stdio and process settings are left
+    /// to the caller.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.script);
+        command.args(&self.args).envs(self.env.iter().cloned());
+        if let Some(ref dir) = self.working_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    /// Expands placeholders in `template` for this invocation's input.
+    pub fn expand(&self, template: &str) -> String {
+        let mut vars = Vars::new(&self.input, &self.output_dir);
+        if let Some(ref output) = self.expected_output {
+            vars.output = output.to_string_lossy().into_owned();
+        }
+        vars.expand(template)
+    }
+
+    /// The command as it would be typed in a shell.
+    pub fn command_line(&self) -> String {
+        command_line(
+            std::iter::once(self.script.as_str()).chain(self.args.iter().map(String::as_str)),
+        )
+    }
+}
+
+/// How an item's expected output is treated under its collision policy.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputPlan {
+    /// Nothing is in the way, or the policy is to overwrite.
+    Write,
+    Skip,
+    Rename(PathBuf),
+    Fail,
+}
+
+/// Applies `policy` to `output`, which collides if it already exists or is
+/// in `claimed` by an earlier item of the same run.
+pub fn plan_output(
+    output: &Path,
+    policy: CollisionPolicy,
+    claimed: &HashSet<PathBuf>,
+) -> OutputPlan {
+    let taken = |path: &Path| path.exists() || claimed.contains(path);
+    if !taken(output) {
+        return OutputPlan::Write;
+    }
+
+    match policy {
+        CollisionPolicy::Overwrite => OutputPlan::Write,
+        CollisionPolicy::Skip => OutputPlan::Skip,
+        CollisionPolicy::Fail => OutputPlan::Fail,
+        CollisionPolicy::AutoRename => {
+            let stem = output
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let extension = output
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+
+            (1..)
+                .map(|n| output.with_file_name(format!("{}_{}{}", stem, n, extension)))
+                .find(|candidate| !taken(candidate))
+                .map(OutputPlan::Rename)
+                .unwrap_or(OutputPlan::Fail)
+        }
+    }
+}
+
+/// Placeholder values for one input.
+struct Vars {
+    input: String,
+    input_dir: String,
+    name: String,
+    stem: String,
+    ext: String,
+    output_dir: String,
+    output: String,
+    files: Vec<String>,
+    files_joined: String,
+    file_list: Option<String>,
+}
+
+impl Vars {
+    fn new(input: &Path, output_dir: &Path) -> Self {
+        let lossy = |s: Option<&std::ffi::OsStr>| {
+            s.map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        Self {
+            input: input.to_string_lossy().into_owned(),
+            input_dir: lossy(input.parent().map(Path::as_os_str)),
+            name: lossy(input.file_name()),
+            stem: lossy(input.file_stem()),
+            ext: lossy(input.extension()),
+            output_dir: output_dir.to_string_lossy().into_owned(),
+            output: String::new(),
+            files: vec![input.to_string_lossy().into_owned()],
+            files_joined: input.to_string_lossy().into_owned(),
+            file_list: None,
+        }
+    }
+
+    fn set_files(&mut self, files: Vec<String>) {
+        self.files_joined = files.join(" ");
+        self.files = files;
+    }
+
+    /// Like `expand`, but an argument that is just `{files}` becomes one
+    /// argument per file.
+    fn expand_arg(&self, arg: &str) -> Vec<String> {
+        if arg == "{files}" {
+            return self.files.clone();
+        }
+        vec![self.expand(arg)]
+    }
+
+    /// Replaces known placeholders in `template`. Unknown ones are kept as is.
+    fn expand(&self, template: &str) -> String {
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            let after = &rest[start..];
+            let Some(end) = after.find('}') else {
+                rest = after;
+                break;
+            };
+
+            match self.lookup(&after[1..end]) {
+                Some(value) => result.push_str(value),
+                None => result.push_str(&after[..=end]),
+            }
+            rest = &after[end + 1..];
+        }
+
+        result.push_str(rest);
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Option<&str> {
+        Some(match name {
+            "input" => &self.input,
+            "input_dir" => &self.input_dir,
+            "name" => &self.name,
+            "stem" => &self.stem,
+            "ext" => &self.ext,
+            "output_dir" => &self.output_dir,
+            "output" => &self.output,
+            "files" => &self.files_joined,
+            "file_list" => self.file_list.as_deref()?,
+            _ => return None,
+        })
+    }
+}
+
+/// Joins `words` into a command line, quoting them as a shell would need.
+pub fn command_line<'a>(words: impl IntoIterator<Item = &'a str>) -> String {
+    words
+        .into_iter()
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
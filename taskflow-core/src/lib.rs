@@ -0,0 +1,61 @@
+//! The engine behind TaskFlow: a queue of files, each run through a script
+//! one at a time, with the settings, hooks, events, reports and history that
+//! go with it.
+//!
+//! [`Engine`] ties the pieces together. It is driven by calling
+//! [`Engine::poll`] regularly, from a GUI's frame loop or a plain loop:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use taskflow_core::{Config, Engine};
+//!
+//! let mut engine = Engine::new(Config::load());
+//! let events = engine.subscribe();
+//! engine.enqueue(vec!["/videos/a.mov".into(), "/videos/b.mov".into()]);
+//! engine.start().expect("script and output directory are configured");
+//!
+//! while engine.is_processing() {
+//!     engine.poll();
+//!     for event in events.try_iter() {
+//!         println!("{}: {}", event.name, event.data);
+//!     }
+//!     std::thread::sleep(Duration::from_millis(50));
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+mod agent;
+pub mod builtin;
+pub mod config;
+pub mod dry_run;
+mod engine;
+pub mod events;
+pub mod executor;
+pub mod history;
+mod hooks;
+mod invocation;
+mod limits;
+mod logger;
+mod outputs;
+mod post_action;
+pub mod preflight;
+mod processor;
+pub mod queue;
+mod report;
+mod results;
+mod sandbox;
+pub mod scheduler;
+pub mod secret;
+mod verify;
+
+pub use agent::{AgentOptions, DEFAULT_PORT as DEFAULT_AGENT_PORT, serve as serve_agent};
+pub use config::Config;
+pub use engine::Engine;
+pub use events::Event;
+pub use executor::Executor;
+pub use invocation::Invocation;
+pub use logger::Logger;
+pub use outputs::{open as open_output, reveal as reveal_output};
+pub use queue::{ItemId, ItemState, Queue, QueueItem};
+pub use report::{RunReport, format_duration};
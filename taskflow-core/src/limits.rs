@@ -0,0 +1,379 @@
+use crate::config::ResourceLimits;
+use crate::report::format_bytes;
+use std::process::{Command, ExitStatus};
+
+/// Tracks the limits applied to one script run so a failure can be
+/// explained, and removes any cgroup created for it.
+pub struct LimitGuard {
+    limits: ResourceLimits,
+    #[cfg(target_os = "linux")]
+    cgroup: Option<std::path::PathBuf>,
+}
+
+impl LimitGuard {
+    /// Describes the limit a failed run most likely hit, if any.
+    #[cfg(unix)]
+    pub fn exceeded(&self, status: ExitStatus, stderr: &str) -> Option<String> {
+        use std::os::unix::process::ExitStatusExt;
+
+        #[cfg(target_os = "linux")]
+        if let (Some(dir), Some(bytes)) = (&self.cgroup, self.limits.max_memory_bytes)
+            && cgroup::oom_killed(dir)
+        {
+            return Some(format!("memory limit of {} exceeded", format_bytes(bytes)));
+        }
+
+        // Only SIGXCPU is specific to the CPU limit; the SIGKILL sent at the
+        // hard limit is indistinguishable from a timeout, cancel or OOM kill
+        if let Some(secs) = self.limits.cpu_time_secs
+            && status.signal() == Some(libc::SIGXCPU)
+        {
+            return Some(format!("CPU time limit of {}s exceeded", secs));
+        }
+
+        // Under the address space fallback, allocations fail instead of the
+        // process being killed, so all there is to go on is the error output
+        if let Some(bytes) = self.limits.max_memory_bytes
+            && !self.uses_cgroup()
+            && mentions_allocation_failure(stderr)
+        {
+            return Some(format!(
+                "memory limit of {} likely exceeded",
+                format_bytes(bytes)
+            ));
+        }
+
+        if let Some(files) = self.limits.max_open_files
+            && stderr.contains("Too many open files")
+        {
+            return Some(format!("open file limit of {} exceeded", files));
+        }
+
+        None
+    }
+
+    #[cfg(not(unix))]
+    pub fn exceeded(&self, _status: ExitStatus, _stderr: &str) -> Option<String> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn uses_cgroup(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.cgroup.is_some();
+        #[cfg(not(target_os = "linux"))]
+        return false;
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        if let Some(dir) = self.cgroup.take() {
+            cgroup::remove(&dir);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mentions_allocation_failure(stderr: &str) -> bool {
+    [
+        "Cannot allocate memory",
+        "Out of memory",
+        "out of memory",
+        "MemoryError",
+        "bad_alloc",
+        "memory allocation failed",
+    ]
+    .iter()
+    .any(|needle| stderr.contains(needle))
+}
+
+/// Arranges for `limits` to be applied to the process spawned from `command`.
+#[cfg(unix)]
+pub fn apply(command: &mut Command, limits: &ResourceLimits) -> Result<LimitGuard, String> {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+
+    #[cfg(target_os = "linux")]
+    let cgroup = limits
+        .max_memory_bytes
+        .and_then(|bytes| cgroup::create(bytes).ok());
+    #[cfg(target_os = "linux")]
+    let cgroup_procs = cgroup
+        .as_ref()
+        .and_then(|dir| CString::new(dir.join("cgroup.procs").as_os_str().as_bytes()).ok());
+    #[cfg(not(target_os = "linux"))]
+    let cgroup_procs: Option<CString> = None;
+
+    // Without a cgroup, fall back to capping the address space
+    let address_space = if cgroup_procs.is_some() {
+        None
+    } else {
+        limits.max_memory_bytes
+    };
+
+    let nice = limits.nice;
+    let cpu_time = limits.cpu_time_secs;
+    let open_files = limits.max_open_files;
+    #[cfg(target_os = "linux")]
+    let ioprio = limits.ionice.map(ioprio_value);
+
+    fn check(ret: libc::c_int) -> io::Result<()> {
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    // SAFETY: the closure only makes async-signal-safe calls (open, write,
+    // close, setpriority, syscall, setrlimit) and does not allocate
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(ref procs) = cgroup_procs {
+                let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                check(fd)?;
+                let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+                libc::close(fd);
+                if written != 1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            if let Some(nice) = nice {
+                check(libc::setpriority(libc::PRIO_PROCESS, 0, nice))?;
+            }
+
+            #[cfg(target_os = "linux")]
+            if let Some(ioprio) = ioprio {
+                // IOPRIO_WHO_PROCESS = 1
+                check(libc::syscall(libc::SYS_ioprio_set, 1, 0, ioprio) as libc::c_int)?;
+            }
+
+            if let Some(bytes) = address_space {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes as libc::rlim_t,
+                    rlim_max: bytes as libc::rlim_t,
+                };
+                check(libc::setrlimit(libc::RLIMIT_AS, &limit))?;
+            }
+
+            if let Some(secs) = cpu_time {
+                // A grace period between SIGXCPU and SIGKILL
+                let limit = libc::rlimit {
+                    rlim_cur: secs as libc::rlim_t,
+                    rlim_max: secs.saturating_add(5) as libc::rlim_t,
+                };
+                check(libc::setrlimit(libc::RLIMIT_CPU, &limit))?;
+            }
+
+            if let Some(files) = open_files {
+                let limit = libc::rlimit {
+                    rlim_cur: files as libc::rlim_t,
+                    rlim_max: files as libc::rlim_t,
+                };
+                check(libc::setrlimit(libc::RLIMIT_NOFILE, &limit))?;
+            }
+
+            Ok(())
+        });
+    }
+
+    Ok(LimitGuard {
+        limits: limits.clone(),
+        #[cfg(target_os = "linux")]
+        cgroup,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn apply(_command: &mut Command, limits: &ResourceLimits) -> Result<LimitGuard, String> {
+    if *limits != ResourceLimits::default() {
+        return Err("Resource limits are not supported on this platform".to_string());
+    }
+    Ok(LimitGuard {
+        limits: limits.clone(),
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn ioprio_value(priority: crate::config::IoPriority) -> libc::c_long {
+    use crate::config::IoPriority;
+
+    const CLASS_SHIFT: libc::c_long = 13;
+    let (class, level) = match priority {
+        IoPriority::Realtime(level) => (1, level.min(7)),
+        IoPriority::BestEffort(level) => (2, level.min(7)),
+        IoPriority::Idle => (3, 0),
+    };
+    (class << CLASS_SHIFT) | level as libc::c_long
+}
+
+/// Per-run cgroup v2 groups for memory limits.
+#[cfg(target_os = "linux")]
+mod cgroup {
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const ROOT: &str = "/sys/fs/cgroup";
+
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    /// Creates a cgroup with `memory.max` set, next to or below our own.
+    pub fn create(max_bytes: u64) -> io::Result<PathBuf> {
+        let root = Path::new(ROOT);
+        if !root.join("cgroup.controllers").exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cgroup v2 is not mounted",
+            ));
+        }
+
+        let own = fs::read_to_string("/proc/self/cgroup")?
+            .lines()
+            .find_map(|line| line.strip_prefix("0::").map(str::to_string))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cgroup v2 membership"))?;
+        let own = root.join(own.trim_start_matches('/'));
+
+        // A cgroup holding processes cannot delegate controllers, so the
+        // parent (e.g. a systemd user slice) is usually where this works
+        let candidates = [Some(own.as_path()), own.parent()];
+        let name = format!(
+            "taskflow-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+
+        for parent in candidates.into_iter().flatten() {
+            let delegates_memory = fs::read_to_string(parent.join("cgroup.subtree_control"))
+                .is_ok_and(|controllers| controllers.split_whitespace().any(|c| c == "memory"));
+            if !delegates_memory {
+                continue;
+            }
+
+            let dir = parent.join(&name);
+            if fs::create_dir(&dir).is_err() {
+                continue;
+            }
+            if fs::write(dir.join("memory.max"), max_bytes.to_string()).is_err() {
+                remove(&dir);
+                continue;
+            }
+            // Keep the limit meaningful on systems with swap
+            let _ = fs::write(dir.join("memory.swap.max"), "0");
+
+            return Ok(dir);
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "no writable cgroup with the memory controller",
+        ))
+    }
+
+    pub fn oom_killed(dir: &Path) -> bool {
+        fs::read_to_string(dir.join("memory.events")).is_ok_and(|events| {
+            events.lines().any(|line| {
+                line.strip_prefix("oom_kill ")
+                    .and_then(|count| count.trim().parse::<u64>().ok())
+                    .is_some_and(|count| count > 0)
+            })
+        })
+    }
+
+    pub fn remove(dir: &Path) {
+        let _ = fs::remove_dir(dir);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn guard(limits: ResourceLimits) -> LimitGuard {
+        LimitGuard {
+            limits,
+            #[cfg(target_os = "linux")]
+            cgroup: None,
+        }
+    }
+
+    /// The output of `sh -c script` run with `limits`.
+    fn run_with(limits: &ResourceLimits, script: &str) -> String {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        let _guard = apply(&mut command, limits).unwrap();
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn limits_are_applied_to_the_child() {
+        let limits = ResourceLimits {
+            cpu_time_secs: Some(30),
+            max_open_files: Some(64),
+            ..ResourceLimits::default()
+        };
+        assert_eq!(run_with(&limits, "ulimit -t; ulimit -n"), "30\n64");
+    }
+
+    #[test]
+    fn sigxcpu_means_the_cpu_limit_was_hit() {
+        let guard = guard(ResourceLimits {
+            cpu_time_secs: Some(30),
+            ..ResourceLimits::default()
+        });
+        let status = ExitStatus::from_raw(libc::SIGXCPU);
+
+        assert_eq!(
+            guard.exceeded(status, ""),
+            Some("CPU time limit of 30s exceeded".to_string())
+        );
+        // A SIGKILL could have come from anywhere
+        assert_eq!(
+            guard.exceeded(ExitStatus::from_raw(libc::SIGKILL), ""),
+            None
+        );
+    }
+
+    #[test]
+    fn failures_are_only_blamed_on_limits_that_were_set() {
+        let failed = ExitStatus::from_raw(1 << 8);
+        let unlimited = guard(ResourceLimits::default());
+        assert_eq!(unlimited.exceeded(failed, "Too many open files"), None);
+        assert_eq!(unlimited.exceeded(failed, "MemoryError"), None);
+
+        let limited = guard(ResourceLimits {
+            max_memory_bytes: Some(512 * 1024 * 1024),
+            max_open_files: Some(64),
+            ..ResourceLimits::default()
+        });
+        assert_eq!(
+            limited.exceeded(failed, "convert: Too many open files"),
+            Some("open file limit of 64 exceeded".to_string())
+        );
+        assert_eq!(
+            limited.exceeded(failed, "Traceback ...\nMemoryError"),
+            Some("memory limit of 512.0 MB likely exceeded".to_string())
+        );
+        assert_eq!(limited.exceeded(failed, "No such file"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn io_priorities_encode_class_and_level() {
+        use crate::config::IoPriority;
+
+        assert_eq!(ioprio_value(IoPriority::Realtime(0)), 1 << 13);
+        assert_eq!(ioprio_value(IoPriority::BestEffort(4)), (2 << 13) | 4);
+        assert_eq!(ioprio_value(IoPriority::BestEffort(9)), (2 << 13) | 7);
+        assert_eq!(ioprio_value(IoPriority::Idle), 3 << 13);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::config::{LogLocation, LogRotation, LogSettings};
+use chrono::{DateTime, Local};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const ROLLING_LOG_NAME: &str = "taskflow.log";
+// Rotated logs are `taskflow.<timestamp>.log` or `taskflow.<timestamp>_<n>.log`,
+// so they can't be mistaken for session logs or reports
+const ROTATED_PREFIX: &str = "taskflow.";
+const ROTATED_STAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Writes the run log, to a file per session or a rolling log as
+/// [`LogSettings`] say.
+pub struct Logger {
+    log_file: Option<PathBuf>,
+    enabled: bool,
+    settings: LogSettings,
+}
+
+impl Logger {
+    pub(crate) fn new(output_dir: Option<PathBuf>, enabled: bool, settings: &LogSettings) -> Self {
+        let log_file = Self::resolve_log_file(output_dir, enabled, settings);
+
+        Self {
+            log_file,
+            enabled,
+            settings: settings.clone(),
+        }
+    }
+
+    /// Appends a timestamped line, if logging is enabled.
+    pub fn log(&self, message: &str) {
+        if !self.enabled || self.log_file.is_none() {
+            return;
+        }
+
+        if let Some(ref path) = self.log_file {
+            let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+            let log_line = format!("[{}] {}\n", timestamp, message);
+
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+
+            if self.is_rolling() {
+                let _ = self.rotate_if_needed(path, log_line.len() as u64);
+            }
+
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = file.write_all(log_line.as_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn log_start(&self, filename: &str, script: &str) {
+        self.log(&format!("Starting: {} with script: {}", filename, script));
+    }
+
+    pub(crate) fn log_success(&self, filename: &str) {
+        self.log(&format!("Success: {}", filename));
+    }
+
+    pub(crate) fn log_skipped(&self, filename: &str, reason: &str) {
+        self.log(&format!("Skipped: {} - {}", filename, reason));
+    }
+
+    pub(crate) fn log_error(&self, filename: &str, error: &str) {
+        self.log(&format!("Error: {} - {}", filename, error));
+    }
+
+    pub(crate) fn log_warning(&self, filename: &str, warning: &str) {
+        self.log(&format!("Warning: {} - {}", filename, warning));
+    }
+
+    pub(crate) fn log_post_action_error(&self, filename: &str, error: &str) {
+        self.log(&format!("Post-action error: {} - {}", filename, error));
+    }
+
+    /// Logs a finished hook; `subject` is the item filename for item hooks.
+    pub(crate) fn log_hook(&self, hook: &str, subject: Option<&str>, result: &Result<(), String>) {
+        let name = match subject {
+            Some(subject) => format!("{} ({})", hook, subject),
+            None => hook.to_string(),
+        };
+        match result {
+            Ok(()) => self.log(&format!("Hook: {} - ok", name)),
+            Err(e) => self.log(&format!("Hook failed: {} - {}", name, e)),
+        }
+    }
+
+    /// The last `count` lines of the current log, oldest first.
+    pub fn tail(&self, count: usize) -> Vec<String> {
+        let Some(contents) = self
+            .log_file
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+        else {
+            return Vec::new();
+        };
+        let lines: Vec<&str> = contents.lines().collect();
+        lines[lines.len().saturating_sub(count)..]
+            .iter()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    pub(crate) fn update(
+        &mut self,
+        output_dir: Option<PathBuf>,
+        enabled: bool,
+        settings: &LogSettings,
+    ) {
+        self.enabled = enabled;
+        self.settings = settings.clone();
+        self.log_file = Self::resolve_log_file(output_dir, enabled, settings);
+    }
+
+    fn resolve_log_file(
+        output_dir: Option<PathBuf>,
+        enabled: bool,
+        settings: &LogSettings,
+    ) -> Option<PathBuf> {
+        if !enabled {
+            return None;
+        }
+
+        let dir = settings.location.directory(output_dir.as_deref())?;
+        if settings.location == LogLocation::OutputDirectory {
+            let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+            Some(dir.join(format!("taskflow_{}.log", timestamp)))
+        } else {
+            Some(dir.join(ROLLING_LOG_NAME))
+        }
+    }
+
+    /// Fixed log directories use a single rolling log instead of one file per session.
+    fn is_rolling(&self) -> bool {
+        self.settings.location != LogLocation::OutputDirectory
+    }
+
+    fn rotate_if_needed(&self, path: &Path, incoming: u64) -> io::Result<()> {
+        let Ok(metadata) = fs::metadata(path) else {
+            return Ok(());
+        };
+        let modified: DateTime<Local> = metadata.modified()?.into();
+
+        let due = match self.settings.rotation {
+            LogRotation::Never => false,
+            LogRotation::Size { max_bytes } => metadata.len() + incoming > max_bytes,
+            LogRotation::Daily => modified.date_naive() != Local::now().date_naive(),
+        };
+        if !due || metadata.len() == 0 {
+            return Ok(());
+        }
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let rotated =
+            Self::unused_rotated_name(dir, &modified.format(ROTATED_STAMP_FORMAT).to_string());
+        fs::rename(path, &rotated)?;
+
+        if self.settings.compress {
+            Self::compress(&rotated)?;
+        }
+
+        self.prune(dir)
+    }
+
+    /// A name for a log rotated at `stamp` that no earlier rotation used,
+    /// compressed or not. Rotations within the same second get a counter.
+    fn unused_rotated_name(dir: &Path, stamp: &str) -> PathBuf {
+        (0..)
+            .map(|n| match n {
+                0 => dir.join(format!("{}{}.log", ROTATED_PREFIX, stamp)),
+                n => dir.join(format!("{}{}_{}.log", ROTATED_PREFIX, stamp, n)),
+            })
+            .find(|path| !path.exists() && !gz_path(path).exists())
+            .expect("some counter is unused")
+    }
+
+    fn compress(path: &Path) -> io::Result<()> {
+        let gz_name = gz_path(path);
+
+        let mut input = File::open(path)?;
+        let output = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&gz_name)?;
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?;
+
+        fs::remove_file(path)
+    }
+
+    /// Deletes the oldest rotated logs beyond the retention count.
+    fn prune(&self, dir: &Path) -> io::Result<()> {
+        let mut rotated: Vec<((String, u32), PathBuf)> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter_map(|path| {
+                let key = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(rotation_key)?;
+                Some((key, path))
+            })
+            .collect();
+
+        rotated.sort();
+
+        let excess = rotated.len().saturating_sub(self.settings.retention);
+        for (_, path) in rotated.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// The timestamp and counter of a rotated log's file name, which sort in
+/// rotation order, or None for any other file.
+fn rotation_key(name: &str) -> Option<(String, u32)> {
+    let rest = name.strip_prefix(ROTATED_PREFIX)?;
+    let rest = rest
+        .strip_suffix(".log.gz")
+        .or_else(|| rest.strip_suffix(".log"))?;
+    let (stamp, counter) = match rest.split_at_checked(15)? {
+        (stamp, "") => (stamp, 0),
+        (stamp, counter) => (stamp, counter.strip_prefix('_')?.parse().ok()?),
+    };
+    chrono::NaiveDateTime::parse_from_str(stamp, ROTATED_STAMP_FORMAT).ok()?;
+    Some((stamp.to_string(), counter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rolling_logger(dir: &Path, retention: usize, compress: bool) -> Logger {
+        let settings = LogSettings {
+            location: LogLocation::Custom(dir.to_path_buf()),
+            rotation: LogRotation::Size { max_bytes: 10 },
+            retention,
+            compress,
+        };
+        Logger::new(None, true, &settings)
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rotations_in_the_same_second_keep_every_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = rolling_logger(dir.path(), 10, true);
+        for message in ["first", "second", "third", "fourth"] {
+            logger.log(message);
+        }
+
+        let names = file_names(dir.path());
+        let rotated: Vec<&String> = names.iter().filter(|name| name.ends_with(".gz")).collect();
+        assert_eq!(rotated.len(), 3, "{:?}", names);
+        assert!(names.contains(&ROLLING_LOG_NAME.to_string()));
+    }
+
+    #[test]
+    fn pruning_leaves_session_logs_and_reports_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let others = [
+            "taskflow_20260101_120000.log",
+            "taskflow_report_20260101_120000.md",
+        ];
+        for name in others {
+            fs::write(dir.path().join(name), "keep").unwrap();
+        }
+
+        let logger = rolling_logger(dir.path(), 1, false);
+        for message in ["first", "second", "third"] {
+            logger.log(message);
+        }
+
+        let names = file_names(dir.path());
+        for name in others {
+            assert!(names.contains(&name.to_string()), "{:?}", names);
+        }
+        let rotated = names.iter().filter(|name| rotation_key(name).is_some());
+        assert_eq!(rotated.count(), 1, "{:?}", names);
+    }
+
+    #[test]
+    fn rotation_key_only_matches_rotated_names() {
+        assert_eq!(
+            rotation_key("taskflow.20260101_120000_2.log.gz"),
+            Some(("20260101_120000".to_string(), 2))
+        );
+        assert!(rotation_key("taskflow.20260101_120000.log").is_some());
+        assert!(rotation_key("taskflow.log").is_none());
+        assert!(rotation_key("taskflow_20260101_120000.log").is_none());
+        assert!(rotation_key("taskflow.notadate_12345.log").is_none());
+    }
+}
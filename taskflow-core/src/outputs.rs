@@ -0,0 +1,120 @@
+use crate::queue::ItemId;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Name of the environment variable pointing scripts at their manifest.
+pub const MANIFEST_VAR: &str = "TASKFLOW_MANIFEST";
+/// Name of the environment variable pointing batch scripts at the list of
+/// their inputs.
+pub const FILE_LIST_VAR: &str = "TASKFLOW_FILE_LIST";
+
+/// Where the script for item `id` may list the files it produced, one per
+/// line. Kept in the output directory so sandboxed scripts can write it.
+pub fn manifest_path(output_dir: &Path, id: ItemId) -> PathBuf {
+    output_dir.join(format!(".taskflow_manifest_{}_{}", std::process::id(), id))
+}
+
+/// Where the inputs of a batch starting with item `id` are listed for a
+/// script that takes them in a file.
+pub fn file_list_path(output_dir: &Path, id: ItemId) -> PathBuf {
+    output_dir.join(format!(".taskflow_files_{}_{}", std::process::id(), id))
+}
+
+/// Reads and removes a manifest. Relative entries are resolved against
+/// `output_dir`, and entries outside it are dropped. Returns None if the
+/// script did not write one.
+pub fn take_manifest(path: &Path, output_dir: &Path) -> Option<Vec<PathBuf>> {
+    let contents = fs::read_to_string(path).ok();
+    let _ = fs::remove_file(path);
+
+    let outputs: Vec<PathBuf> = contents?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| confined(output_dir, Path::new(line)))
+        .collect();
+
+    if outputs.is_empty() {
+        None
+    } else {
+        Some(outputs)
+    }
+}
+
+/// `output` resolved against `output_dir`, provided it stays inside it: it
+/// may not contain `..`, and if absolute must be under `output_dir`.
+pub(crate) fn confined(output_dir: &Path, output: &Path) -> Option<PathBuf> {
+    if output
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return None;
+    }
+    let path = output_dir.join(output);
+    (path.starts_with(output_dir) && path != output_dir).then_some(path)
+}
+
+/// Opens `path` with the desktop's default application.
+pub fn open(path: &Path) -> Result<(), String> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        Command::new("explorer")
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(path);
+
+    spawn_detached(&mut command).map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Shows `path` in the desktop's file manager, selected where supported.
+pub fn reveal(path: &Path) -> Result<(), String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", path.display()));
+        command
+    } else {
+        // xdg-open cannot select a file, so open its folder
+        let mut command = Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(path));
+        command
+    };
+
+    spawn_detached(&mut command).map_err(|e| format!("Failed to reveal {}: {}", path.display(), e))
+}
+
+/// Spawns without waiting, reaping the child in the background.
+fn spawn_detached(command: &mut Command) -> std::io::Result<()> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_keep_only_entries_in_the_output_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = manifest_path(dir.path(), 1);
+        let inside = dir.path().join("b.out");
+        let contents = format!("a.out\n\n../escape\n/etc/passwd\n{}\n", inside.display());
+        fs::write(&manifest, contents).unwrap();
+
+        let outputs = take_manifest(&manifest, dir.path()).unwrap();
+        assert_eq!(outputs, [dir.path().join("a.out"), inside]);
+        assert!(!manifest.exists());
+        assert_eq!(take_manifest(&manifest, dir.path()), None);
+    }
+}
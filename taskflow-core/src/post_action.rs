@@ -0,0 +1,313 @@
+use crate::config::PostAction;
+use crate::invocation::Invocation;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Applies `actions` in order to the source file of `invocation`. A move or
+/// rename changes the file the following actions see.
+pub fn run(actions: &[PostAction], invocation: &Invocation) -> Result<(), String> {
+    let mut source = invocation.input.clone();
+
+    for action in actions {
+        source = apply(action, invocation, &source)
+            .map_err(|e| format!("{} failed: {}", describe(action), e))?;
+    }
+
+    Ok(())
+}
+
+fn describe(action: &PostAction) -> String {
+    match action {
+        PostAction::Move(to) => format!("Move to '{}'", to),
+        PostAction::Copy(to) => format!("Copy to '{}'", to),
+        PostAction::Rename(to) => format!("Rename to '{}'", to),
+        PostAction::Trash => "Move to trash".to_string(),
+    }
+}
+
+/// Applies one action and returns where the source file now is.
+fn apply(action: &PostAction, invocation: &Invocation, source: &Path) -> io::Result<PathBuf> {
+    match action {
+        PostAction::Move(to) => {
+            let destination = destination(invocation, to, source)?;
+            move_file(source, &destination)?;
+            Ok(destination)
+        }
+        PostAction::Copy(to) => {
+            let destination = destination(invocation, to, source)?;
+            fs::copy(source, &destination)?;
+            Ok(source.to_path_buf())
+        }
+        PostAction::Rename(to) => {
+            let name = invocation.expand(to);
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("'{}' is not a file name", name),
+                ));
+            }
+            let destination = source.with_file_name(name);
+            ensure_free(&destination)?;
+            fs::rename(source, &destination)?;
+            Ok(destination)
+        }
+        PostAction::Trash => {
+            trash::trash(source)?;
+            Ok(source.to_path_buf())
+        }
+    }
+}
+
+/// Resolves a destination template for `source`, creating its directory.
+fn destination(invocation: &Invocation, template: &str, source: &Path) -> io::Result<PathBuf> {
+    let expanded = invocation.expand(template);
+    let base = source.parent().unwrap_or(Path::new("."));
+    let mut destination = base.join(&expanded);
+
+    if expanded.ends_with(['/', std::path::MAIN_SEPARATOR]) || destination.is_dir() {
+        let name = source
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "source has no name"))?;
+        destination = destination.join(name);
+    }
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    ensure_free(&destination)?;
+    Ok(destination)
+}
+
+/// Refuses to replace an existing file.
+fn ensure_free(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Renames `from` to `to`, copying when they are on different filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// The freedesktop.org trash (Linux and BSDs).
+#[cfg(all(unix, not(target_os = "macos")))]
+mod trash {
+    use super::move_file;
+    use chrono::Local;
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    pub fn trash(path: &Path) -> io::Result<()> {
+        let trash = dirs::data_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?
+            .join("Trash");
+        let files = trash.join("files");
+        let info = trash.join("info");
+        fs::create_dir_all(&files)?;
+        fs::create_dir_all(&info)?;
+
+        let path = path.canonicalize()?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "source has no name"))?
+            .to_string_lossy()
+            .into_owned();
+
+        // The .trashinfo file is created exclusively to claim a free name
+        for n in 1.. {
+            let candidate = if n == 1 {
+                name.clone()
+            } else {
+                format!("{}.{}", name, n)
+            };
+            let info_path = info.join(format!("{}.trashinfo", candidate));
+            let mut info_file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            };
+
+            write!(
+                info_file,
+                "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                percent_encode(path.as_os_str().as_bytes()),
+                Local::now().format("%Y-%m-%dT%H:%M:%S")
+            )?;
+
+            return move_file(&path, &files.join(&candidate)).inspect_err(|_| {
+                let _ = fs::remove_file(&info_path);
+            });
+        }
+
+        unreachable!()
+    }
+
+    fn percent_encode(bytes: &[u8]) -> String {
+        bytes
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+                    (b as char).to_string()
+                } else {
+                    format!("%{:02X}", b)
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod trash {
+    use super::move_file;
+    use std::io;
+    use std::path::Path;
+
+    pub fn trash(path: &Path) -> io::Result<()> {
+        let trash = dirs::home_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?
+            .join(".Trash");
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "source has no name"))?
+            .to_string_lossy()
+            .into_owned();
+
+        let destination = (1..)
+            .map(|n| {
+                if n == 1 {
+                    trash.join(&name)
+                } else {
+                    trash.join(format!("{} {}", name, n))
+                }
+            })
+            .find(|candidate| !candidate.exists())
+            .unwrap();
+        move_file(path, &destination)
+    }
+}
+
+#[cfg(not(unix))]
+mod trash {
+    use std::io;
+    use std::path::Path;
+
+    pub fn trash(_path: &Path) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "moving to the trash is not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScriptSettings;
+
+    /// An item for `dir/in/photo.jpg`, which is created.
+    fn item(dir: &Path) -> Invocation {
+        let input = dir.join("in/photo.jpg");
+        fs::create_dir_all(input.parent().unwrap()).unwrap();
+        fs::write(&input, "photo").unwrap();
+        Invocation::resolve(
+            "/scripts/convert.sh",
+            &input,
+            &dir.join("out"),
+            &ScriptSettings::default(),
+        )
+    }
+
+    #[test]
+    fn moves_into_a_directory_keep_the_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = item(dir.path());
+
+        run(&[PostAction::Move("processed/".to_string())], &invocation).unwrap();
+        assert!(!invocation.input.exists());
+        assert!(dir.path().join("in/processed/photo.jpg").exists());
+    }
+
+    #[test]
+    fn destinations_are_templated() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = item(dir.path());
+        let archive = dir.path().join("archive");
+
+        let actions = [
+            PostAction::Copy(format!("{}/{{stem}}.orig.{{ext}}", archive.display())),
+            PostAction::Rename("{stem}.done.{ext}".to_string()),
+        ];
+        run(&actions, &invocation).unwrap();
+        assert_eq!(
+            fs::read_to_string(archive.join("photo.orig.jpg")).unwrap(),
+            "photo"
+        );
+        assert!(!invocation.input.exists());
+        assert!(dir.path().join("in/photo.done.jpg").exists());
+    }
+
+    #[test]
+    fn later_actions_follow_the_moved_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = item(dir.path());
+
+        let actions = [
+            PostAction::Move("done/".to_string()),
+            PostAction::Rename("{name}.bak".to_string()),
+        ];
+        run(&actions, &invocation).unwrap();
+        assert!(dir.path().join("in/done/photo.jpg.bak").exists());
+    }
+
+    #[test]
+    fn existing_files_are_not_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = item(dir.path());
+        let taken = dir.path().join("in/processed/photo.jpg");
+        fs::create_dir_all(taken.parent().unwrap()).unwrap();
+        fs::write(&taken, "earlier").unwrap();
+
+        let error = run(&[PostAction::Move("processed/".to_string())], &invocation).unwrap_err();
+        assert_eq!(
+            error,
+            format!(
+                "Move to 'processed/' failed: {} already exists",
+                taken.display()
+            )
+        );
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "earlier");
+        assert!(invocation.input.exists());
+    }
+
+    #[test]
+    fn renames_stay_in_the_same_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = item(dir.path());
+
+        let error = run(&[PostAction::Rename("../{name}".to_string())], &invocation).unwrap_err();
+        assert_eq!(
+            error,
+            "Rename to '../{name}' failed: '../photo.jpg' is not a file name"
+        );
+        assert!(invocation.input.exists());
+    }
+}
@@ -0,0 +1,401 @@
+//! Checks made before a run starts, to catch problems early.
+
+use crate::builtin::{self, Builtin};
+use crate::config::{Config, ExecutorSettings, ResourceLimits, ScriptSettings, WorkerSettings};
+use crate::dry_run::DryRun;
+use crate::queue::Queue;
+use crate::report::format_bytes;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// How serious a finding is. Errors sort first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The run can't start.
+    Error,
+    /// The run can start, but something may go wrong.
+    Warning,
+}
+
+/// A problem found before a run starts. Errors block the run.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    /// Whether it blocks the run.
+    pub severity: Severity,
+    /// What is wrong, for display.
+    pub message: String,
+}
+
+impl Finding {
+    /// A finding that blocks the run.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    /// A finding that doesn't block the run.
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+/// The result of validating the queue and settings before a run.
+pub struct Preflight {
+    /// Everything found, errors first.
+    pub findings: Vec<Finding>,
+}
+
+impl Preflight {
+    /// Checks the pending items of `queue` and the settings they run with.
+    pub fn check(queue: &Queue, config: &Config) -> Self {
+        let dry_run = DryRun::new(queue, config);
+
+        let mut findings = dry_run.problems;
+        if dry_run.rows.is_empty() {
+            findings.push(Finding::error("No pending items in the queue"));
+        }
+        for row in dry_run.rows {
+            let filename = row.filename();
+            findings.extend(row.problems.into_iter().map(|finding| Finding {
+                message: format!("{}: {}", filename, finding.message),
+                ..finding
+            }));
+        }
+        findings.sort_by_key(|finding| finding.severity);
+
+        Self { findings }
+    }
+
+    /// Whether nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Number of findings that block the run.
+    pub fn error_count(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    /// Number of findings that don't block the run.
+    pub fn warning_count(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    }
+}
+
+/// Checks that apply to every item of a script: the script, its
+/// interpreter and executor, and the output directory. `scripts` are the
+/// scripts the pending items run with, and `input_bytes` is their total size.
+pub(crate) fn check_run(
+    config: &Config,
+    scripts: &[Option<&str>],
+    input_bytes: u64,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for script in scripts {
+        match *script {
+            Some(script) if Builtin::parse(script).is_some() => {}
+            Some(script) if script.starts_with(builtin::PREFIX) => {
+                findings.push(Finding::error(format!("Unknown built-in {}", script)));
+            }
+            Some(script) => {
+                let settings = config.script_settings(script);
+                let local = settings.executor == ExecutorSettings::Local;
+                findings.extend(check_script(Path::new(script), local));
+                findings.extend(check_executor(&settings));
+                if settings.batch.is_some() && !config.workers.local {
+                    findings.push(Finding::error(
+                        "Batch scripts can't run on agents, and the local worker is disabled",
+                    ));
+                }
+            }
+            None => findings.push(Finding::error("No script selected")),
+        }
+    }
+
+    match config.output_directory.as_deref() {
+        Some(dir) => findings.extend(check_output_dir(dir, input_bytes)),
+        None => findings.push(Finding::error("No output directory selected")),
+    }
+
+    findings.extend(check_workers(&config.workers));
+    findings
+}
+
+fn check_workers(workers: &WorkerSettings) -> Vec<Finding> {
+    if !workers.local && workers.agents.is_empty() {
+        return vec![Finding::error(
+            "No workers: the local worker is disabled and no agents are configured",
+        )];
+    }
+    workers
+        .agents
+        .iter()
+        .filter(|agent| agent.secret.is_empty())
+        .map(|agent| Finding::error(format!("Agent {} has no secret", agent.address)))
+        .collect()
+}
+
+/// The interpreter is only checked for scripts run on this machine.
+fn check_script(script: &Path, local: bool) -> Option<Finding> {
+    if !script.exists() {
+        return Some(Finding::error(format!(
+            "Script {} not found",
+            script.display()
+        )));
+    }
+    if !script.is_file() {
+        return Some(Finding::error(format!(
+            "Script {} is not a file",
+            script.display()
+        )));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = script.metadata()
+            && metadata.permissions().mode() & 0o111 == 0
+        {
+            return Some(Finding::error(format!(
+                "Script {} is not executable",
+                script.display()
+            )));
+        }
+    }
+
+    if local {
+        check_interpreter(script)
+    } else {
+        None
+    }
+}
+
+/// Checks that the programs a non-local executor needs are installed.
+fn check_executor(settings: &ScriptSettings) -> Vec<Finding> {
+    let programs: &[&str] = match settings.executor {
+        ExecutorSettings::Local => return Vec::new(),
+        ExecutorSettings::Container { runtime, .. } => &[runtime.program()],
+        ExecutorSettings::Ssh { .. } => &["ssh", "scp", "tar"],
+    };
+
+    let mut findings: Vec<Finding> = programs
+        .iter()
+        .filter(|program| find_on_path(program).is_none())
+        .map(|program| Finding::error(format!("'{}' was not found on PATH", program)))
+        .collect();
+
+    if settings.limits != ResourceLimits::default() || settings.sandbox.enabled {
+        findings.push(Finding::warning(
+            "Resource limits and the sandbox only apply to local scripts and will be ignored",
+        ));
+    }
+    findings
+}
+
+/// Checks that the interpreter named in the script's `#!` line exists.
+fn check_interpreter(script: &Path) -> Option<Finding> {
+    let file = fs::File::open(script).ok()?;
+    let mut first_line = Vec::new();
+    // Only the first line matters; don't read a whole binary
+    BufReader::new(file)
+        .take(256)
+        .read_until(b'\n', &mut first_line)
+        .ok()?;
+    let line = String::from_utf8_lossy(&first_line);
+    let shebang = line.strip_prefix("#!")?.trim();
+
+    let mut parts = shebang.split_whitespace();
+    let mut interpreter = parts.next()?;
+    // `#!/usr/bin/env python3` looks the real interpreter up on PATH
+    if Path::new(interpreter)
+        .file_name()
+        .is_some_and(|n| n == "env")
+    {
+        interpreter = parts.find(|part| !part.starts_with('-'))?;
+    }
+
+    let found = if interpreter.contains('/') {
+        Path::new(interpreter).is_file()
+    } else {
+        find_on_path(interpreter).is_some()
+    };
+
+    if found {
+        None
+    } else {
+        Some(Finding::error(format!(
+            "Interpreter '{}' from the script's #! line was not found",
+            interpreter
+        )))
+    }
+}
+
+fn find_on_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+fn check_output_dir(dir: &Path, input_bytes: u64) -> Vec<Finding> {
+    if !dir.is_dir() {
+        return vec![Finding::error(format!(
+            "Output directory {} does not exist",
+            dir.display()
+        ))];
+    }
+
+    let probe = dir.join(format!(".taskflow_write_test_{}", std::process::id()));
+    if let Err(e) = fs::write(&probe, b"") {
+        return vec![Finding::error(format!(
+            "Output directory {} is not writable: {}",
+            dir.display(),
+            e
+        ))];
+    }
+    let _ = fs::remove_file(&probe);
+
+    let mut findings = Vec::new();
+    if let Some(free) = free_space(dir)
+        && free < input_bytes
+    {
+        findings.push(Finding::warning(format!(
+            "Output directory has {} free, less than the {} of input",
+            format_bytes(free),
+            format_bytes(input_bytes)
+        )));
+    }
+    findings
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `dir`.
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path is NUL-terminated and stat is only read after success
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A config running a shell script into an existing output directory.
+    fn config(dir: &Path) -> Config {
+        let script = dir.join("convert.sh");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::create_dir(dir.join("out")).unwrap();
+        Config {
+            selected_script: Some(script.to_string_lossy().into_owned()),
+            output_directory: Some(dir.join("out")),
+            ..Config::default()
+        }
+    }
+
+    fn errors(preflight: &Preflight) -> Vec<&str> {
+        preflight
+            .findings
+            .iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn runs_with_readable_inputs_are_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let mut queue = Queue::new();
+        queue.add(dir.path().join("a.txt"));
+
+        let preflight = Preflight::check(&queue, &config);
+        assert!(preflight.is_clean(), "{:?}", preflight.findings);
+    }
+
+    #[test]
+    fn missing_inputs_block_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let mut queue = Queue::new();
+        queue.add(dir.path().join("a.txt"));
+        queue.add(dir.path().join("gone.txt"));
+
+        let preflight = Preflight::check(&queue, &config);
+        assert_eq!(errors(&preflight), ["gone.txt: Input file not found"]);
+    }
+
+    #[test]
+    fn unreadable_inputs_block_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+        let input = dir.path().join("locked.txt");
+        fs::write(&input, "a").unwrap();
+        fs::set_permissions(&input, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::File::open(&input).is_ok() {
+            // Permissions don't stop root
+            return;
+        }
+        let mut queue = Queue::new();
+        queue.add(input);
+
+        let preflight = Preflight::check(&queue, &config);
+        assert_eq!(preflight.error_count(), 1);
+        assert!(errors(&preflight)[0].starts_with("locked.txt: Input file is not readable"));
+    }
+
+    #[test]
+    fn missing_scripts_and_output_dirs_block_the_run() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let mut queue = Queue::new();
+        queue.add(dir.path().join("a.txt"));
+        let config = Config {
+            selected_script: Some("/no/such/script.sh".to_string()),
+            output_directory: Some(dir.path().join("no-such-dir")),
+            ..Config::default()
+        };
+
+        let preflight = Preflight::check(&queue, &config);
+        let no_dir = format!(
+            "Output directory {} does not exist",
+            dir.path().join("no-such-dir").display()
+        );
+        assert_eq!(
+            errors(&preflight),
+            ["Script /no/such/script.sh not found", no_dir.as_str()]
+        );
+    }
+}
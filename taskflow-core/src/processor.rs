@@ -0,0 +1,1008 @@
+use crate::builtin::Builtin;
+use crate::config::{Hook, Hooks, ResultProtocol, ScriptSettings, WorkerSettings};
+use crate::executor::{self, Executor, Exit, RemoteExecutor, Running};
+use crate::hooks::{self, HookKind};
+use crate::invocation::Invocation;
+use crate::outputs;
+use crate::post_action;
+use crate::queue::ItemId;
+use crate::results::{self, FileResult};
+use crate::verify;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::SystemTime;
+
+#[derive(Debug)]
+pub enum ProcessorMessage {
+    Process {
+        /// The items the script runs for, each with its own invocation for
+        /// hooks and post-actions.
+        items: Vec<(ItemId, Invocation)>,
+        /// The one item's invocation, or a batch invocation covering them all.
+        invocation: Box<Invocation>,
+        settings: Box<ScriptSettings>,
+        /// Hooks to run around each item; run-level hooks are ignored.
+        hooks: Box<Hooks>,
+    },
+    RunHook {
+        kind: HookKind,
+        hook: Hook,
+        env: Vec<(String, String)>,
+    },
+}
+
+#[derive(Debug)]
+pub enum ProcessorResult {
+    Started(ItemId),
+    /// The script succeeded, producing these files.
+    Success(ItemId, Vec<PathBuf>),
+    Error(ItemId, String),
+    /// A post-action on the source file failed. Sent before the item's
+    /// `Success` or `Error`, which reflect the script alone.
+    PostActionFailed(ItemId, String),
+    /// Warnings the script reported for the item, through its exit code or
+    /// the result protocol. Sent before the item's `Success` or `Error`.
+    Warnings(ItemId, Vec<String>),
+    /// The item's worker was lost before it finished, e.g. an agent stopped
+    /// responding, so it should be dispatched again.
+    Requeued(ItemId, String),
+    /// A hook finished. `item` is set for item hooks.
+    HookFinished {
+        kind: HookKind,
+        item: Option<ItemId>,
+        result: Result<(), String>,
+    },
+}
+
+pub struct Processor {
+    workers: Vec<Worker>,
+    rx: Receiver<ProcessorResult>,
+    // The worker each unfinished item was handed to
+    assigned: HashMap<ItemId, usize>,
+}
+
+/// A thread that runs one item, or one batch, at a time.
+struct Worker {
+    tx: Sender<ProcessorMessage>,
+    running: Arc<Running>,
+    // Overrides the executor from the script settings; agents' health
+    // decides whether they are given items
+    executor: Option<Arc<dyn Executor>>,
+    // Agents take a single input per job, so are never given batches
+    agent: bool,
+}
+
+impl Processor {
+    /// A processor with the workers in `settings`. The local worker runs each
+    /// script with the executor its settings ask for.
+    pub fn new(settings: &WorkerSettings) -> Self {
+        Self::spawn(settings, None)
+    }
+
+    /// Like `new`, but the local worker runs every script with `executor`,
+    /// whatever its settings say.
+    pub fn with_executor(settings: &WorkerSettings, executor: Arc<dyn Executor>) -> Self {
+        Self::spawn(settings, Some(executor))
+    }
+
+    fn spawn(settings: &WorkerSettings, local: Option<Arc<dyn Executor>>) -> Self {
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+
+        let mut executors = Vec::new();
+        if settings.local {
+            executors.push((local, false));
+        }
+        for agent in &settings.agents {
+            let remote: Arc<dyn Executor> = Arc::new(RemoteExecutor::new(agent.clone()));
+            executors.extend((0..agent.slots.max(1)).map(|_| (Some(Arc::clone(&remote)), true)));
+        }
+
+        let workers = executors
+            .into_iter()
+            .map(|(executor, agent)| {
+                let (msg_tx, msg_rx) = std::sync::mpsc::channel();
+                let running = Arc::new(Running::default());
+
+                // Spawn worker thread
+                let (result_tx, worker_running, worker_executor) =
+                    (result_tx.clone(), Arc::clone(&running), executor.clone());
+                thread::spawn(move || {
+                    Self::worker_thread(msg_rx, result_tx, worker_running, worker_executor);
+                });
+
+                Worker {
+                    tx: msg_tx,
+                    running,
+                    executor,
+                    agent,
+                }
+            })
+            .collect();
+
+        Self {
+            workers,
+            rx: result_rx,
+            assigned: HashMap::new(),
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Whether `process` has a worker to hand an item to.
+    pub fn has_idle_worker(&self) -> bool {
+        self.idle_worker(false).is_some()
+    }
+
+    /// Whether `process_batch` has a worker to hand a batch to.
+    pub fn has_idle_batch_worker(&self) -> bool {
+        self.idle_worker(true).is_some()
+    }
+
+    fn idle_worker(&self, batch: bool) -> Option<usize> {
+        (0..self.workers.len()).find(|index| {
+            let worker = &self.workers[*index];
+            let healthy = worker
+                .executor
+                .as_ref()
+                .is_none_or(|executor| executor.healthy());
+            healthy && !(batch && worker.agent) && !self.assigned.values().any(|busy| busy == index)
+        })
+    }
+
+    /// Hands an item to an idle worker.
+    pub fn process(
+        &mut self,
+        id: ItemId,
+        invocation: Invocation,
+        settings: ScriptSettings,
+        hooks: Hooks,
+    ) -> Result<(), String> {
+        let items = vec![(id, invocation.clone())];
+        self.dispatch(false, items, invocation, settings, hooks)
+    }
+
+    /// Hands a batch script to an idle worker that isn't an agent, to run
+    /// once with `invocation` for all of `items`.
+    pub fn process_batch(
+        &mut self,
+        items: Vec<(ItemId, Invocation)>,
+        invocation: Invocation,
+        settings: ScriptSettings,
+        hooks: Hooks,
+    ) -> Result<(), String> {
+        self.dispatch(true, items, invocation, settings, hooks)
+    }
+
+    fn dispatch(
+        &mut self,
+        batch: bool,
+        items: Vec<(ItemId, Invocation)>,
+        invocation: Invocation,
+        settings: ScriptSettings,
+        hooks: Hooks,
+    ) -> Result<(), String> {
+        let index = self
+            .idle_worker(batch)
+            .ok_or_else(|| "No worker is available".to_string())?;
+        let ids: Vec<ItemId> = items.iter().map(|(id, _)| *id).collect();
+        self.workers[index]
+            .tx
+            .send(ProcessorMessage::Process {
+                items,
+                invocation: Box::new(invocation),
+                settings: Box::new(settings),
+                hooks: Box::new(hooks),
+            })
+            .map_err(|e| format!("Failed to send process message: {}", e))?;
+        self.assigned.extend(ids.into_iter().map(|id| (id, index)));
+        Ok(())
+    }
+
+    /// Queues a run-level hook behind any items already sent to the first
+    /// worker. Hooks always run on this machine.
+    pub fn run_hook(
+        &self,
+        kind: HookKind,
+        hook: Hook,
+        env: Vec<(String, String)>,
+    ) -> Result<(), String> {
+        self.first_worker()?
+            .tx
+            .send(ProcessorMessage::RunHook { kind, hook, env })
+            .map_err(|e| format!("Failed to send hook message: {}", e))
+    }
+
+    fn first_worker(&self) -> Result<&Worker, String> {
+        self.workers
+            .first()
+            .ok_or_else(|| "No workers are configured".to_string())
+    }
+
+    pub fn try_recv_result(&mut self) -> Option<ProcessorResult> {
+        let result = self.rx.try_recv().ok()?;
+        if let ProcessorResult::Success(id, _)
+        | ProcessorResult::Error(id, _)
+        | ProcessorResult::Requeued(id, _) = result
+        {
+            self.assigned.remove(&id);
+        }
+        Some(result)
+    }
+
+    /// Stops the running scripts (and anything they spawned) with SIGSTOP.
+    /// Scripts on agents keep running. Every worker is signalled even if
+    /// some fail; the errors are reported together.
+    pub fn suspend(&self) -> Result<(), String> {
+        Self::signal_all(self.workers.iter().map(|worker| {
+            Self::signal(&worker.running, Signal::Stop)?;
+            worker.running.suspended.store(true, Ordering::SeqCst);
+            Ok(())
+        }))
+    }
+
+    /// Continues scripts previously stopped with `suspend`.
+    pub fn resume(&self) -> Result<(), String> {
+        Self::signal_all(self.workers.iter().map(|worker| {
+            worker.running.suspended.store(false, Ordering::SeqCst);
+            Self::signal(&worker.running, Signal::Continue)
+        }))
+    }
+
+    fn signal_all(results: impl Iterator<Item = Result<(), String>>) -> Result<(), String> {
+        let mut errors: Vec<String> = Vec::new();
+        for error in results.filter_map(Result::err) {
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    #[cfg(unix)]
+    fn signal(running: &Running, signal: Signal) -> Result<(), String> {
+        let Some(pid) = *running.pid.lock().unwrap() else {
+            return Ok(());
+        };
+
+        let signal = match signal {
+            Signal::Stop => libc::SIGSTOP,
+            Signal::Continue => libc::SIGCONT,
+        };
+
+        // Scripts run in their own process group; signal the whole group
+        // SAFETY: kill has no memory safety requirements
+        if unsafe { libc::kill(-(pid as libc::pid_t), signal) } == 0 {
+            Ok(())
+        } else {
+            Err(format!(
+                "Failed to signal script: {}",
+                std::io::Error::last_os_error()
+            ))
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn signal(_running: &Running, _signal: Signal) -> Result<(), String> {
+        Err("Suspending scripts is not supported on this platform".to_string())
+    }
+
+    fn worker_thread(
+        rx: Receiver<ProcessorMessage>,
+        tx: Sender<ProcessorResult>,
+        running: Arc<Running>,
+        executor: Option<Arc<dyn Executor>>,
+    ) {
+        while let Ok(msg) = rx.recv() {
+            match msg {
+                ProcessorMessage::Process {
+                    items,
+                    invocation,
+                    settings,
+                    hooks,
+                } => {
+                    for (id, _) in &items {
+                        let _ = tx.send(ProcessorResult::Started(*id));
+                    }
+
+                    let mut blocked = None;
+                    if let Some(hook) = hooks.get(HookKind::BeforeItem) {
+                        for (id, item) in &items {
+                            let env = hooks::item_env(HookKind::BeforeItem, item, &[], None);
+                            let result = Self::execute_hook(hook, env, Some(item), &running);
+                            if hook.abort_on_failure
+                                && let Err(ref e) = result
+                            {
+                                blocked.get_or_insert(format!("before_item hook failed: {}", e));
+                            }
+                            let _ = tx.send(ProcessorResult::HookFinished {
+                                kind: HookKind::BeforeItem,
+                                item: Some(*id),
+                                result,
+                            });
+                        }
+                    }
+
+                    // Built-ins always run here, whatever the worker
+                    let executor: Arc<dyn Executor> = match Builtin::parse(&invocation.script) {
+                        Some(builtin) => Arc::new(builtin),
+                        None => executor.clone().unwrap_or_else(|| {
+                            Arc::from(executor::for_settings(&settings.executor))
+                        }),
+                    };
+                    let outcomes = match (blocked, items.first()) {
+                        (Some(error), _) => vec![ItemOutcome::failed(error); items.len()],
+                        (None, Some((first, _))) => Self::execute_script(
+                            *first,
+                            &items,
+                            &invocation,
+                            &settings,
+                            executor.as_ref(),
+                            &running,
+                        ),
+                        (None, None) => continue,
+                    };
+
+                    // The items never really ran; give them to another worker
+                    if !executor.healthy()
+                        && outcomes.iter().all(|outcome| outcome.result.is_err())
+                        && let Some(Err(e)) = outcomes.first().map(|outcome| &outcome.result)
+                    {
+                        for (id, _) in &items {
+                            let _ = tx.send(ProcessorResult::Requeued(*id, e.clone()));
+                        }
+                        continue;
+                    }
+
+                    for ((id, item), outcome) in items.iter().zip(outcomes) {
+                        let result = outcome.result;
+                        let post_actions = match result {
+                            Ok(_) => &settings.post_actions.on_success,
+                            Err(_) => &settings.post_actions.on_failure,
+                        };
+                        if let Err(e) = post_action::run(post_actions, item) {
+                            let _ = tx.send(ProcessorResult::PostActionFailed(*id, e));
+                        }
+
+                        let (kind, env) = match result {
+                            Ok(ref outputs) => (
+                                HookKind::AfterItemSuccess,
+                                hooks::item_env(HookKind::AfterItemSuccess, item, outputs, None),
+                            ),
+                            Err(ref e) => (
+                                HookKind::AfterItemFailure,
+                                hooks::item_env(HookKind::AfterItemFailure, item, &[], Some(e)),
+                            ),
+                        };
+                        if let Some(hook) = hooks.get(kind) {
+                            let result = Self::execute_hook(hook, env, Some(item), &running);
+                            let _ = tx.send(ProcessorResult::HookFinished {
+                                kind,
+                                item: Some(*id),
+                                result,
+                            });
+                        }
+
+                        if !outcome.warnings.is_empty() {
+                            let _ = tx.send(ProcessorResult::Warnings(*id, outcome.warnings));
+                        }
+                        let result_msg = match result {
+                            Ok(outputs) => ProcessorResult::Success(*id, outputs),
+                            Err(e) => ProcessorResult::Error(*id, e),
+                        };
+
+                        let _ = tx.send(result_msg);
+                    }
+                }
+                ProcessorMessage::RunHook { kind, hook, env } => {
+                    let result = Self::execute_hook(&hook, env, None, &running);
+                    let _ = tx.send(ProcessorResult::HookFinished {
+                        kind,
+                        item: None,
+                        result,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Runs the script once for `items` and returns how each one ended: as
+    /// its line of the result protocol says if the script printed one,
+    /// otherwise as the script did. Outputs are verified once for the run.
+    fn execute_script(
+        id: ItemId,
+        items: &[(ItemId, Invocation)],
+        invocation: &Invocation,
+        settings: &ScriptSettings,
+        executor: &dyn Executor,
+        running: &Running,
+    ) -> Vec<ItemOutcome> {
+        let manifest = outputs::manifest_path(&invocation.output_dir, id);
+        if let Some(ref list) = invocation.file_list {
+            let contents: String = invocation
+                .inputs
+                .iter()
+                .map(|input| format!("{}\n", input.display()))
+                .collect();
+            if let Err(e) = fs::write(list, contents) {
+                let error = format!("Failed to execute script: could not write file list: {}", e);
+                return vec![ItemOutcome::failed(error); items.len()];
+            }
+        }
+        let started = SystemTime::now();
+        let execution = executor.execute(invocation, settings, &manifest, running);
+        if let Some(ref list) = invocation.file_list {
+            let _ = fs::remove_file(list);
+        }
+
+        // Without a manifest, only the output this run was told to write is
+        // its own; other files in the directory may be another worker's
+        let produced =
+            outputs::take_manifest(&manifest, &invocation.output_dir).unwrap_or_else(|| {
+                invocation
+                    .expected_output
+                    .iter()
+                    .filter(|output| output.is_file())
+                    .cloned()
+                    .collect()
+            });
+
+        let reported = match settings.results {
+            ResultProtocol::ExitStatus => Vec::new(),
+            ResultProtocol::JsonLines => results::parse(&execution.stdout),
+        };
+        let lines: Vec<Option<&FileResult>> = items
+            .iter()
+            .map(|(_, item)| results::find(&reported, &item.input, items.len() == 1))
+            .collect();
+        let script_results: Vec<Result<(), String>> = lines
+            .iter()
+            .map(|line| match line {
+                Some(line) => line.result(),
+                None => execution.result.clone(),
+            })
+            .collect();
+
+        let verified = if script_results.iter().any(Result::is_ok) {
+            verify::verify(
+                invocation,
+                &settings.verify,
+                started,
+                settings.timeout_secs,
+                running,
+            )
+            .map(drop)
+            .map_err(|e| format!("Verification failed: {}", e))
+        } else {
+            Ok(())
+        };
+        // In a batch, an output named after exactly one input is that
+        // input's; a stem shared by several inputs can't be told apart
+        let inputs: Vec<&Path> = items.iter().map(|(_, item)| item.input.as_path()).collect();
+        let owner = |output: &Path| {
+            let mut owners =
+                (0..inputs.len()).filter(|&i| inputs[i].file_stem() == output.file_stem());
+            owners.next().filter(|_| owners.next().is_none())
+        };
+        // A warning naming inputs, by path or else by file name, is about
+        // those; any other is about the run
+        let about = |warning: &str, index: usize| {
+            let by_path = |input: &Path| warning.contains(&*input.to_string_lossy());
+            let by_name = |input: &Path| names_file(warning, input);
+            if inputs.iter().any(|input| by_path(input)) {
+                by_path(inputs[index])
+            } else if inputs.iter().any(|input| by_name(input)) {
+                by_name(inputs[index])
+            } else {
+                true
+            }
+        };
+
+        lines
+            .into_iter()
+            .zip(script_results)
+            .enumerate()
+            .map(|(index, (line, result))| {
+                let outputs = match line.and_then(|line| line.outputs_in(&invocation.output_dir)) {
+                    Some(outputs) => outputs,
+                    None if items.len() == 1 => produced.clone(),
+                    None => produced
+                        .iter()
+                        .filter(|output| owner(output) == Some(index))
+                        .cloned()
+                        .collect(),
+                };
+                ItemOutcome {
+                    result: result.and_then(|()| verified.clone()).map(|()| outputs),
+                    warnings: execution
+                        .warnings
+                        .iter()
+                        .filter(|warning| about(warning, index))
+                        .chain(line.iter().flat_map(|line| &line.warnings))
+                        .cloned()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Runs a hook. Item hooks may use the placeholders of `invocation`.
+    fn execute_hook(
+        hook: &Hook,
+        env: Vec<(String, String)>,
+        invocation: Option<&Invocation>,
+        running: &Running,
+    ) -> Result<(), String> {
+        let words: Vec<String> = match invocation {
+            Some(invocation) => hook.command.iter().map(|w| invocation.expand(w)).collect(),
+            None => hook.command.clone(),
+        };
+
+        let mut command = Command::new(&words[0]);
+        command.args(&words[1..]).envs(env);
+
+        let exit = executor::run(&mut command, hook.timeout_secs, running)
+            .map_err(|e| format!("could not run '{}': {}", words[0], e))?;
+
+        match exit {
+            Exit::Finished(output) if output.status.success() => Ok(()),
+            Exit::Finished(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(match stderr.trim() {
+                    "" => output.status.to_string(),
+                    message => message.to_string(),
+                })
+            }
+            Exit::TimedOut(after) => Err(format!("timed out after {}s", after.as_secs())),
+        }
+    }
+}
+
+/// How one item of a run ended.
+#[derive(Debug, Clone)]
+struct ItemOutcome {
+    result: Result<Vec<PathBuf>, String>,
+    warnings: Vec<String>,
+}
+
+impl ItemOutcome {
+    fn failed(error: String) -> Self {
+        Self {
+            result: Err(error),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Stop,
+    Continue,
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Self::new(&WorkerSettings::default())
+    }
+}
+
+/// Whether `warning` names the file name of `input` as a whole word.
+fn names_file(warning: &str, input: &Path) -> bool {
+    let Some(name) = input.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+    let part_of_name = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+    warning.match_indices(&*name).any(|(at, _)| {
+        let before = warning[..at].chars().next_back();
+        let after = warning[at + name.len()..].chars().next();
+        !before.is_some_and(part_of_name) && !after.is_some_and(|c| c.is_alphanumeric())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{self, AgentOptions};
+    use crate::config::{AgentSettings, BatchInput, Staging};
+    use std::io;
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::path::Path;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    const SECRET: &str = "swordfish";
+
+    /// Starts an agent on a free port, returning its address.
+    fn start_agent(work_dir: &Path) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let options = AgentOptions {
+            listen: address.clone(),
+            secret: SECRET.to_string(),
+            work_dir: work_dir.to_path_buf(),
+        };
+        fs::create_dir_all(work_dir).unwrap();
+        thread::spawn(move || agent::accept(listener, options, |_| {}));
+        address
+    }
+
+    /// Forwards connections to an agent until killed, so the agent can be
+    /// made to vanish mid-item.
+    struct Relay {
+        address: String,
+        streams: Arc<Mutex<Vec<TcpStream>>>,
+    }
+
+    impl Relay {
+        fn to(agent: String) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let streams = Arc::new(Mutex::new(Vec::new()));
+            let open = Arc::clone(&streams);
+            thread::spawn(move || {
+                for client in listener.incoming() {
+                    let client = client.unwrap();
+                    let upstream = TcpStream::connect(&agent).unwrap();
+                    open.lock()
+                        .unwrap()
+                        .extend([client.try_clone().unwrap(), upstream.try_clone().unwrap()]);
+                    for (mut from, mut to) in [
+                        (client.try_clone().unwrap(), upstream.try_clone().unwrap()),
+                        (upstream, client),
+                    ] {
+                        thread::spawn(move || {
+                            let _ = io::copy(&mut from, &mut to);
+                            let _ = to.shutdown(Shutdown::Write);
+                        });
+                    }
+                }
+            });
+            Self { address, streams }
+        }
+
+        fn connections(&self) -> usize {
+            self.streams.lock().unwrap().len() / 2
+        }
+
+        fn kill(&self) {
+            for stream in self.streams.lock().unwrap().drain(..) {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    fn agent_settings(address: String, staging: Staging) -> AgentSettings {
+        AgentSettings {
+            address,
+            secret: SECRET.to_string(),
+            staging,
+            slots: 1,
+        }
+    }
+
+    fn next_result(processor: &mut Processor) -> ProcessorResult {
+        let deadline = Instant::now() + Duration::from_secs(20);
+        loop {
+            if let Some(result) = processor.try_recv_result() {
+                return result;
+            }
+            assert!(Instant::now() < deadline, "no result from the processor");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Results up to and including the one that settles `id`.
+    fn settle(processor: &mut Processor, id: ItemId) -> ProcessorResult {
+        loop {
+            match next_result(processor) {
+                result @ (ProcessorResult::Success(done, _)
+                | ProcessorResult::Error(done, _)
+                | ProcessorResult::Requeued(done, _))
+                    if done == id =>
+                {
+                    return result;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn items_lost_with_an_agent_finish_on_another() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (input_dir, output_dir) = (dir.path().join("in"), dir.path().join("out"));
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::create_dir_all(&output_dir).unwrap();
+        let script = dir.path().join("convert.sh");
+        fs::write(
+            &script,
+            "#!/bin/sh\n\
+             case \"$1\" in *slow*) sleep 5 ;; esac\n\
+             cp \"$1\" \"$2/$(basename \"$1\" .txt).out\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let script = script.to_string_lossy().into_owned();
+
+        // The first worker uploads to an agent behind a relay, the second
+        // shares this machine's paths with its agent
+        let relay = Relay::to(start_agent(&dir.path().join("agent-a")));
+        let workers = WorkerSettings {
+            local: false,
+            agents: vec![
+                agent_settings(relay.address.clone(), Staging::Upload),
+                agent_settings(start_agent(&dir.path().join("agent-b")), Staging::Shared),
+            ],
+        };
+        let mut processor = Processor::new(&workers);
+        let settings = ScriptSettings {
+            output_name: Some("{stem}.out".to_string()),
+            ..ScriptSettings::default()
+        };
+        let dispatch = |processor: &mut Processor, id: ItemId, name: &str| {
+            let input = input_dir.join(name);
+            fs::write(&input, name).unwrap();
+            let invocation = Invocation::resolve(&script, &input, &output_dir, &settings);
+            processor
+                .process(id, invocation, settings.clone(), Hooks::default())
+                .unwrap();
+        };
+
+        dispatch(&mut processor, 1, "fast.txt");
+        let fast = output_dir.join("fast.out");
+        match settle(&mut processor, 1) {
+            ProcessorResult::Success(_, outputs) => assert_eq!(outputs, [fast.as_path()]),
+            _ => panic!("fast.txt did not succeed"),
+        }
+        assert_eq!(fs::read_to_string(&fast).unwrap(), "fast.txt");
+
+        dispatch(&mut processor, 2, "slow.txt");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while relay.connections() < 2 {
+            assert!(
+                Instant::now() < deadline,
+                "slow.txt never reached the agent"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        relay.kill();
+        assert!(matches!(
+            settle(&mut processor, 2),
+            ProcessorResult::Requeued(..)
+        ));
+
+        dispatch(&mut processor, 2, "slow.txt");
+        let slow = output_dir.join("slow.out");
+        match settle(&mut processor, 2) {
+            ProcessorResult::Success(_, outputs) => assert_eq!(outputs, [slow.as_path()]),
+            _ => panic!("slow.txt did not succeed on the second agent"),
+        }
+        assert_eq!(fs::read_to_string(slow).unwrap(), "slow.txt");
+    }
+
+    /// A batch run of `script` over inputs called `names` in `dir`, handed
+    /// to a local worker as items 1, 2, ... Returns the output directory.
+    #[cfg(unix)]
+    fn start_batch(
+        processor: &mut Processor,
+        dir: &Path,
+        script: &str,
+        settings: ScriptSettings,
+        names: &[&str],
+    ) -> (PathBuf, Vec<PathBuf>) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let output_dir = dir.join("out");
+        fs::create_dir_all(&output_dir).unwrap();
+        let path = dir.join("batch.sh");
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let script = path.to_string_lossy().into_owned();
+
+        let inputs: Vec<PathBuf> = names.iter().map(|name| dir.join(name)).collect();
+        for input in &inputs {
+            fs::write(input, "input").unwrap();
+        }
+        let items = (1..)
+            .zip(&inputs)
+            .map(|(id, input)| {
+                let invocation = Invocation::resolve(&script, input, &output_dir, &settings);
+                (id, invocation)
+            })
+            .collect();
+        let file_list = outputs::file_list_path(&output_dir, 1);
+        let invocation =
+            Invocation::resolve_batch(&script, &inputs, &output_dir, &settings, None, &file_list);
+        processor
+            .process_batch(items, invocation, settings, Hooks::default())
+            .unwrap();
+        (output_dir, inputs)
+    }
+
+    #[test]
+    fn signalling_reports_every_workers_error_once() {
+        let results = [
+            Err("Failed to signal script: No such process".to_string()),
+            Ok(()),
+            Err("Failed to signal script: Operation not permitted".to_string()),
+            Err("Failed to signal script: No such process".to_string()),
+        ];
+        assert_eq!(
+            Processor::signal_all(results.into_iter()),
+            Err("Failed to signal script: No such process; \
+                 Failed to signal script: Operation not permitted"
+                .to_string())
+        );
+        assert_eq!(Processor::signal_all([Ok(()), Ok(())].into_iter()), Ok(()));
+    }
+
+    fn local_processor() -> Processor {
+        Processor::new(&WorkerSettings {
+            local: true,
+            agents: Vec::new(),
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn batch_items_without_a_result_line_get_only_their_own_outputs_and_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut processor = local_processor();
+        let settings = ScriptSettings {
+            args: vec!["{output_dir}".to_string()],
+            batch: Some(BatchInput::Args),
+            warning_exit_codes: vec![2],
+            ..ScriptSettings::default()
+        };
+        let (output_dir, _) = start_batch(
+            &mut processor,
+            dir.path(),
+            "#!/bin/sh\n\
+             out=\"$1\"; shift\n\
+             for f in \"$@\"; do\n\
+               cp \"$f\" \"$out/$(basename \"$f\" .txt).out\"\n\
+               echo \"$(basename \"$f\" .txt).out\" >> \"$TASKFLOW_MANIFEST\"\n\
+             done\n\
+             echo sheet.pdf >> \"$TASKFLOW_MANIFEST\"\n\
+             touch \"$out/sheet.pdf\"\n\
+             echo 'low on disk' >&2\n\
+             echo 'b.txt: clipped' >&2\n\
+             exit 2\n",
+            settings,
+            &["a.txt", "b.txt"],
+        );
+
+        let mut warnings = HashMap::new();
+        let mut outputs = HashMap::new();
+        while outputs.len() < 2 {
+            match next_result(&mut processor) {
+                ProcessorResult::Warnings(id, found) => {
+                    warnings.insert(id, found);
+                }
+                ProcessorResult::Success(id, found) => {
+                    outputs.insert(id, found);
+                }
+                ProcessorResult::Error(id, e) => panic!("item {} failed: {}", id, e),
+                _ => {}
+            }
+        }
+        assert_eq!(outputs[&1], [output_dir.join("a.out")]);
+        assert_eq!(outputs[&2], [output_dir.join("b.out")]);
+        // The run's warning is every item's; one naming an item is its own
+        assert_eq!(warnings[&1], ["low on disk"]);
+        assert_eq!(warnings[&2], ["low on disk", "b.txt: clipped"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn json_lines_results_decide_each_items_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut processor = local_processor();
+        let settings = ScriptSettings {
+            args: vec!["{output_dir}".to_string()],
+            batch: Some(BatchInput::Args),
+            results: ResultProtocol::JsonLines,
+            ..ScriptSettings::default()
+        };
+        let (output_dir, _) = start_batch(
+            &mut processor,
+            dir.path(),
+            "#!/bin/sh\n\
+             touch \"$1/a.webp\"\n\
+             echo 'converting...'\n\
+             echo '{\"file\": \"a.jpg\", \"status\": \"ok\", \"outputs\": [\"a.webp\", \"../a.webp\"]}'\n\
+             echo '{\"file\": \"b.jpg\", \"status\": \"error\", \"message\": \"not a JPEG\"}'\n",
+            settings,
+            &["a.jpg", "b.jpg"],
+        );
+
+        match settle(&mut processor, 1) {
+            ProcessorResult::Success(_, outputs) => {
+                assert_eq!(outputs, [output_dir.join("a.webp")])
+            }
+            _ => panic!("a.jpg did not succeed"),
+        }
+        match settle(&mut processor, 2) {
+            ProcessorResult::Error(_, error) => assert_eq!(error, "Script failed: not a JPEG"),
+            _ => panic!("b.jpg did not fail"),
+        }
+    }
+
+    /// Runs a batch script that copies how it was given its inputs to
+    /// `received`, returning that and the inputs.
+    #[cfg(unix)]
+    fn received(batch: BatchInput, script: &str) -> (String, Vec<PathBuf>, PathBuf) {
+        let dir = tempfile::tempdir().unwrap().keep();
+        let mut processor = local_processor();
+        let settings = ScriptSettings {
+            args: vec!["{output_dir}".to_string()],
+            batch: Some(batch),
+            ..ScriptSettings::default()
+        };
+        let (output_dir, inputs) = start_batch(
+            &mut processor,
+            &dir,
+            script,
+            settings,
+            &["a.txt", "b c.txt"],
+        );
+        for id in [1, 2] {
+            assert!(matches!(
+                settle(&mut processor, id),
+                ProcessorResult::Success(..)
+            ));
+        }
+        let received = fs::read_to_string(output_dir.join("received")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (received, inputs, output_dir)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_file_batches_get_their_inputs_in_a_file() {
+        let (received, inputs, output_dir) = received(
+            BatchInput::ListFile,
+            "#!/bin/sh\n\
+             [ \"$2\" = \"$TASKFLOW_FILE_LIST\" ] || exit 1\n\
+             { echo \"$2\"; cat \"$2\"; } > \"$1/received\"\n",
+        );
+
+        let list = outputs::file_list_path(&output_dir, 1);
+        let expected = format!(
+            "{}\n{}\n{}\n",
+            list.display(),
+            inputs[0].display(),
+            inputs[1].display()
+        );
+        assert_eq!(received, expected);
+        // Removed once the script is done
+        assert!(!list.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdin_batches_get_their_inputs_on_stdin() {
+        let (received, inputs, _) = received(
+            BatchInput::Stdin,
+            "#!/bin/sh\n\
+             cat > \"$1/received\"\n",
+        );
+
+        let expected = format!("{}\n{}\n", inputs[0].display(), inputs[1].display());
+        assert_eq!(received, expected);
+    }
+}
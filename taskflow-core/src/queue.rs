@@ -0,0 +1,369 @@
+//! The files waiting to be processed, and how each one ended.
+
+use std::path::{Path, PathBuf};
+
+/// Where an item is in its run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ItemState {
+    /// Waiting to be dispatched.
+    Pending,
+    /// Dispatched to a worker.
+    Processing,
+    /// Succeeded.
+    Completed,
+    /// Succeeded, but the script reported warnings. They are in the item's
+    /// `warnings`.
+    CompletedWithWarnings,
+    /// Not run, e.g. because its output already exists.
+    Skipped(String),
+    /// Failed, with why.
+    Error(String),
+}
+
+/// Stable identifier for a queue item, unaffected by reordering or removal.
+pub type ItemId = u64;
+
+/// A file in the queue. Its fields change only through the [`Queue`] and
+/// the engine, so the queue's states stay consistent.
+#[derive(Debug, Clone)]
+pub struct QueueItem {
+    pub(crate) id: ItemId,
+    pub(crate) path: PathBuf,
+    pub(crate) state: ItemState,
+    pub(crate) selected: bool,
+    pub(crate) outputs: Vec<PathBuf>,
+    pub(crate) post_action_error: Option<String>,
+    pub(crate) script: Option<String>,
+    pub(crate) warnings: Vec<String>,
+    pub(crate) batch: Option<ItemId>,
+}
+
+impl QueueItem {
+    pub(crate) fn new(id: ItemId, path: PathBuf) -> Self {
+        Self {
+            id,
+            path,
+            state: ItemState::Pending,
+            selected: false,
+            outputs: Vec::new(),
+            post_action_error: None,
+            script: None,
+            warnings: Vec::new(),
+            batch: None,
+        }
+    }
+
+    /// Assigned when the item is added.
+    pub fn id(&self) -> ItemId {
+        self.id
+    }
+
+    /// The input file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where it is in its run.
+    pub fn state(&self) -> &ItemState {
+        &self.state
+    }
+
+    /// Whether it is selected in the queue list.
+    pub fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    /// Files produced by the last successful run.
+    pub fn outputs(&self) -> &[PathBuf] {
+        &self.outputs
+    }
+
+    /// Why a post-action on the source file failed, if one did.
+    pub fn post_action_error(&self) -> Option<&str> {
+        self.post_action_error.as_deref()
+    }
+
+    /// The script to run it with instead of the selected one, e.g. the
+    /// script it ran with when re-queued from history.
+    pub fn script(&self) -> Option<&str> {
+        self.script.as_deref()
+    }
+
+    /// Warnings the script reported on its last run.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// The batch a batch script last ran it in, identified by the batch's
+    /// first item.
+    pub fn batch(&self) -> Option<ItemId> {
+        self.batch
+    }
+
+    /// Processing items can't be selected, moved or removed.
+    pub fn is_locked(&self) -> bool {
+        matches!(self.state, ItemState::Processing)
+    }
+
+    /// Finished without error, including items that were skipped.
+    pub fn is_completed(&self) -> bool {
+        matches!(
+            self.state,
+            ItemState::Completed | ItemState::CompletedWithWarnings | ItemState::Skipped(_)
+        )
+    }
+
+    /// Whether its last run failed.
+    pub fn is_failed(&self) -> bool {
+        matches!(self.state, ItemState::Error(_))
+    }
+
+    /// The script it runs with, given the selected one.
+    pub fn script_or<'a>(&'a self, selected: Option<&'a str>) -> Option<&'a str> {
+        self.script.as_deref().or(selected)
+    }
+
+    /// The input's file name, for display.
+    pub fn filename(&self) -> String {
+        self.path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown")
+            .to_string()
+    }
+}
+
+/// Items in the order they are dispatched.
+#[derive(Debug, Default)]
+pub struct Queue {
+    items: Vec<QueueItem>,
+    next_id: ItemId,
+}
+
+impl Queue {
+    /// An empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pending file, returning its id.
+    pub fn add(&mut self, path: PathBuf) -> ItemId {
+        self.next_id += 1;
+        self.items.push(QueueItem::new(self.next_id, path));
+        self.next_id
+    }
+
+    /// Adds a file that runs with `script` instead of the selected one.
+    pub fn add_with_script(&mut self, path: PathBuf, script: String) -> ItemId {
+        let id = self.add(path);
+        if let Some(item) = self.items.last_mut() {
+            item.script = Some(script);
+        }
+        id
+    }
+
+    /// Adds pending files in order.
+    pub fn add_multiple(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            self.add(path);
+        }
+    }
+
+    /// Removes selected items that aren't processing.
+    pub fn remove_selected(&mut self) {
+        self.items.retain(|item| !item.selected || item.is_locked());
+    }
+
+    /// Deselects every item.
+    pub fn clear_selection(&mut self) {
+        for item in &mut self.items {
+            item.selected = false;
+        }
+    }
+
+    /// Selects the item at `index`, unless it is locked.
+    pub fn select(&mut self, index: usize) {
+        if let Some(item) = self.items.get_mut(index)
+            && !item.is_locked()
+        {
+            item.selected = true;
+        }
+    }
+
+    /// Flips the selection of the item at `index`, unless it is locked.
+    pub fn toggle_select(&mut self, index: usize) {
+        if let Some(item) = self.items.get_mut(index)
+            && !item.is_locked()
+        {
+            item.selected = !item.selected;
+        }
+    }
+
+    /// Selects the unlocked items from `start` to `end`, inclusive.
+    pub fn select_range(&mut self, start: usize, end: usize) {
+        let (start, end) = if start <= end {
+            (start, end)
+        } else {
+            (end, start)
+        };
+
+        for i in start..=end {
+            if let Some(item) = self.items.get_mut(i)
+                && !item.is_locked()
+            {
+                item.selected = true;
+            }
+        }
+    }
+
+    /// Moves the selected, unlocked items to `target_index`, keeping their order.
+    pub fn move_selected(&mut self, target_index: usize) {
+        // Extract selected items (excluding locked ones)
+        let mut selected = Vec::new();
+        let mut i = 0;
+        while i < self.items.len() {
+            if self.items[i].selected && !self.items[i].is_locked() {
+                selected.push(self.items.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+
+        // Insert at target position
+        let insert_pos = target_index.min(self.items.len());
+        for (offset, item) in selected.into_iter().enumerate() {
+            self.items.insert(insert_pos + offset, item);
+        }
+    }
+
+    /// Removes items that succeeded or were skipped. Items with warnings
+    /// stay, so they can be looked at.
+    pub fn remove_completed(&mut self) {
+        self.items
+            .retain(|item| !item.is_completed() || item.state == ItemState::CompletedWithWarnings);
+    }
+
+    /// Removes items that completed with warnings.
+    pub fn remove_with_warnings(&mut self) {
+        self.items
+            .retain(|item| item.state != ItemState::CompletedWithWarnings);
+    }
+
+    /// Removes items that failed.
+    pub fn remove_failed(&mut self) {
+        self.items.retain(|item| !item.is_failed());
+    }
+
+    pub(crate) fn get_next_pending(&self) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| matches!(item.state, ItemState::Pending))
+    }
+
+    pub(crate) fn set_state(&mut self, index: usize, state: ItemState) {
+        if let Some(item) = self.items.get_mut(index) {
+            item.state = state;
+        }
+    }
+
+    /// The item with `id`, if it is still queued.
+    pub fn get(&self, id: ItemId) -> Option<&QueueItem> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    pub(crate) fn set_state_by_id(&mut self, id: ItemId, state: ItemState) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            item.state = state;
+        }
+    }
+
+    pub(crate) fn set_outputs_by_id(&mut self, id: ItemId, outputs: Vec<PathBuf>) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            item.outputs = outputs;
+        }
+    }
+
+    pub(crate) fn set_post_action_error_by_id(&mut self, id: ItemId, error: String) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            item.post_action_error = Some(error);
+        }
+    }
+
+    pub(crate) fn set_warnings_by_id(&mut self, id: ItemId, warnings: Vec<String>) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            item.warnings = warnings;
+        }
+    }
+
+    pub(crate) fn set_batch_by_id(&mut self, id: ItemId, batch: ItemId) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.id == id) {
+            item.batch = Some(batch);
+        }
+    }
+
+    /// Every item, in dispatch order.
+    pub fn items(&self) -> &[QueueItem] {
+        &self.items
+    }
+
+    /// Number of items.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether there are no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A queue with one item in each finished state.
+    fn finished_queue() -> Queue {
+        let mut queue = Queue::new();
+        let states = [
+            ItemState::Completed,
+            ItemState::CompletedWithWarnings,
+            ItemState::Skipped("exists".to_string()),
+            ItemState::Error("failed".to_string()),
+            ItemState::Pending,
+        ];
+        for (index, state) in states.into_iter().enumerate() {
+            queue.add(PathBuf::from(format!("/in/{}.txt", index)));
+            queue.set_state(index, state);
+        }
+        queue
+    }
+
+    fn states(queue: &Queue) -> Vec<ItemState> {
+        queue
+            .items()
+            .iter()
+            .map(|item| item.state.clone())
+            .collect()
+    }
+
+    #[test]
+    fn removing_completed_items_keeps_warned_ones_until_cleared() {
+        let mut queue = finished_queue();
+
+        queue.remove_completed();
+        assert_eq!(
+            states(&queue),
+            [
+                ItemState::CompletedWithWarnings,
+                ItemState::Error("failed".to_string()),
+                ItemState::Pending
+            ]
+        );
+
+        queue.remove_with_warnings();
+        assert_eq!(
+            states(&queue),
+            [ItemState::Error("failed".to_string()), ItemState::Pending]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use crate::processor::ProcessorResult;
+use crate::queue::{ItemId, Queue};
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const EXCERPT_MAX_CHARS: usize = 300;
+
+#[derive(Debug, Clone)]
+pub enum ReportOutcome {
+    Success,
+    /// Succeeded with these warnings.
+    Warnings(Vec<String>),
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportEntry {
+    pub path: PathBuf,
+    pub outcome: ReportOutcome,
+    pub duration: Duration,
+    pub output_bytes: u64,
+}
+
+impl ReportEntry {
+    pub fn filename(&self) -> String {
+        self.path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Unknown")
+            .to_string()
+    }
+}
+
+struct InProgress {
+    path: PathBuf,
+    started: Instant,
+    warnings: Vec<String>,
+}
+
+/// Summary of a processing run: how each item ended, how long it took and
+/// how much it wrote.
+#[derive(Default)]
+pub struct RunReport {
+    entries: Vec<ReportEntry>,
+    in_progress: HashMap<ItemId, InProgress>,
+    started_at: Option<DateTime<Local>>,
+    finished_at: Option<DateTime<Local>>,
+}
+
+impl RunReport {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&mut self, result: &ProcessorResult, queue: &Queue) {
+        match result {
+            ProcessorResult::Started(id) => {
+                if let Some(item) = queue.get(*id) {
+                    self.started_at.get_or_insert_with(Local::now);
+                    self.in_progress.insert(
+                        *id,
+                        InProgress {
+                            path: item.path.clone(),
+                            started: Instant::now(),
+                            warnings: Vec::new(),
+                        },
+                    );
+                }
+            }
+            ProcessorResult::Warnings(id, warnings) => {
+                if let Some(progress) = self.in_progress.get_mut(id) {
+                    progress.warnings = warnings.clone();
+                }
+            }
+            ProcessorResult::Success(id, outputs) => {
+                let warnings = self
+                    .in_progress
+                    .get_mut(id)
+                    .map(|progress| std::mem::take(&mut progress.warnings))
+                    .unwrap_or_default();
+                let outcome = if warnings.is_empty() {
+                    ReportOutcome::Success
+                } else {
+                    ReportOutcome::Warnings(warnings)
+                };
+                self.finish(*id, outcome, output_bytes(outputs));
+            }
+            ProcessorResult::Error(id, error) => {
+                self.finish(*id, ReportOutcome::Failed(excerpt(error)), 0);
+            }
+            ProcessorResult::Requeued(id, _) => {
+                self.in_progress.remove(id);
+            }
+            ProcessorResult::PostActionFailed(..) | ProcessorResult::HookFinished { .. } => {}
+        }
+    }
+
+    /// Records an item that finished without its script being run, e.g.
+    /// because its output already exists.
+    pub(crate) fn record_unstarted(&mut self, path: &Path, outcome: ReportOutcome) {
+        self.started_at.get_or_insert_with(Local::now);
+        self.entries.push(ReportEntry {
+            path: path.to_path_buf(),
+            outcome,
+            duration: Duration::ZERO,
+            output_bytes: 0,
+        });
+        self.finished_at = Some(Local::now());
+    }
+
+    fn finish(&mut self, id: ItemId, outcome: ReportOutcome, output_bytes: u64) {
+        let Some(progress) = self.in_progress.remove(&id) else {
+            return;
+        };
+
+        self.entries.push(ReportEntry {
+            path: progress.path,
+            outcome,
+            duration: progress.started.elapsed(),
+            output_bytes,
+        });
+        self.finished_at = Some(Local::now());
+    }
+
+    /// Whether no item has finished yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Items that succeeded without warnings.
+    pub fn success_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, ReportOutcome::Success))
+            .count()
+    }
+
+    /// Items that succeeded with warnings.
+    pub fn warning_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, ReportOutcome::Warnings(_)))
+            .count()
+    }
+
+    /// Items that were skipped.
+    pub fn skipped_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, ReportOutcome::Skipped(_)))
+            .count()
+    }
+
+    /// Items that failed.
+    pub fn failure_count(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, ReportOutcome::Failed(_)))
+            .count()
+    }
+
+    /// Time from the start of the run to its last result.
+    pub fn total_time(&self) -> Duration {
+        match (self.started_at, self.finished_at) {
+            (Some(start), Some(end)) => (end - start).to_std().unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Size of every output the run recorded.
+    pub fn total_output_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.output_bytes).sum()
+    }
+
+    /// Writes Markdown, HTML and CSV versions of the report into `dir`.
+    pub fn write(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let timestamp = self
+            .started_at
+            .unwrap_or_else(Local::now)
+            .format("%Y%m%d_%H%M%S");
+
+        let mut written = Vec::new();
+        for (extension, contents) in [
+            ("md", self.to_markdown()),
+            ("html", self.to_html()),
+            ("csv", self.to_csv()),
+        ] {
+            let path = dir.join(format!("taskflow_report_{}.{}", timestamp, extension));
+            fs::write(&path, contents)?;
+            written.push(path);
+        }
+
+        Ok(written)
+    }
+
+    fn started_label(&self) -> String {
+        self.started_at
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// The report as a Markdown document.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# TaskFlow Run Report\n\n");
+        out.push_str(&format!("- **Started:** {}\n", self.started_label()));
+        out.push_str(&format!("- **Files processed:** {}\n", self.entries.len()));
+        out.push_str(&format!("- **Succeeded:** {}\n", self.success_count()));
+        out.push_str(&format!("- **With warnings:** {}\n", self.warning_count()));
+        out.push_str(&format!("- **Skipped:** {}\n", self.skipped_count()));
+        out.push_str(&format!("- **Failed:** {}\n", self.failure_count()));
+        out.push_str(&format!(
+            "- **Total time:** {}\n",
+            format_duration(self.total_time())
+        ));
+        out.push_str(&format!(
+            "- **Total output:** {}\n\n",
+            format_bytes(self.total_output_bytes())
+        ));
+
+        out.push_str("| File | Result | Duration | Output size | Error |\n");
+        out.push_str("|---|---|---|---|---|\n");
+        for entry in &self.entries {
+            let (result, error) = match &entry.outcome {
+                ReportOutcome::Success => ("Success", String::new()),
+                ReportOutcome::Warnings(warnings) => {
+                    ("Warnings", escape_markdown(&warnings.join("; ")))
+                }
+                ReportOutcome::Skipped(reason) => ("Skipped", escape_markdown(reason)),
+                ReportOutcome::Failed(e) => ("Failed", escape_markdown(e)),
+            };
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                escape_markdown(&entry.filename()),
+                result,
+                format_duration(entry.duration),
+                format_bytes(entry.output_bytes),
+                error
+            ));
+        }
+
+        out
+    }
+
+    /// The report as a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>TaskFlow Run Report</title>\n<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             table { border-collapse: collapse; }\n\
+             th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n\
+             .failed { background: #fbe9e9; }\n\
+             .warnings { background: #fbf5e0; }\n\
+             </style>\n</head>\n<body>\n<h1>TaskFlow Run Report</h1>\n<ul>\n",
+        );
+        out.push_str(&format!(
+            "<li><b>Started:</b> {}</li>\n",
+            self.started_label()
+        ));
+        out.push_str(&format!(
+            "<li><b>Files processed:</b> {}</li>\n",
+            self.entries.len()
+        ));
+        out.push_str(&format!(
+            "<li><b>Succeeded:</b> {}</li>\n",
+            self.success_count()
+        ));
+        out.push_str(&format!(
+            "<li><b>With warnings:</b> {}</li>\n",
+            self.warning_count()
+        ));
+        out.push_str(&format!(
+            "<li><b>Skipped:</b> {}</li>\n",
+            self.skipped_count()
+        ));
+        out.push_str(&format!(
+            "<li><b>Failed:</b> {}</li>\n",
+            self.failure_count()
+        ));
+        out.push_str(&format!(
+            "<li><b>Total time:</b> {}</li>\n",
+            format_duration(self.total_time())
+        ));
+        out.push_str(&format!(
+            "<li><b>Total output:</b> {}</li>\n</ul>\n",
+            format_bytes(self.total_output_bytes())
+        ));
+
+        out.push_str("<table>\n<tr><th>File</th><th>Result</th><th>Duration</th><th>Output size</th><th>Error</th></tr>\n");
+        for entry in &self.entries {
+            let (class, result, error) = match &entry.outcome {
+                ReportOutcome::Success => ("", "Success", String::new()),
+                ReportOutcome::Warnings(warnings) => (
+                    " class=\"warnings\"",
+                    "Warnings",
+                    escape_html(&warnings.join("\n")),
+                ),
+                ReportOutcome::Skipped(reason) => ("", "Skipped", escape_html(reason)),
+                ReportOutcome::Failed(e) => (" class=\"failed\"", "Failed", escape_html(e)),
+            };
+            out.push_str(&format!(
+                "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><pre>{}</pre></td></tr>\n",
+                class,
+                escape_html(&entry.filename()),
+                result,
+                format_duration(entry.duration),
+                format_bytes(entry.output_bytes),
+                error
+            ));
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+
+        out
+    }
+
+    /// One CSV line per item.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("file,path,result,duration_seconds,output_bytes,error\n");
+        for entry in &self.entries {
+            let (result, error) = match &entry.outcome {
+                ReportOutcome::Success => ("success", String::new()),
+                ReportOutcome::Warnings(warnings) => ("warnings", warnings.join("; ")),
+                ReportOutcome::Skipped(reason) => ("skipped", reason.clone()),
+                ReportOutcome::Failed(e) => ("failed", e.clone()),
+            };
+            out.push_str(&format!(
+                "{},{},{},{:.3},{},{}\n",
+                escape_csv(&entry.filename()),
+                escape_csv(&entry.path.to_string_lossy()),
+                result,
+                entry.duration.as_secs_f64(),
+                entry.output_bytes,
+                escape_csv(&error)
+            ));
+        }
+        out
+    }
+}
+
+/// Trims a script error down to something readable in a table cell.
+fn excerpt(error: &str) -> String {
+    let trimmed = error.trim();
+    if trimmed.chars().count() <= EXCERPT_MAX_CHARS {
+        return trimmed.to_string();
+    }
+    // The end of stderr is usually the most useful part
+    let tail: String = trimmed
+        .chars()
+        .rev()
+        .take(EXCERPT_MAX_CHARS)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("...{}", tail)
+}
+
+/// The total size of `outputs` that still exist.
+fn output_bytes(outputs: &[PathBuf]) -> u64 {
+    outputs
+        .iter()
+        .filter_map(|output| fs::metadata(output).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Formats a duration as e.g. `1h 02m 03s`, `2m 03s` or `3.2s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!(
+            "{}h {:02}m {:02}s",
+            secs / 3600,
+            (secs % 3600) / 60,
+            secs % 60
+        )
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Keeps `text` inside one Markdown table cell.
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace("\r\n", " ")
+        .replace(['\n', '\r'], " ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn escape_csv(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A report with one item of each outcome.
+    fn report() -> RunReport {
+        let mut report = RunReport::new();
+        report.record_unstarted(Path::new("/in/plain.txt"), ReportOutcome::Success);
+        report.record_unstarted(
+            Path::new("/in/a|b.txt"),
+            ReportOutcome::Warnings(vec!["low | high".to_string(), "clipped".to_string()]),
+        );
+        report.record_unstarted(
+            Path::new("/in/skip.txt"),
+            ReportOutcome::Skipped("Output exists |\nkept".to_string()),
+        );
+        report.record_unstarted(
+            Path::new("/in/x,y.txt"),
+            ReportOutcome::Failed("Script failed: <bad> \"input\"\r\nline 2".to_string()),
+        );
+        report
+    }
+
+    #[test]
+    fn outcomes_are_counted() {
+        let report = report();
+        assert_eq!(report.success_count(), 1);
+        assert_eq!(report.warning_count(), 1);
+        assert_eq!(report.skipped_count(), 1);
+        assert_eq!(report.failure_count(), 1);
+    }
+
+    #[test]
+    fn markdown_cells_stay_on_one_row() {
+        let markdown = report().to_markdown();
+        let rows: Vec<&str> = markdown
+            .lines()
+            .skip_while(|line| !line.starts_with("|---"))
+            .skip(1)
+            .collect();
+
+        assert_eq!(rows.len(), 4);
+        assert!(rows[1].starts_with("| a\\|b.txt | Warnings |"));
+        assert!(rows[1].ends_with("| low \\| high; clipped |"));
+        assert!(rows[2].ends_with("| Output exists \\| kept |"));
+        assert!(rows[3].ends_with("| Script failed: <bad> \"input\" line 2 |"));
+    }
+
+    #[test]
+    fn html_escapes_names_and_errors() {
+        let html = report().to_html();
+        assert!(html.contains("<td>a|b.txt</td>"));
+        assert!(html.contains("<pre>Script failed: &lt;bad&gt; &quot;input&quot;\r\nline 2</pre>"));
+        assert!(!html.contains("<bad>"));
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_separators() {
+        let csv = report().to_csv();
+        let lines: Vec<&str> = csv.split('\n').collect();
+
+        assert!(lines[1].starts_with("plain.txt,/in/plain.txt,success,"));
+        assert!(lines[3].ends_with(",skipped,0.000,0,\"Output exists |"));
+        assert_eq!(lines[4], "kept\"");
+        assert!(lines[5].starts_with("\"x,y.txt\",\"/in/x,y.txt\",failed,"));
+        assert!(lines[5].ends_with(",\"Script failed: <bad> \"\"input\"\"\r"));
+    }
+
+    #[test]
+    fn long_errors_keep_their_end() {
+        let error = format!("{}the cause", "x".repeat(EXCERPT_MAX_CHARS));
+        let trimmed = excerpt(&error);
+
+        assert!(trimmed.starts_with("..."));
+        assert!(trimmed.ends_with("the cause"));
+        assert_eq!(trimmed.chars().count(), EXCERPT_MAX_CHARS + 3);
+        assert_eq!(excerpt("  short\n"), "short");
+    }
+
+    #[test]
+    fn durations_and_sizes_are_readable() {
+        assert_eq!(format_duration(Duration::from_millis(3200)), "3.2s");
+        assert_eq!(format_duration(Duration::from_secs(123)), "2m 03s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+}
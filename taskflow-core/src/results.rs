@@ -0,0 +1,178 @@
+//! The result protocol, for scripts that report more than an exit status.
+//!
+//! With `results` set to `JsonLines`, a script prints one JSON object per
+//! line on stdout for each item it finished:
+//!
+//! ```text
+//! {"file": "/photos/a.jpg", "status": "ok", "outputs": ["a.webp"], "warnings": ["colour profile dropped"]}
+//! {"file": "b.jpg", "status": "error", "message": "not a JPEG"}
+//! ```
+//!
+//! `file` is the input's path as given to the script, or just its file
+//! name. A script run for a single item may leave it out. `outputs` are
+//! relative to the output directory, and replace the outputs TaskFlow would
+//! otherwise record. Any other output is ignored, so scripts can keep
+//! logging to stdout.
+
+use crate::outputs;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// One line of the protocol.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileResult {
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    pub status: FileStatus,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub outputs: Vec<PathBuf>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Ok,
+    Error,
+}
+
+impl FileResult {
+    /// Whether this line is about `input`. Lines without a file are about
+    /// the only input of a run, if it has just one.
+    pub fn is_for(&self, input: &Path, only_input: bool) -> bool {
+        let Some(ref file) = self.file else {
+            return only_input;
+        };
+        if file == input {
+            return true;
+        }
+        let bare_name = file.parent() == Some(Path::new(""));
+        bare_name && input.file_name() == Some(file.as_os_str())
+    }
+
+    /// The item's result, with errors worded like other script failures.
+    pub fn result(&self) -> Result<(), String> {
+        match self.status {
+            FileStatus::Ok => Ok(()),
+            FileStatus::Error => Err(format!(
+                "Script failed: {}",
+                self.message.as_deref().unwrap_or("reported an error")
+            )),
+        }
+    }
+
+    /// The reported outputs as full paths, or None if there were none.
+    /// Outputs outside `output_dir` are dropped.
+    pub fn outputs_in(&self, output_dir: &Path) -> Option<Vec<PathBuf>> {
+        if self.outputs.is_empty() {
+            return None;
+        }
+        Some(
+            self.outputs
+                .iter()
+                .filter_map(|output| outputs::confined(output_dir, output))
+                .collect(),
+        )
+    }
+}
+
+/// The protocol lines in `stdout`.
+pub fn parse(stdout: &str) -> Vec<FileResult> {
+    stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// The result reported for `input`, if there is one. A file reported more
+/// than once keeps its last line.
+pub fn find<'a>(
+    results: &'a [FileResult],
+    input: &Path,
+    only_input: bool,
+) -> Option<&'a FileResult> {
+    results
+        .iter()
+        .rev()
+        .find(|result| result.is_for(input, only_input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STDOUT: &str = r#"converting...
+{"file": "/photos/a.jpg", "status": "ok", "outputs": ["a.webp"]}
+{"file": "b.jpg", "status": "error", "message": "not a JPEG"}
+{"file": "b.jpg", "status": "ok", "warnings": ["colour profile dropped"]}
+{"file": "c.jpg", "status": "error"}
+{not json}
+"#;
+
+    #[test]
+    fn only_protocol_lines_are_parsed() {
+        let results = parse(STDOUT);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].file, Some(PathBuf::from("/photos/a.jpg")));
+    }
+
+    #[test]
+    fn lines_match_full_paths_or_bare_names() {
+        let results = parse(STDOUT);
+        let a = find(&results, Path::new("/photos/a.jpg"), false).unwrap();
+        assert_eq!(a.outputs, [PathBuf::from("a.webp")]);
+
+        assert!(find(&results, Path::new("/elsewhere/a.jpg"), false).is_none());
+        assert!(find(&results, Path::new("/photos/b.jpg"), false).is_some());
+        assert!(find(&results, Path::new("/photos/d.jpg"), false).is_none());
+    }
+
+    #[test]
+    fn lines_without_a_file_are_for_a_single_input() {
+        let results = parse(r#"{"status": "ok"}"#);
+        assert!(find(&results, Path::new("/photos/a.jpg"), true).is_some());
+        assert!(find(&results, Path::new("/photos/a.jpg"), false).is_none());
+    }
+
+    #[test]
+    fn the_last_line_for_a_file_wins() {
+        let results = parse(STDOUT);
+        let b = find(&results, Path::new("/photos/b.jpg"), false).unwrap();
+        assert_eq!(b.result(), Ok(()));
+        assert_eq!(b.warnings, ["colour profile dropped"]);
+    }
+
+    #[test]
+    fn error_lines_fail_with_their_message() {
+        let results = parse(STDOUT);
+        assert_eq!(
+            results[1].result(),
+            Err("Script failed: not a JPEG".to_string())
+        );
+        assert_eq!(
+            results[3].result(),
+            Err("Script failed: reported an error".to_string())
+        );
+    }
+
+    #[test]
+    fn outputs_outside_the_output_dir_are_dropped() {
+        let line = parse(
+            r#"{"status": "ok", "outputs": ["a.webp", "sub/a.png", "../a.gif", "/etc/passwd", "/out/b.webp"]}"#,
+        )
+        .remove(0);
+        assert_eq!(
+            line.outputs_in(Path::new("/out")).unwrap(),
+            [
+                PathBuf::from("/out/a.webp"),
+                PathBuf::from("/out/sub/a.png"),
+                PathBuf::from("/out/b.webp")
+            ]
+        );
+    }
+}
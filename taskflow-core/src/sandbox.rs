@@ -0,0 +1,288 @@
+use crate::config::{SandboxFallback, SandboxSettings};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Confines the process spawned from `command` so it can read `inputs`,
+/// `script` and system directories, and write only to `output_dir`.
+///
+/// When the kernel cannot enforce the sandbox, this fails or leaves the
+/// command untouched according to `settings.fallback`.
+pub fn apply(
+    command: &mut Command,
+    settings: &SandboxSettings,
+    script: &Path,
+    inputs: &[PathBuf],
+    output_dir: &Path,
+) -> Result<(), String> {
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    match landlock_sandbox::apply(command, settings, script, inputs, output_dir) {
+        Ok(()) => Ok(()),
+        Err(Unavailable(reason)) => match settings.fallback {
+            SandboxFallback::Fail => Err(format!("Sandbox unavailable: {}", reason)),
+            SandboxFallback::RunUnsandboxed => Ok(()),
+        },
+    }
+}
+
+/// The kernel cannot enforce the requested sandbox.
+struct Unavailable(String);
+
+#[cfg(target_os = "linux")]
+mod landlock_sandbox {
+    use super::Unavailable;
+    use crate::config::SandboxSettings;
+    use landlock::{
+        ABI, Access, AccessFs, AccessNet, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        path_beneath_rules,
+    };
+    use std::error::Error;
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    /// Directories holding interpreters, libraries and configuration that
+    /// scripts need to run.
+    const SYSTEM_READ_PATHS: &[&str] = &[
+        "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/proc", "/nix",
+    ];
+
+    const DEVICE_PATHS: &[&str] = &["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
+
+    // ABI versions the rules are written against
+    const FS_ABI: ABI = ABI::V3;
+    const NET_ABI: ABI = ABI::V4;
+
+    pub fn apply(
+        command: &mut Command,
+        settings: &SandboxSettings,
+        script: &Path,
+        inputs: &[PathBuf],
+        output_dir: &Path,
+    ) -> Result<(), Unavailable> {
+        let abi = kernel_abi();
+        if abi < 1 {
+            return Err(Unavailable(
+                "Landlock is not enabled in this kernel".to_string(),
+            ));
+        }
+        if settings.block_network && abi < 4 {
+            return Err(Unavailable(
+                "blocking network access needs Landlock ABI 4 (Linux 6.7+)".to_string(),
+            ));
+        }
+
+        let read = AccessFs::from_read(FS_ABI);
+        let all = AccessFs::from_all(FS_ABI);
+
+        let mut ruleset = Ruleset::default()
+            .handle_access(all)
+            .map_err(|e| Unavailable(e.to_string()))?;
+        if settings.block_network {
+            ruleset = ruleset
+                .handle_access(AccessNet::from_all(NET_ABI))
+                .map_err(|e| Unavailable(e.to_string()))?;
+        }
+
+        let ruleset = ruleset
+            .create()
+            .and_then(|r| r.add_rules(path_beneath_rules(SYSTEM_READ_PATHS, read)))
+            .and_then(|r| r.add_rules(path_beneath_rules(&settings.read_paths, read)))
+            .and_then(|r| r.add_rules(path_beneath_rules([script], read)))
+            .and_then(|r| r.add_rules(path_beneath_rules(inputs, read)))
+            .and_then(|r| r.add_rules(path_beneath_rules(DEVICE_PATHS, all)))
+            .and_then(|r| r.add_rules(path_beneath_rules(&settings.write_paths, all)))
+            .and_then(|r| r.add_rules(path_beneath_rules([output_dir], all)))
+            .map_err(|e| Unavailable(e.to_string()))?;
+
+        let mut ruleset = Some(ruleset);
+
+        // SAFETY: restricting the child only issues prctl, landlock and
+        // close syscalls on an already created ruleset. Failures carry the
+        // errno they came with, so the error path doesn't allocate either
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(ruleset) = ruleset.take() {
+                    ruleset
+                        .restrict_self()
+                        .map_err(|e| io::Error::from_raw_os_error(os_error(&e)))?;
+                }
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The errno behind a Landlock error, found without allocating.
+    fn os_error(error: &(dyn Error + 'static)) -> i32 {
+        let mut source = Some(error);
+        while let Some(error) = source {
+            if let Some(code) = error
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::raw_os_error)
+            {
+                return code;
+            }
+            source = error.source();
+        }
+        libc::EPERM
+    }
+
+    /// The Landlock ABI version supported by the running kernel, or 0.
+    pub(super) fn kernel_abi() -> i64 {
+        const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1;
+
+        // SAFETY: querying the version takes no ruleset attributes
+        let version = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<libc::c_void>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        version.max(0)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod landlock_sandbox {
+    use super::Unavailable;
+    use crate::config::SandboxSettings;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    pub fn apply(
+        _command: &mut Command,
+        _settings: &SandboxSettings,
+        _script: &Path,
+        _inputs: &[PathBuf],
+        _output_dir: &Path,
+    ) -> Result<(), Unavailable> {
+        Err(Unavailable(
+            "sandboxing is only supported on Linux".to_string(),
+        ))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    struct Dirs {
+        _root: tempfile::TempDir,
+        input: PathBuf,
+        output_dir: PathBuf,
+        outside: PathBuf,
+    }
+
+    fn dirs() -> Dirs {
+        let root = tempfile::tempdir().unwrap();
+        let input = root.path().join("in.txt");
+        fs::write(&input, "input").unwrap();
+        let output_dir = root.path().join("out");
+        let outside = root.path().join("elsewhere");
+        fs::create_dir(&output_dir).unwrap();
+        fs::create_dir(&outside).unwrap();
+        Dirs {
+            _root: root,
+            input,
+            output_dir,
+            outside,
+        }
+    }
+
+    /// `sh -c script` with `$1` the input, `$2` the output directory and
+    /// `$3` a directory outside it, confined by `settings`.
+    fn sandboxed(dirs: &Dirs, settings: &SandboxSettings, script: &str) -> Result<Command, String> {
+        let mut command = Command::new("/bin/sh");
+        command
+            .args(["-c", script, "sh"])
+            .args([&dirs.input, &dirs.output_dir, &dirs.outside]);
+        apply(
+            &mut command,
+            settings,
+            Path::new("/bin/sh"),
+            std::slice::from_ref(&dirs.input),
+            &dirs.output_dir,
+        )
+        .map(|()| command)
+    }
+
+    fn enabled(fallback: SandboxFallback) -> SandboxSettings {
+        SandboxSettings {
+            enabled: true,
+            fallback,
+            ..SandboxSettings::default()
+        }
+    }
+
+    #[test]
+    fn sandboxed_scripts_write_only_to_the_output_dir() {
+        if landlock_sandbox::kernel_abi() < 1 {
+            return;
+        }
+        let dirs = dirs();
+        let script = "cp \"$1\" \"$2/copy.txt\" && ! touch \"$3/escaped.txt\" 2>/dev/null";
+
+        let status = sandboxed(&dirs, &enabled(SandboxFallback::Fail), script)
+            .unwrap()
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(dirs.output_dir.join("copy.txt").exists());
+        assert!(!dirs.outside.join("escaped.txt").exists());
+    }
+
+    #[test]
+    fn extra_write_paths_are_writable() {
+        if landlock_sandbox::kernel_abi() < 1 {
+            return;
+        }
+        let dirs = dirs();
+        let settings = SandboxSettings {
+            write_paths: vec![dirs.outside.clone()],
+            ..enabled(SandboxFallback::Fail)
+        };
+
+        let status = sandboxed(&dirs, &settings, "touch \"$3/allowed.txt\"")
+            .unwrap()
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn without_landlock_the_fallback_decides() {
+        if landlock_sandbox::kernel_abi() >= 1 {
+            return;
+        }
+        let dirs = dirs();
+        let script = "touch \"$3/escaped.txt\"";
+
+        let error = sandboxed(&dirs, &enabled(SandboxFallback::Fail), script).unwrap_err();
+        assert!(error.starts_with("Sandbox unavailable: "), "{}", error);
+
+        let status = sandboxed(&dirs, &enabled(SandboxFallback::RunUnsandboxed), script)
+            .unwrap()
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn disabled_sandboxes_leave_the_script_alone() {
+        let dirs = dirs();
+        let status = sandboxed(&dirs, &SandboxSettings::default(), "touch \"$3/free.txt\"")
+            .unwrap()
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(dirs.outside.join("free.txt").exists());
+    }
+}
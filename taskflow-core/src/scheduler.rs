@@ -0,0 +1,215 @@
+//! Delayed starts and daily windows for when items may be dispatched.
+
+use crate::config::{ScheduleSettings, TimeWindow};
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone};
+use std::fmt;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+impl TimeWindow {
+    /// Whether `time` falls inside the window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            // Spans midnight
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Parses a window written as `HH:MM-HH:MM`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (start, end) = text
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid window '{}', expected HH:MM-HH:MM", text.trim()))?;
+
+        Ok(Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format(TIME_FORMAT),
+            self.end.format(TIME_FORMAT)
+        )
+    }
+}
+
+/// Parses a time of day written as `HH:MM`.
+pub fn parse_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text.trim(), TIME_FORMAT)
+        .map_err(|_| format!("Invalid time '{}', expected HH:MM", text.trim()))
+}
+
+/// Formats a time of day as `HH:MM`.
+pub fn format_time(time: NaiveTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+/// Parses a comma-separated list of windows, e.g. `22:00-06:00, 12:00-13:00`.
+pub fn parse_windows(text: &str) -> Result<Vec<TimeWindow>, String> {
+    text.split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(TimeWindow::parse)
+        .collect()
+}
+
+/// Formats windows the way [`parse_windows`] reads them.
+pub fn format_windows(windows: &[TimeWindow]) -> String {
+    windows
+        .iter()
+        .map(|w| w.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The next time the clock reads `time`, today or tomorrow.
+pub(crate) fn next_occurrence(time: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let mut date = now.date_naive();
+    if now.time() >= time {
+        date += Duration::days(1);
+    }
+
+    Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .unwrap_or(now)
+}
+
+/// `at HH:MM`, or `tomorrow at HH:MM` when `time` falls on the day after
+/// `now`, for status messages.
+pub(crate) fn describe(time: DateTime<Local>, now: DateTime<Local>) -> String {
+    if time.date_naive() == now.date_naive() {
+        format!("at {}", time.format(TIME_FORMAT))
+    } else {
+        format!("tomorrow at {}", time.format(TIME_FORMAT))
+    }
+}
+
+/// Whether items may be dispatched at `now`. An empty window list never blocks.
+pub(crate) fn window_is_open(settings: &ScheduleSettings, now: DateTime<Local>) -> bool {
+    !settings.windows_enabled
+        || settings.windows.is_empty()
+        || settings.windows.iter().any(|w| w.contains(now.time()))
+}
+
+pub(crate) fn next_window_start(
+    settings: &ScheduleSettings,
+    now: DateTime<Local>,
+) -> Option<DateTime<Local>> {
+    settings
+        .windows
+        .iter()
+        .map(|w| next_occurrence(w.start, now))
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        parse_time(text).unwrap()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 1, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn schedule(windows: &str) -> ScheduleSettings {
+        ScheduleSettings {
+            start_at: None,
+            windows_enabled: true,
+            windows: parse_windows(windows).unwrap(),
+        }
+    }
+
+    #[test]
+    fn windows_include_their_start_but_not_their_end() {
+        let window = TimeWindow::parse("09:00-17:00").unwrap();
+        assert!(!window.contains(time("08:59")));
+        assert!(window.contains(time("09:00")));
+        assert!(window.contains(time("16:59")));
+        assert!(!window.contains(time("17:00")));
+    }
+
+    #[test]
+    fn windows_can_span_midnight() {
+        let window = TimeWindow::parse("22:00-06:00").unwrap();
+        assert!(window.contains(time("22:00")));
+        assert!(window.contains(time("23:59")));
+        assert!(window.contains(time("00:00")));
+        assert!(window.contains(time("05:59")));
+        assert!(!window.contains(time("06:00")));
+        assert!(!window.contains(time("12:00")));
+        assert!(!window.contains(time("21:59")));
+    }
+
+    #[test]
+    fn windows_round_trip_through_text() {
+        let windows = parse_windows(" 22:00-06:00, 12:00 - 13:30,").unwrap();
+        assert_eq!(format_windows(&windows), "22:00-06:00, 12:00-13:30");
+        assert_eq!(parse_windows("").unwrap(), []);
+
+        assert_eq!(
+            TimeWindow::parse("22:00"),
+            Err("Invalid window '22:00', expected HH:MM-HH:MM".to_string())
+        );
+        assert_eq!(
+            parse_windows("22:00-25:00"),
+            Err("Invalid time '25:00', expected HH:MM".to_string())
+        );
+    }
+
+    #[test]
+    fn start_times_later_today_start_today() {
+        let now = at(10, 18, 0);
+        assert_eq!(next_occurrence(time("22:00"), now), at(10, 22, 0));
+        assert_eq!(describe(at(10, 22, 0), now), "at 22:00");
+    }
+
+    #[test]
+    fn start_times_already_passed_today_start_tomorrow() {
+        // A 02:00 start chosen in the evening means tonight, after midnight
+        let now = at(10, 18, 0);
+        assert_eq!(next_occurrence(time("02:00"), now), at(11, 2, 0));
+        // Started just after the start time, so the next one is a day away
+        let now = at(10, 22, 30);
+        assert_eq!(next_occurrence(time("22:00"), now), at(11, 22, 0));
+        assert_eq!(next_occurrence(time("22:30"), now), at(11, 22, 30));
+        assert_eq!(describe(at(11, 22, 0), now), "tomorrow at 22:00");
+    }
+
+    #[test]
+    fn dispatch_waits_for_an_open_window() {
+        let settings = schedule("22:00-06:00, 12:00-13:00");
+        assert!(window_is_open(&settings, at(10, 23, 0)));
+        assert!(window_is_open(&settings, at(10, 12, 30)));
+        assert!(!window_is_open(&settings, at(10, 18, 0)));
+        assert_eq!(
+            next_window_start(&settings, at(10, 18, 0)),
+            Some(at(10, 22, 0))
+        );
+        assert_eq!(
+            next_window_start(&settings, at(10, 9, 0)),
+            Some(at(10, 12, 0))
+        );
+    }
+
+    #[test]
+    fn disabled_or_empty_windows_never_block() {
+        let mut settings = schedule("22:00-06:00");
+        settings.windows_enabled = false;
+        assert!(window_is_open(&settings, at(10, 18, 0)));
+        assert!(window_is_open(&schedule(""), at(10, 18, 0)));
+        assert_eq!(next_window_start(&schedule(""), at(10, 18, 0)), None);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::config::VerifySettings;
+use crate::executor::{self, Exit, Running};
+use crate::invocation::{self, Invocation};
+use crate::report::format_bytes;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+// Filesystems with coarse timestamps can date a new file slightly before
+// the script was started
+const MTIME_SLACK: Duration = Duration::from_secs(2);
+
+/// Checks the outputs of a successful run against `settings`. Returns the
+/// matched files, or why verification failed. The validation command gets
+/// the same `timeout_secs` as the script.
+pub fn verify(
+    invocation: &Invocation,
+    settings: &VerifySettings,
+    started: SystemTime,
+    timeout_secs: Option<u64>,
+    running: &Running,
+) -> Result<Vec<PathBuf>, String> {
+    let since = started.checked_sub(MTIME_SLACK).unwrap_or(started);
+    let mut verified = Vec::new();
+
+    for pattern in &settings.outputs {
+        let pattern = invocation.expand(pattern);
+        let path = Path::new(&pattern);
+        if path.has_root() || path.is_absolute() {
+            return Err(format!(
+                "'{}' must be relative to the output directory",
+                pattern
+            ));
+        }
+        if path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return Err(format!("'{}' may not contain '..'", pattern));
+        }
+        let matches: Vec<PathBuf> = glob(&invocation.output_dir, &pattern)
+            .into_iter()
+            .filter(|path| {
+                path.metadata()
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|modified| modified >= since)
+            })
+            .collect();
+
+        if matches.is_empty() {
+            return Err(format!("no output matching '{}' was written", pattern));
+        }
+        verified.extend(matches);
+    }
+
+    if let Some(min) = settings.min_size_bytes {
+        for path in &verified {
+            let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+            if size < min {
+                return Err(format!(
+                    "{} is {}, smaller than the minimum of {}",
+                    path.display(),
+                    format_bytes(size),
+                    format_bytes(min)
+                ));
+            }
+        }
+    }
+
+    if !settings.command.is_empty() {
+        for path in &verified {
+            run_check(invocation, &settings.command, path, timeout_secs, running)?;
+        }
+    }
+
+    Ok(verified)
+}
+
+/// Runs the validation command for one output file.
+fn run_check(
+    invocation: &Invocation,
+    template: &[String],
+    file: &Path,
+    timeout_secs: Option<u64>,
+    running: &Running,
+) -> Result<(), String> {
+    let file = file.to_string_lossy();
+    // Expand first so a file name can never be read as a placeholder
+    let words: Vec<String> = template
+        .iter()
+        .map(|word| invocation.expand(word).replace("{file}", &file))
+        .collect();
+
+    let mut command = Command::new(&words[0]);
+    command.args(&words[1..]);
+    let exit = executor::run(&mut command, timeout_secs, running)
+        .map_err(|e| format!("could not run '{}': {}", words[0], e))?;
+    let command_line = invocation::command_line(words.iter().map(String::as_str));
+    let output = match exit {
+        Exit::Finished(output) if output.status.success() => return Ok(()),
+        Exit::Finished(output) => output,
+        Exit::TimedOut(after) => {
+            return Err(format!(
+                "'{}' timed out after {}s on {}",
+                command_line,
+                after.as_secs(),
+                file
+            ));
+        }
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let detail = match stderr.trim() {
+        "" => output.status.to_string(),
+        message => message.to_string(),
+    };
+    Err(format!("'{}' rejected {}: {}", command_line, file, detail))
+}
+
+/// Files under `base` matching `pattern`, where `*` and `?` match within a
+/// single path component.
+fn glob(base: &Path, pattern: &str) -> Vec<PathBuf> {
+    let pattern = Path::new(pattern);
+    let mut candidates = vec![base.to_path_buf()];
+
+    for component in pattern.components() {
+        let Component::Normal(part) = component else {
+            continue;
+        };
+        let part = part.to_string_lossy();
+
+        candidates = if part.contains(['*', '?']) {
+            candidates
+                .iter()
+                .filter_map(|dir| fs::read_dir(dir).ok())
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| wildcard_match(&part, &entry.file_name().to_string_lossy()))
+                .map(|entry| entry.path())
+                .collect()
+        } else {
+            candidates
+                .iter()
+                .map(|dir| dir.join(part.as_ref()))
+                .filter(|path| path.exists())
+                .collect()
+        };
+    }
+
+    candidates.retain(|path| path.is_file());
+    candidates.sort();
+    candidates
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Backtracking match remembering the last `*`
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScriptSettings;
+    use std::time::Instant;
+
+    fn written(dir: &Path) -> Invocation {
+        fs::write(dir.join("a.out"), "done").unwrap();
+        let input = dir.join("a.txt");
+        Invocation::resolve("true", &input, dir, &ScriptSettings::default())
+    }
+
+    #[test]
+    fn patterns_leaving_the_output_dir_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = written(dir.path());
+        let settings = VerifySettings {
+            outputs: vec!["../*.out".to_string()],
+            ..VerifySettings::default()
+        };
+
+        let result = verify(
+            &invocation,
+            &settings,
+            SystemTime::now(),
+            None,
+            &Running::default(),
+        );
+        assert_eq!(result, Err("'../*.out' may not contain '..'".to_string()));
+
+        let settings = VerifySettings {
+            outputs: vec!["/etc/*".to_string()],
+            ..VerifySettings::default()
+        };
+        let result = verify(
+            &invocation,
+            &settings,
+            SystemTime::now(),
+            None,
+            &Running::default(),
+        );
+        assert_eq!(
+            result,
+            Err("'/etc/*' must be relative to the output directory".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_hanging_check_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let invocation = written(dir.path());
+        let settings = VerifySettings {
+            outputs: vec!["*.out".to_string()],
+            command: vec!["sleep".to_string(), "30".to_string()],
+            ..VerifySettings::default()
+        };
+
+        let started = Instant::now();
+        let result = verify(
+            &invocation,
+            &settings,
+            SystemTime::now(),
+            Some(1),
+            &Running::default(),
+        );
+        assert!(result.unwrap_err().contains("timed out after 1s"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}